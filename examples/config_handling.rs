@@ -51,6 +51,72 @@ fn main() -> Result<()> {
         assert_eq!(file_config.root_dir, config.root_dir)
     }
 
+    if !command_line_args.data_dirs.is_empty() {
+        assert_eq!(command_line_args.data_dirs, config.data_dirs)
+    } else {
+        assert_eq!(file_config.data_dirs, config.data_dirs)
+    }
+
+    if command_line_args.blob_quota.is_some() {
+        assert_eq!(command_line_args.blob_quota, config.blob_quota)
+    } else {
+        assert_eq!(file_config.blob_quota, config.blob_quota)
+    }
+
+    if command_line_args.map_quota.is_some() {
+        assert_eq!(command_line_args.map_quota, config.map_quota)
+    } else {
+        assert_eq!(file_config.map_quota, config.map_quota)
+    }
+
+    if command_line_args.sequence_quota.is_some() {
+        assert_eq!(command_line_args.sequence_quota, config.sequence_quota)
+    } else {
+        assert_eq!(file_config.sequence_quota, config.sequence_quota)
+    }
+
+    if command_line_args.storage_backend.is_some() {
+        assert_eq!(command_line_args.storage_backend, config.storage_backend)
+    } else {
+        assert_eq!(file_config.storage_backend, config.storage_backend)
+    }
+
+    if command_line_args.compression.is_some() {
+        assert_eq!(command_line_args.compression, config.compression)
+    } else {
+        assert_eq!(file_config.compression, config.compression)
+    }
+
+    if command_line_args.cache_capacity.is_some() {
+        assert_eq!(command_line_args.cache_capacity, config.cache_capacity)
+    } else {
+        assert_eq!(file_config.cache_capacity, config.cache_capacity)
+    }
+
+    if command_line_args.mutable_copy_count.is_some() {
+        assert_eq!(
+            command_line_args.mutable_copy_count,
+            config.mutable_copy_count
+        )
+    } else {
+        assert_eq!(file_config.mutable_copy_count, config.mutable_copy_count)
+    }
+
+    if command_line_args.map_history_depth.is_some() {
+        assert_eq!(
+            command_line_args.map_history_depth,
+            config.map_history_depth
+        )
+    } else {
+        assert_eq!(file_config.map_history_depth, config.map_history_depth)
+    }
+
+    if command_line_args.command.is_some() {
+        assert_eq!(command_line_args.command, config.command)
+    } else {
+        assert_eq!(file_config.command, config.command)
+    }
+
     if command_line_args.verbose > 0 {
         assert_eq!(command_line_args.verbose, config.verbose)
     } else {
@@ -88,6 +154,10 @@ fn main() -> Result<()> {
         config.clear_data,
         file_config.clear_data || command_line_args.clear_data
     );
+    assert_eq!(
+        config.encrypt_chunks,
+        file_config.encrypt_chunks || command_line_args.encrypt_chunks
+    );
 
     if !command_line_args
         .network_config
@@ -29,7 +29,9 @@
 
 use log::{self, error, info};
 use self_update::{cargo_crate_version, Status};
-use sn_node::{self, utils, write_connection_info, Config, Node};
+use sn_node::{
+    self, export_chunks, import_chunks, utils, write_connection_info, Config, Node, StoreCommand,
+};
 use std::{collections::HashSet, io::Write, net::SocketAddr, process};
 use structopt::{clap, StructOpt};
 
@@ -88,6 +90,24 @@ async fn run_node() {
 
     info!("Node PID is: {:?}", std::process::id());
 
+    if let Some(command) = &config.command {
+        let result = match command {
+            StoreCommand::ExportChunks { archive } => export_chunks(&config, archive).await,
+            StoreCommand::ImportChunks { archive } => import_chunks(&config, archive).await,
+        };
+        match result {
+            Ok(manifest) => {
+                println!("Done: {}", manifest);
+                process::exit(0);
+            }
+            Err(e) => {
+                println!("Failed to move chunks: {}", e);
+                error!("Failed to move chunks: {:?}", e);
+                process::exit(1);
+            }
+        }
+    }
+
     if config.update() || config.update_only() {
         match update() {
             Ok(status) => {
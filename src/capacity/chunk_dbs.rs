@@ -6,34 +6,426 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{utils, Result};
-use futures::lock::Mutex;
-use pickledb::PickleDb;
-use std::sync::Arc;
-use std::{cell::RefCell, path::Path, rc::Rc};
+use crate::{to_db_key::from_db_key, utils, Error, Result, ToDbKey};
+use log::{info, warn};
+use pickledb::{PickleDb, PickleDbDumpPolicy};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sled::{
+    transaction::{TransactionError, TransactionResult},
+    Db, Transactional, Tree,
+};
+use sn_data_types::{BlobAddress, PublicKey};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    ops::Bound,
+    path::Path,
+};
+use xor_name::{XorName, XOR_NAME_LEN};
 
+const DB_DIR: &str = "chunk_holders";
+const METADATA_TREE: &str = "metadata";
+const HOLDERS_TREE: &str = "holders";
+const FULL_ADULTS_TREE: &str = "full_adults";
+const STORAGE_FAILURES_TREE: &str = "storage_failures";
+
+// The pickledb files these dbs used to be kept in, imported on first open.
 const BLOB_META_DB_NAME: &str = "immutable_data.db";
 const HOLDER_META_DB_NAME: &str = "holder_data.db";
 const FULL_ADULTS_DB_NAME: &str = "full_adults.db";
-// The number of separate copies of a blob chunk which should be maintained.
 
+/// The holders of a Blob chunk, and its owner if private.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct ChunkMetadata {
+    pub(crate) holders: BTreeSet<XorName>,
+    pub(crate) owner: Option<PublicKey>,
+}
+
+/// The Blob chunks held by a node.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct HolderMetadata {
+    pub(crate) chunks: BTreeSet<BlobAddress>,
+}
+
+/// The chunk→holder and holder→chunk indexes, the set of full adults and the storage challenges
+/// failed by each adult, kept in an embedded
+/// transactional database so that related writes to both indexes land together or not at all.
 #[derive(Clone)]
 pub struct ChunkHolderDbs {
-    pub metadata: Arc<Mutex<PickleDb>>,
-    pub holders: Arc<Mutex<PickleDb>>,
-    pub full_adults: Arc<Mutex<PickleDb>>,
+    db: Db,
+    metadata: Tree,
+    holders: Tree,
+    full_adults: Tree,
+    storage_failures: Tree,
+}
+
+/// A set of writes to the chunk and holder indexes, applied atomically by
+/// [`ChunkHolderDbs::apply`].
+#[derive(Default)]
+pub(crate) struct ChunkHolderBatch {
+    metadata: Vec<(String, Option<Vec<u8>>)>,
+    holders: Vec<(String, Option<Vec<u8>>)>,
+}
+
+impl ChunkHolderBatch {
+    /// Sets the metadata of the chunk at `address`, or removes it if it has no holders left.
+    pub(crate) fn set_chunk(
+        &mut self,
+        address: &BlobAddress,
+        metadata: &ChunkMetadata,
+    ) -> Result<()> {
+        let value = if metadata.holders.is_empty() {
+            None
+        } else {
+            Some(utils::serialise(metadata)?.to_vec())
+        };
+        self.metadata.push((address.to_db_key()?, value));
+        Ok(())
+    }
+
+    /// Sets the chunks held by `holder`, or removes its entry if it holds none.
+    pub(crate) fn set_holder(&mut self, holder: &XorName, metadata: &HolderMetadata) -> Result<()> {
+        let value = if metadata.chunks.is_empty() {
+            None
+        } else {
+            Some(utils::serialise(metadata)?.to_vec())
+        };
+        self.holders.push((holder.to_db_key()?, value));
+        Ok(())
+    }
+
+    /// Removes the entry of `holder`.
+    pub(crate) fn remove_holder(&mut self, holder: &XorName) -> Result<()> {
+        self.holders.push((holder.to_db_key()?, None));
+        Ok(())
+    }
 }
 
 impl ChunkHolderDbs {
-    ///
+    /// Opens the dbs under `path`, creating them if needed.  Entries of any pickledb files left by
+    /// earlier versions are imported, after which the files are removed.
     pub fn new(path: &Path) -> Result<Self> {
-        let metadata = utils::new_auto_dump_db(path, BLOB_META_DB_NAME)?;
-        let holders = utils::new_auto_dump_db(path, HOLDER_META_DB_NAME)?;
-        let full_adults = utils::new_auto_dump_db(path, FULL_ADULTS_DB_NAME)?;
-        Ok(Self {
-            metadata: Arc::new(Mutex::new(metadata)),
-            holders: Arc::new(Mutex::new(holders)),
-            full_adults: Arc::new(Mutex::new(full_adults)),
-        })
+        let db = sled::open(path.join(DB_DIR))?;
+        let dbs = Self {
+            metadata: db.open_tree(METADATA_TREE)?,
+            holders: db.open_tree(HOLDERS_TREE)?,
+            full_adults: db.open_tree(FULL_ADULTS_TREE)?,
+            storage_failures: db.open_tree(STORAGE_FAILURES_TREE)?,
+            db,
+        };
+        dbs.migrate(path)?;
+        Ok(dbs)
+    }
+
+    /// Returns the metadata of the chunk at `address`, if any.
+    pub(crate) fn chunk(&self, address: &BlobAddress) -> Result<Option<ChunkMetadata>> {
+        get(&self.metadata, &address.to_db_key()?)
+    }
+
+    /// Returns up to `limit` chunks along with their metadata, in key order, starting after the
+    /// chunk at `after` if given.
+    pub(crate) fn chunks(
+        &self,
+        after: Option<&BlobAddress>,
+        limit: usize,
+    ) -> Result<Vec<(BlobAddress, ChunkMetadata)>> {
+        let start = match after {
+            Some(address) => Bound::Excluded(address.to_db_key()?.into_bytes()),
+            None => Bound::Unbounded,
+        };
+        self.metadata
+            .range::<Vec<u8>, _>((start, Bound::Unbounded))
+            .take(limit)
+            .map(|entry| {
+                let (key, value) = entry?;
+                let key =
+                    String::from_utf8(key.to_vec()).map_err(|e| Error::Logic(e.to_string()))?;
+                Ok((from_db_key(&key)?, utils::deserialise(&value)?))
+            })
+            .collect()
+    }
+
+    /// Returns a chunk picked at random, along with its metadata, if there are any chunks.
+    pub(crate) fn random_chunk(&self) -> Result<Option<(BlobAddress, ChunkMetadata)>> {
+        let name = XorName::random();
+        let start = if rand::random() {
+            BlobAddress::Public(name)
+        } else {
+            BlobAddress::Private(name)
+        };
+        let mut chunks = self.chunks(Some(&start), 1)?;
+        if chunks.is_empty() {
+            chunks = self.chunks(None, 1)?;
+        }
+        Ok(chunks.pop())
+    }
+
+    /// Returns the chunks held by `holder`, if any.
+    pub(crate) fn holder(&self, holder: &XorName) -> Result<Option<HolderMetadata>> {
+        get(&self.holders, &holder.to_db_key()?)
+    }
+
+    /// Applies all writes of `batch` in a single transaction, and waits for them to be durable.
+    pub(crate) async fn apply(&self, batch: ChunkHolderBatch) -> Result<()> {
+        let result: TransactionResult<()> =
+            (&self.metadata, &self.holders).transaction(|(metadata, holders)| {
+                for (key, value) in &batch.metadata {
+                    let _ = match value {
+                        Some(value) => metadata.insert(key.as_bytes(), value.as_slice())?,
+                        None => metadata.remove(key.as_bytes())?,
+                    };
+                }
+                for (key, value) in &batch.holders {
+                    let _ = match value {
+                        Some(value) => holders.insert(key.as_bytes(), value.as_slice())?,
+                        None => holders.remove(key.as_bytes())?,
+                    };
+                }
+                Ok(())
+            });
+        match result {
+            Ok(()) => {}
+            Err(TransactionError::Storage(error)) => return Err(error.into()),
+            Err(TransactionError::Abort(())) => {
+                return Err(Error::Logic("Chunk holder transaction aborted".to_string()))
+            }
+        }
+        let _ = self.db.flush_async().await?;
+        Ok(())
+    }
+
+    /// Number of adults recorded as full.
+    pub(crate) fn full_adult_count(&self) -> usize {
+        self.full_adults.len()
+    }
+
+    /// Whether the adult `node` is recorded as full.
+    pub(crate) fn is_full_adult(&self, node: &XorName) -> Result<bool> {
+        Ok(self
+            .full_adults
+            .contains_key(node.to_db_key()?.as_bytes())?)
+    }
+
+    /// Records the adult `node` as full.
+    pub(crate) async fn add_full_adult(&self, node: &XorName) -> Result<()> {
+        let _ = self
+            .full_adults
+            .insert(node.to_db_key()?.as_bytes(), Vec::new())?;
+        let _ = self.db.flush_async().await?;
+        Ok(())
+    }
+
+    /// Counts a storage challenge failed by the adult `node`, and returns its failures so far.
+    pub(crate) async fn add_storage_failure(&self, node: &XorName) -> Result<u64> {
+        let key = node.to_db_key()?;
+        let failures = match self.storage_failures.get(key.as_bytes())? {
+            Some(value) => decode_count(&value)? + 1,
+            None => 1,
+        };
+        let _ = self
+            .storage_failures
+            .insert(key.as_bytes(), failures.to_le_bytes().to_vec())?;
+        let _ = self.db.flush_async().await?;
+        Ok(failures)
+    }
+
+    /// Returns the number of storage challenges failed by each adult which failed any.
+    pub(crate) fn storage_failures(&self) -> Result<BTreeMap<XorName, u64>> {
+        self.storage_failures
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let key =
+                    String::from_utf8(key.to_vec()).map_err(|e| Error::Logic(e.to_string()))?;
+                Ok((from_db_key(&key)?, decode_count(&value)?))
+            })
+            .collect()
+    }
+
+    fn migrate(&self, path: &Path) -> Result<()> {
+        let mut imported = Vec::new();
+
+        let db_path = path.join(BLOB_META_DB_NAME);
+        if let Ok(db) = PickleDb::load_bin(&db_path, PickleDbDumpPolicy::NeverDump) {
+            import::<ChunkMetadata>(&db, &self.metadata)?;
+            imported.push(db_path);
+        }
+        let db_path = path.join(HOLDER_META_DB_NAME);
+        if let Ok(db) = PickleDb::load_bin(&db_path, PickleDbDumpPolicy::NeverDump) {
+            import::<HolderMetadata>(&db, &self.holders)?;
+            imported.push(db_path);
+        }
+        let db_path = path.join(FULL_ADULTS_DB_NAME);
+        if let Ok(db) = PickleDb::load_bin(&db_path, PickleDbDumpPolicy::NeverDump) {
+            // Full adults were kept as lists named by their public keys, which are converted to
+            // the names they are now recorded by.
+            for node_id in db.get_all() {
+                match adult_name(&node_id) {
+                    Some(name) => {
+                        let _ = self
+                            .full_adults
+                            .insert(name.to_db_key()?.as_bytes(), Vec::new())?;
+                    }
+                    None => warn!("Dropping full adult with unknown id {}", node_id),
+                }
+            }
+            imported.push(db_path);
+        }
+
+        if imported.is_empty() {
+            return Ok(());
+        }
+        let _ = self.db.flush()?;
+        for db_path in imported {
+            info!("Imported chunk holder db {}", db_path.display());
+            fs::remove_file(db_path)?;
+        }
+        Ok(())
+    }
+}
+
+fn get<T: DeserializeOwned>(tree: &Tree, key: &str) -> Result<Option<T>> {
+    match tree.get(key.as_bytes())? {
+        Some(value) => Ok(Some(utils::deserialise(&value)?)),
+        None => Ok(None),
+    }
+}
+
+fn decode_count(value: &[u8]) -> Result<u64> {
+    let mut bytes = [0; 8];
+    if value.len() != bytes.len() {
+        return Err(Error::Logic("Invalid count in DB".to_string()));
+    }
+    bytes.copy_from_slice(value);
+    Ok(u64::from_le_bytes(bytes))
+}
+
+// The name of the adult with the public key `node_id`, as formatted by `PublicKey`'s `Display`.
+// Adults are named by their ed25519 keys.
+fn adult_name(node_id: &str) -> Option<XorName> {
+    let hex_key = node_id
+        .strip_prefix("PublicKey::Ed25519(")?
+        .strip_suffix(')')?;
+    let bytes = hex::decode(hex_key).ok()?;
+    if bytes.len() != XOR_NAME_LEN {
+        return None;
+    }
+    let mut name = XorName::default();
+    name.0.copy_from_slice(&bytes);
+    Some(name)
+}
+
+fn import<T: Serialize + DeserializeOwned>(db: &PickleDb, tree: &Tree) -> Result<()> {
+    for key in db.get_all() {
+        if let Some(value) = db.get::<T>(&key) {
+            let _ = tree.insert(key.as_bytes(), utils::serialise(&value)?.to_vec())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sn_data_types::Keypair;
+    use tempdir::TempDir;
+
+    fn temp_dir() -> Result<TempDir> {
+        TempDir::new("chunk_dbs_test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
+    }
+
+    #[tokio::test]
+    async fn batch_updates_both_indexes() -> Result<()> {
+        let root = temp_dir()?;
+        let dbs = ChunkHolderDbs::new(root.path())?;
+        let address = BlobAddress::Public(XorName::random());
+        let holder = XorName::random();
+
+        let mut chunk = ChunkMetadata::default();
+        let _ = chunk.holders.insert(holder);
+        let mut held = HolderMetadata::default();
+        let _ = held.chunks.insert(address);
+        let mut batch = ChunkHolderBatch::default();
+        batch.set_chunk(&address, &chunk)?;
+        batch.set_holder(&holder, &held)?;
+        dbs.apply(batch).await?;
+        assert_eq!(dbs.chunk(&address)?, Some(chunk.clone()));
+        assert_eq!(dbs.holder(&holder)?, Some(held.clone()));
+        assert_eq!(dbs.chunks(None, 10)?, vec![(address, chunk.clone())]);
+        assert!(dbs.chunks(Some(&address), 10)?.is_empty());
+
+        // Emptied entries are removed.
+        let _ = chunk.holders.remove(&holder);
+        let _ = held.chunks.remove(&address);
+        let mut batch = ChunkHolderBatch::default();
+        batch.set_chunk(&address, &chunk)?;
+        batch.set_holder(&holder, &held)?;
+        dbs.apply(batch).await?;
+        assert_eq!(dbs.chunk(&address)?, None);
+        assert_eq!(dbs.holder(&holder)?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn full_adults_are_flagged_by_name() -> Result<()> {
+        let root = temp_dir()?;
+        let dbs = ChunkHolderDbs::new(root.path())?;
+        let full = XorName::random();
+        dbs.add_full_adult(&full).await?;
+        dbs.add_full_adult(&full).await?;
+        assert!(dbs.is_full_adult(&full)?);
+        assert!(!dbs.is_full_adult(&XorName::random())?);
+        assert_eq!(dbs.full_adult_count(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_failures_are_counted_per_adult() -> Result<()> {
+        let root = temp_dir()?;
+        let dbs = ChunkHolderDbs::new(root.path())?;
+        let adult = XorName::random();
+        assert_eq!(dbs.add_storage_failure(&adult).await?, 1);
+        assert_eq!(dbs.add_storage_failure(&adult).await?, 2);
+        let other = XorName::random();
+        assert_eq!(dbs.add_storage_failure(&other).await?, 1);
+
+        let failures = dbs.storage_failures()?;
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures.get(&adult), Some(&2));
+        assert_eq!(failures.get(&other), Some(&1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pickledb_files_are_imported() -> Result<()> {
+        let root = temp_dir()?;
+        let address = BlobAddress::Public(XorName::random());
+        let holder = XorName::random();
+        let mut chunk = ChunkMetadata::default();
+        let _ = chunk.holders.insert(holder);
+        let mut held = HolderMetadata::default();
+        let _ = held.chunks.insert(address);
+        let full_adult = Keypair::new_ed25519(&mut rand::thread_rng()).public_key();
+        {
+            let mut metadata = utils::new_auto_dump_db(root.path(), BLOB_META_DB_NAME)?;
+            metadata.set(&address.to_db_key()?, &chunk)?;
+            let mut holders = utils::new_auto_dump_db(root.path(), HOLDER_META_DB_NAME)?;
+            holders.set(&holder.to_db_key()?, &held)?;
+            let mut full_adults = utils::new_auto_dump_db(root.path(), FULL_ADULTS_DB_NAME)?;
+            let _ = full_adults
+                .lcreate(&full_adult.to_string())?
+                .ladd(&"Node Full");
+            let _ = full_adults.lcreate("unknown node")?.ladd(&"Node Full");
+        }
+
+        let dbs = ChunkHolderDbs::new(root.path())?;
+        assert_eq!(dbs.chunk(&address)?, Some(chunk));
+        assert_eq!(dbs.holder(&holder)?, Some(held));
+        assert_eq!(dbs.full_adult_count(), 1);
+        assert!(dbs.is_full_adult(&XorName::from(full_adult))?);
+        assert!(!root.path().join(BLOB_META_DB_NAME).exists());
+        assert!(!root.path().join(HOLDER_META_DB_NAME).exists());
+        assert!(!root.path().join(FULL_ADULTS_DB_NAME).exists());
+        Ok(())
     }
 }
@@ -11,6 +11,7 @@ mod rate_limit;
 
 use crate::Result;
 pub use chunk_dbs::ChunkHolderDbs;
+pub(crate) use chunk_dbs::{ChunkHolderBatch, ChunkMetadata, HolderMetadata};
 use log::info;
 pub use rate_limit::RateLimit;
 use sn_data_types::PublicKey;
@@ -31,19 +32,12 @@ impl Capacity {
 
     /// Number of full chunk storing nodes in the section.
     pub async fn full_nodes(&self) -> u8 {
-        self.dbs.full_adults.lock().await.total_keys() as u8
+        self.dbs.full_adult_count() as u8
     }
 
     ///
     pub async fn increase_full_node_count(&mut self, node_id: PublicKey) -> Result<()> {
         info!("Increasing full node count");
-        let _ = self
-            .dbs
-            .full_adults
-            .lock()
-            .await
-            .lcreate(&node_id.to_string())?
-            .ladd(&"Node Full");
-        Ok(())
+        self.dbs.add_full_adult(&node_id.into()).await
     }
 }
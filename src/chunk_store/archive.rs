@@ -0,0 +1,286 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Export of all stored chunks into a single archive, and import of such an archive, to move a
+//! node's data to another machine.
+//!
+//! An archive is a stream of length-prefixed, bincode-serialised records after a short header:
+//! one record per chunk, holding the serialised chunk and its checksum, then a manifest counting
+//! the chunks and committing to all of their checksums.  As the manifest comes last, an archive
+//! can be written in one pass, and an archive which was cut short fails to import.
+
+use super::{
+    chunk::Chunk, BlobChunkStore, ChunkStore, MapChunkStore, MapHistoryChunkStore,
+    SequenceChunkStore, StoreConfig, UsedSpace,
+};
+use crate::{utils, Config, Error, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+};
+use xor_name::XorName;
+
+const MAGIC: &[u8] = b"sn_chunks";
+const VERSION: u32 = 2;
+/// The largest record accepted on import, well above the largest chunk.
+const MAX_RECORD_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The summary of an archive, written after all of its chunks.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ArchiveManifest {
+    /// Number of Blob chunks.
+    pub blobs: u64,
+    /// Number of Map chunks.
+    pub maps: u64,
+    /// Number of earlier Map versions.
+    pub map_versions: u64,
+    /// Number of Sequence chunks.
+    pub sequences: u64,
+    /// Total size of the serialised chunks.
+    pub bytes: u64,
+    /// Hash chained over the checksums of all chunks, in archive order.
+    pub digest: XorName,
+}
+
+impl ArchiveManifest {
+    fn add(&mut self, kind: ChunkKind, data: &[u8], checksum: &XorName) {
+        match kind {
+            ChunkKind::Blob => self.blobs += 1,
+            ChunkKind::Map => self.maps += 1,
+            ChunkKind::MapVersion => self.map_versions += 1,
+            ChunkKind::Sequence => self.sequences += 1,
+        }
+        self.bytes += data.len() as u64;
+        self.digest = XorName::from_content(&[&self.digest.0, &checksum.0]);
+    }
+}
+
+impl Display for ArchiveManifest {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{} Blobs, {} Maps, {} earlier Map versions, {} Sequences, {} bytes",
+            self.blobs, self.maps, self.map_versions, self.sequences, self.bytes
+        )
+    }
+}
+
+/// The store a chunk of an archive is imported into.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+enum ChunkKind {
+    Blob,
+    Map,
+    MapVersion,
+    Sequence,
+}
+
+#[derive(Deserialize, Serialize)]
+enum Record {
+    Chunk {
+        kind: ChunkKind,
+        data: Vec<u8>,
+        checksum: XorName,
+    },
+    Manifest(ArchiveManifest),
+}
+
+/// Writes all chunks stored by the node configured by `config` to a new archive file at `archive`,
+/// and returns the archive's manifest.
+pub async fn export_chunks(config: &Config, archive: &Path) -> Result<ArchiveManifest> {
+    let root_dir = config.root_dir()?;
+    let store_config = StoreConfig::new(config, &root_dir).await?;
+    // Opening the stores registers their contents, which mustn't count against any limit.
+    let used_space = UsedSpace::new(u64::MAX);
+
+    let mut writer = BufWriter::new(File::create(archive).await?);
+    writer.write_all(MAGIC).await?;
+    writer.write_u32_le(VERSION).await?;
+
+    let mut manifest = ArchiveManifest::default();
+    let blobs = BlobChunkStore::new(&root_dir, used_space.clone(), &store_config).await?;
+    export_store(&blobs, ChunkKind::Blob, &mut writer, &mut manifest).await?;
+    let maps = MapChunkStore::new(&root_dir, used_space.clone(), &store_config).await?;
+    export_store(&maps, ChunkKind::Map, &mut writer, &mut manifest).await?;
+    let map_versions =
+        MapHistoryChunkStore::new(&root_dir, used_space.clone(), &store_config).await?;
+    export_store(
+        &map_versions,
+        ChunkKind::MapVersion,
+        &mut writer,
+        &mut manifest,
+    )
+    .await?;
+    let sequences = SequenceChunkStore::new(&root_dir, used_space, &store_config).await?;
+    export_store(&sequences, ChunkKind::Sequence, &mut writer, &mut manifest).await?;
+
+    write_record(&mut writer, &Record::Manifest(manifest.clone())).await?;
+    writer.flush().await?;
+    writer.get_mut().sync_all().await?;
+    info!("Exported {} to {}", manifest, archive.display());
+    Ok(manifest)
+}
+
+/// Stores all chunks from the archive at `archive` in the node configured by `config`, whose stores
+/// must be empty, and returns the archive's manifest.
+///
+/// Each chunk is verified against its checksum and its content before being stored, and the
+/// archive as a whole against its manifest.  The node's used space records are rebuilt as the
+/// chunks are stored.  If the import fails, the chunks stored so far are removed again, so that
+/// it can be retried.
+pub async fn import_chunks(config: &Config, archive: &Path) -> Result<ArchiveManifest> {
+    let root_dir = config.root_dir()?;
+    tokio::fs::create_dir_all(&root_dir).await?;
+    let store_config = StoreConfig::new(config, &root_dir).await?;
+    let used_space = UsedSpace::from_config(config);
+
+    let mut blobs = BlobChunkStore::new(&root_dir, used_space.clone(), &store_config).await?;
+    let mut maps = MapChunkStore::new(&root_dir, used_space.clone(), &store_config).await?;
+    let mut map_versions =
+        MapHistoryChunkStore::new(&root_dir, used_space.clone(), &store_config).await?;
+    let mut sequences =
+        SequenceChunkStore::new(&root_dir, used_space.clone(), &store_config).await?;
+    if used_space.total().await > 0 {
+        return Err(Error::DataExists);
+    }
+
+    let result = import_archive(
+        archive,
+        &mut blobs,
+        &mut maps,
+        &mut map_versions,
+        &mut sequences,
+    )
+    .await;
+    if let Err(error) = &result {
+        warn!("Rolling back import from {}: {}", archive.display(), error);
+        remove_all(&mut blobs).await?;
+        remove_all(&mut maps).await?;
+        remove_all(&mut map_versions).await?;
+        remove_all(&mut sequences).await?;
+    }
+    result
+}
+
+async fn import_archive(
+    archive: &Path,
+    blobs: &mut BlobChunkStore,
+    maps: &mut MapChunkStore,
+    map_versions: &mut MapHistoryChunkStore,
+    sequences: &mut SequenceChunkStore,
+) -> Result<ArchiveManifest> {
+    let mut reader = BufReader::new(File::open(archive).await?);
+    let mut magic = vec![0; MAGIC.len()];
+    let _ = reader.read_exact(&mut magic).await?;
+    if magic != MAGIC {
+        return Err(invalid("not a chunk archive"));
+    }
+    let version = reader.read_u32_le().await?;
+    if version != VERSION {
+        return Err(invalid(&format!("unsupported version {}", version)));
+    }
+
+    let mut manifest = ArchiveManifest::default();
+    loop {
+        match read_record(&mut reader).await? {
+            Record::Chunk {
+                kind,
+                data,
+                checksum,
+            } => {
+                if XorName::from_content(&[data.as_slice()]) != checksum {
+                    return Err(invalid("chunk checksum mismatch"));
+                }
+                match kind {
+                    ChunkKind::Blob => import_chunk(blobs, &data).await?,
+                    ChunkKind::Map => import_chunk(maps, &data).await?,
+                    ChunkKind::MapVersion => import_chunk(map_versions, &data).await?,
+                    ChunkKind::Sequence => import_chunk(sequences, &data).await?,
+                }
+                manifest.add(kind, &data, &checksum);
+            }
+            Record::Manifest(expected) => {
+                if expected != manifest {
+                    return Err(invalid("archive doesn't match its manifest"));
+                }
+                break;
+            }
+        }
+    }
+
+    info!("Imported {} from {}", manifest, archive.display());
+    Ok(manifest)
+}
+
+async fn export_store<T, W>(
+    store: &ChunkStore<T>,
+    kind: ChunkKind,
+    writer: &mut W,
+    manifest: &mut ArchiveManifest,
+) -> Result<()>
+where
+    T: Chunk,
+    W: AsyncWrite + Unpin,
+{
+    for id in store.keys().await {
+        let data = utils::serialise(&store.get(&id).await?)?.to_vec();
+        let checksum = XorName::from_content(&[data.as_slice()]);
+        manifest.add(kind, &data, &checksum);
+        write_record(
+            writer,
+            &Record::Chunk {
+                kind,
+                data,
+                checksum,
+            },
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn import_chunk<T: Chunk>(store: &mut ChunkStore<T>, data: &[u8]) -> Result<()> {
+    let chunk: T = bincode::deserialize(data).map_err(|_| Error::CorruptChunk)?;
+    if !chunk.is_valid() {
+        return Err(Error::CorruptChunk);
+    }
+    store.put(&chunk).await
+}
+
+async fn remove_all<T: Chunk>(store: &mut ChunkStore<T>) -> Result<()> {
+    for id in store.keys().await {
+        store.delete(&id).await?;
+    }
+    Ok(())
+}
+
+async fn write_record<W: AsyncWrite + Unpin>(writer: &mut W, record: &Record) -> Result<()> {
+    let bytes = utils::serialise(record)?;
+    writer.write_u64_le(bytes.len() as u64).await?;
+    writer.write_all(&bytes).await?;
+    Ok(())
+}
+
+async fn read_record<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Record> {
+    let len = reader.read_u64_le().await?;
+    if len > MAX_RECORD_SIZE {
+        return Err(invalid("oversized record"));
+    }
+    let mut bytes = vec![0; len as usize];
+    let _ = reader.read_exact(&mut bytes).await?;
+    bincode::deserialize(&bytes).map_err(|_| invalid("unreadable record"))
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidArchive(reason.to_string())
+}
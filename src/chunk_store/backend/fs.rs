@@ -0,0 +1,277 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::ChunkBackend;
+use crate::{utils, Error, Result};
+use async_trait::async_trait;
+use log::{info, warn};
+use std::{
+    ffi::OsStr,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs::{self, DirEntry, File},
+    io::AsyncWriteExt,
+};
+use xor_name::XorName;
+
+pub(in crate::chunk_store) const FORMAT_VERSION_FILENAME: &str = "format_version";
+
+/// The on-disk layout version of a store.  Version 0 (no marker file) keeps all chunk files flat
+/// in the store dir; version 1 spreads them over two levels of shard dirs.
+const FORMAT_VERSION: u32 = 1;
+/// The max name length for a chunk file.
+const MAX_CHUNK_FILE_NAME_LENGTH: usize = 104;
+/// The extension of the temp files chunks are written to before being moved into place.
+pub(in crate::chunk_store) const TEMP_FILE_EXTENSION: &str = "tmp";
+
+/// Keeps each chunk as a file, named after the hex of its key, under the store dir.
+///
+/// All file I/O goes through `tokio::fs`, which runs it on the blocking thread pool, so a slow
+/// disk doesn't stall the node's event loop.
+pub(crate) struct FsBackend {
+    dir: PathBuf,
+    // Where chunks that fail verification are moved to, out of the way of the store.
+    quarantine_dir: PathBuf,
+}
+
+impl FsBackend {
+    /// Opens the store at `dir`, creating it if needed, and returns it along with the space used by
+    /// the chunk files it already holds.
+    ///
+    /// The store is migrated to the current layout, and any temp files left behind by writes
+    /// interrupted by a crash are removed.
+    pub(crate) async fn open(dir: &Path, quarantine_dir: &Path) -> Result<(Self, u64)> {
+        if fs::metadata(dir).await.is_err() {
+            create_new_root(dir).await?
+        }
+        migrate(dir).await?;
+
+        let mut stored = 0;
+        for entry in shard_entries(dir).await? {
+            let path = entry.path();
+            if path.extension() == Some(OsStr::new(TEMP_FILE_EXTENSION)) {
+                warn!("Removing orphaned temp file: {}", path.display());
+                fs::remove_file(&path).await?;
+            } else if to_key(&entry).is_some() {
+                stored += entry.metadata().await?.len();
+            }
+        }
+
+        let backend = Self {
+            dir: dir.to_path_buf(),
+            quarantine_dir: quarantine_dir.to_path_buf(),
+        };
+        Ok((backend, stored))
+    }
+
+    fn file_path(&self, key: &[u8]) -> PathBuf {
+        file_path(&self.dir, key)
+    }
+}
+
+#[async_trait]
+impl ChunkBackend for FsBackend {
+    async fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let file_path = self.file_path(key);
+        let shard_dir = file_path.parent().unwrap_or(&self.dir);
+
+        // Write to a temp file which is then renamed over the chunk file, so that a crash can
+        // never leave a partially written chunk file behind.
+        let temp_file_path = file_path.with_extension(TEMP_FILE_EXTENSION);
+        if let Err(e) = write_file(shard_dir, &temp_file_path, &file_path, value).await {
+            let _ = fs::remove_file(&temp_file_path).await;
+            return Err(e.into());
+        }
+        if let Err(e) = sync_dir(shard_dir).await {
+            warn!("Could not sync chunk store dir: {}", e);
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.file_path(key)).await {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn size(&self, key: &[u8]) -> Result<Option<u64>> {
+        Ok(fs::metadata(self.file_path(key))
+            .await
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len()))
+    }
+
+    async fn delete(&mut self, key: &[u8]) -> Result<()> {
+        match fs::remove_file(self.file_path(key)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn quarantine(&mut self, key: &[u8]) -> Result<()> {
+        let file_path = self.file_path(key);
+        if self.size(key).await?.is_none() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.quarantine_dir).await?;
+        fs::rename(&file_path, self.quarantine_dir.join(hex::encode(key))).await?;
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(shard_entries(&self.dir)
+            .await?
+            .iter()
+            .filter_map(to_key)
+            .collect())
+    }
+}
+
+/// Returns the path of the file holding the value stored under `key` in the store at `dir`.
+pub(in crate::chunk_store) fn file_path(dir: &Path, key: &[u8]) -> PathBuf {
+    let file_name = hex::encode(key);
+    shard_dir(dir, OsStr::new(&file_name)).join(file_name)
+}
+
+async fn create_new_root(root: &Path) -> Result<()> {
+    fs::create_dir_all(root).await?;
+
+    // Verify that chunk files can be created.
+    let temp_file_path = root.join("0".repeat(MAX_CHUNK_FILE_NAME_LENGTH));
+    let _ = File::create(&temp_file_path).await?;
+    fs::remove_file(temp_file_path).await?;
+
+    Ok(())
+}
+
+/// Brings the layout of the store at `dir` up to `FORMAT_VERSION`, moving the chunk files of a
+/// flat store into their shard dirs.
+///
+/// Each file is moved by a rename and the version marker is only written once all of them have
+/// been moved, so an interrupted migration is simply resumed on the next start.
+async fn migrate(dir: &Path) -> Result<()> {
+    let version_path = dir.join(FORMAT_VERSION_FILENAME);
+    let version = match fs::read(&version_path).await {
+        Ok(bytes) => bincode::deserialize::<u32>(&bytes)?,
+        Err(_) => 0,
+    };
+    if version > FORMAT_VERSION {
+        return Err(Error::Logic(format!(
+            "Unsupported chunk store format version {} at {}",
+            version,
+            dir.display()
+        )));
+    }
+    if version == FORMAT_VERSION {
+        return Ok(());
+    }
+
+    let mut moved = 0;
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if !entry.file_type().await?.is_file() {
+            continue;
+        }
+        if path.extension() == Some(OsStr::new(TEMP_FILE_EXTENSION)) {
+            warn!("Removing orphaned temp file: {}", path.display());
+            fs::remove_file(&path).await?;
+            continue;
+        }
+        if to_key(&entry).is_none() {
+            continue;
+        }
+        let file_name = entry.file_name();
+        let shard_dir = shard_dir(dir, &file_name);
+        fs::create_dir_all(&shard_dir).await?;
+        fs::rename(&path, shard_dir.join(&file_name)).await?;
+        moved += 1;
+    }
+    if moved > 0 {
+        info!(
+            "Migrated {} chunk files at {} to format version {}",
+            moved,
+            dir.display(),
+            FORMAT_VERSION
+        );
+    }
+
+    let temp_version_path = version_path.with_extension(TEMP_FILE_EXTENSION);
+    write_file(
+        dir,
+        &temp_version_path,
+        &version_path,
+        &utils::serialise(&FORMAT_VERSION)?,
+    )
+    .await?;
+    sync_dir(dir).await?;
+    Ok(())
+}
+
+// Writes `value` to `temp_file_path` within `dir`, syncs it, and renames it to `file_path`.
+async fn write_file(
+    dir: &Path,
+    temp_file_path: &Path,
+    file_path: &Path,
+    value: &[u8],
+) -> io::Result<()> {
+    fs::create_dir_all(dir).await?;
+    let mut file = File::create(temp_file_path).await?;
+    file.write_all(value).await?;
+    file.sync_all().await?;
+    fs::rename(temp_file_path, file_path).await
+}
+
+// Returns the shard dir under `dir` holding the chunk file named `file_name`.  Two levels of
+// 256 dirs, taken from a hash of the name, keep the number of entries per dir small for stores
+// holding millions of chunks.
+fn shard_dir(dir: &Path, file_name: &OsStr) -> PathBuf {
+    let hash = XorName::from_content(&[file_name.to_string_lossy().as_bytes()]);
+    dir.join(format!("{:02x}", hash.0[0]))
+        .join(format!("{:02x}", hash.0[1]))
+}
+
+// Lists the entries of all shard dirs under `dir`.
+async fn shard_entries(dir: &Path) -> io::Result<Vec<DirEntry>> {
+    let mut entries = vec![];
+    let mut firsts = fs::read_dir(dir).await?;
+    while let Some(first) = firsts.next_entry().await? {
+        if !first.file_type().await?.is_dir() {
+            continue;
+        }
+        let mut seconds = fs::read_dir(first.path()).await?;
+        while let Some(second) = seconds.next_entry().await? {
+            if !second.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut shard = fs::read_dir(second.path()).await?;
+            while let Some(entry) = shard.next_entry().await? {
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+// Persists the renaming of a file within `dir`. Directories can't be synced on Windows.
+async fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir).await?.sync_all().await?;
+    Ok(())
+}
+
+fn to_key(entry: &DirEntry) -> Option<Vec<u8>> {
+    let file_name = entry.file_name();
+    let file_name = file_name.into_string().ok()?;
+    hex::decode(file_name).ok()
+}
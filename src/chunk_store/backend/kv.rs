@@ -0,0 +1,76 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::ChunkBackend;
+use crate::Result;
+use async_trait::async_trait;
+use sled::{Db, Tree};
+use std::path::Path;
+
+const DB_DIR: &str = "db";
+const QUARANTINE_TREE: &str = "quarantine";
+
+/// Keeps chunks in an embedded key-value database under the store dir.
+///
+/// Writes are made durable with `flush_async`, so waiting on the disk doesn't stall the node's
+/// event loop.
+pub(crate) struct KvBackend {
+    db: Db,
+    quarantined: Tree,
+}
+
+impl KvBackend {
+    /// Opens the database under `dir`, creating it if needed, and returns it along with the space
+    /// used by the chunks it already holds.
+    pub(crate) fn open(dir: &Path) -> Result<(Self, u64)> {
+        let db = sled::open(dir.join(DB_DIR))?;
+        let quarantined = db.open_tree(QUARANTINE_TREE)?;
+        let mut stored = 0;
+        for entry in db.iter() {
+            let (_, value) = entry?;
+            stored += value.len() as u64;
+        }
+        Ok((Self { db, quarantined }, stored))
+    }
+}
+
+#[async_trait]
+impl ChunkBackend for KvBackend {
+    async fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let _ = self.db.insert(key, value)?;
+        let _ = self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.db.get(key)?.map(|value| value.to_vec()))
+    }
+
+    async fn size(&self, key: &[u8]) -> Result<Option<u64>> {
+        Ok(self.db.get(key)?.map(|value| value.len() as u64))
+    }
+
+    async fn delete(&mut self, key: &[u8]) -> Result<()> {
+        let _ = self.db.remove(key)?;
+        let _ = self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn quarantine(&mut self, key: &[u8]) -> Result<()> {
+        if let Some(value) = self.db.get(key)? {
+            let _ = self.quarantined.insert(key, value)?;
+            let _ = self.db.remove(key)?;
+            let _ = self.db.flush_async().await?;
+        }
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<Vec<u8>>> {
+        self.db.iter().keys().map(|key| Ok(key?.to_vec())).collect()
+    }
+}
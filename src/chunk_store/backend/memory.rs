@@ -0,0 +1,51 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::ChunkBackend;
+use crate::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+/// Keeps chunks in memory only, so they are lost when the node stops.  Meant for tests.
+#[derive(Default)]
+pub(crate) struct MemoryBackend {
+    values: HashMap<Vec<u8>, Vec<u8>>,
+    quarantined: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+#[async_trait]
+impl ChunkBackend for MemoryBackend {
+    async fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let _ = self.values.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.values.get(key).cloned())
+    }
+
+    async fn size(&self, key: &[u8]) -> Result<Option<u64>> {
+        Ok(self.values.get(key).map(|value| value.len() as u64))
+    }
+
+    async fn delete(&mut self, key: &[u8]) -> Result<()> {
+        let _ = self.values.remove(key);
+        Ok(())
+    }
+
+    async fn quarantine(&mut self, key: &[u8]) -> Result<()> {
+        if let Some(value) = self.values.remove(key) {
+            let _ = self.quarantined.insert(key.to_vec(), value);
+        }
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self.values.keys().cloned().collect())
+    }
+}
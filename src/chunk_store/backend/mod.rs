@@ -0,0 +1,48 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! The storage media a `ChunkStore` can keep its chunks in.
+
+mod fs;
+mod kv;
+mod memory;
+
+pub(super) use self::fs::FsBackend;
+#[cfg(test)]
+pub(super) use self::fs::{file_path, FORMAT_VERSION_FILENAME, TEMP_FILE_EXTENSION};
+pub(super) use kv::KvBackend;
+pub(super) use memory::MemoryBackend;
+
+use crate::Result;
+use async_trait::async_trait;
+
+/// Storage of serialised chunks, keyed by their serialised ids.
+///
+/// A backend only stores bytes; `ChunkStore` does the (de)serialisation, verification and used
+/// space accounting on top of it, so that these behave the same over any backend.
+#[async_trait]
+pub(super) trait ChunkBackend: Send + Sync {
+    /// Stores `value` under `key`, replacing any previous value.  A failed write must leave any
+    /// previous value in place.
+    async fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()>;
+
+    /// Returns the value stored under `key`, if any.
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// Returns the size of the value stored under `key`, if any.
+    async fn size(&self, key: &[u8]) -> Result<Option<u64>>;
+
+    /// Removes the value stored under `key`, if any.
+    async fn delete(&mut self, key: &[u8]) -> Result<()>;
+
+    /// Moves the value stored under `key`, if any, out of the store and into quarantine.
+    async fn quarantine(&mut self, key: &[u8]) -> Result<()>;
+
+    /// Lists the keys of all stored values.
+    async fn keys(&self) -> Result<Vec<Vec<u8>>>;
+}
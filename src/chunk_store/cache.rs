@@ -0,0 +1,121 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use bytes::Bytes;
+use lru::LruCache;
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::Mutex,
+};
+
+/// A least recently used cache of chunks, keyed by their serialised ids, and bounded by the total
+/// serialised size of the chunks it holds.
+pub(super) struct ChunkCache<T> {
+    capacity: u64,
+    inner: Mutex<Inner<T>>,
+}
+
+struct Inner<T> {
+    entries: LruCache<Bytes, (T, u64)>,
+    size: u64,
+    hits: u64,
+    misses: u64,
+}
+
+/// Counters of a chunk store's read cache.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct CacheStats {
+    /// Reads served from the cache.
+    pub hits: u64,
+    /// Reads that had to go to the store.
+    pub misses: u64,
+    /// Number of chunks in the cache.
+    pub len: usize,
+    /// Total serialised size of the chunks in the cache.
+    pub size: u64,
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{} hits, {} misses, {} chunks, {} bytes",
+            self.hits, self.misses, self.len, self.size
+        )
+    }
+}
+
+impl<T: Clone> ChunkCache<T> {
+    /// Creates a cache holding at most `capacity` bytes of chunks.  A capacity of 0 disables it.
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(Inner {
+                entries: LruCache::unbounded(),
+                size: 0,
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+
+    /// Returns the chunk cached under `key`, if any, marking it as the most recently used.
+    pub fn get(&self, key: &Bytes) -> Option<T> {
+        let mut inner = self.lock();
+        let chunk = inner.entries.get(key).map(|(chunk, _)| chunk.clone());
+        if chunk.is_some() {
+            inner.hits += 1;
+        } else {
+            inner.misses += 1;
+        }
+        chunk
+    }
+
+    /// Caches `chunk` under `key`, evicting the least recently used chunks to stay within the
+    /// capacity.  Chunks bigger than the whole capacity are not cached.
+    pub fn insert(&self, key: Bytes, chunk: T, size: u64) {
+        if size > self.capacity {
+            return;
+        }
+        let mut inner = self.lock();
+        if let Some((_, replaced)) = inner.entries.put(key, (chunk, size)) {
+            inner.size -= replaced;
+        }
+        inner.size += size;
+        while inner.size > self.capacity {
+            match inner.entries.pop_lru() {
+                Some((_, (_, evicted))) => inner.size -= evicted,
+                None => break,
+            }
+        }
+    }
+
+    /// Drops the chunk cached under `key`, if any.
+    pub fn remove(&self, key: &Bytes) {
+        let mut inner = self.lock();
+        if let Some((_, size)) = inner.entries.pop(key) {
+            inner.size -= size;
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.lock();
+        CacheStats {
+            hits: inner.hits,
+            misses: inner.misses,
+            len: inner.entries.len(),
+            size: inner.size,
+        }
+    }
+
+    // Every update leaves the cache consistent, so it's still usable if a holder of the lock
+    // panicked.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner<T>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -10,9 +10,15 @@ use crate::ToDbKey;
 use serde::{de::DeserializeOwned, Serialize};
 use xor_name::XorName;
 
-pub(crate) trait Chunk: Serialize + DeserializeOwned {
+pub(crate) trait Chunk: Clone + Serialize + DeserializeOwned {
     type Id: ChunkId;
     fn id(&self) -> &Self::Id;
+
+    /// Checks that the chunk content still matches its id, for chunk types whose id is derived
+    /// from their content.
+    fn is_valid(&self) -> bool {
+        true
+    }
 }
 
 pub(crate) trait ChunkId: ToDbKey + PartialEq + Eq + DeserializeOwned {}
@@ -0,0 +1,52 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Compression of serialised chunks.
+//!
+//! A compressed chunk is stored behind a header of `MAGIC` followed by a byte identifying the
+//! codec.  Uncompressed chunks are stored as they are, without a header, as they always have been.
+//! The serialisation of every chunk type starts with a small enum variant index, so it can never
+//! start with `MAGIC`.
+
+use crate::{config_handler::Compression, Error, Result};
+
+const MAGIC: &[u8] = b"snc";
+const ZSTD_ID: u8 = 1;
+const LZ4_ID: u8 = 2;
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compresses a serialised chunk for storage with the given codec.  The chunk is left
+/// uncompressed if compressing doesn't make it smaller.
+pub(super) fn encode(compression: Compression, serialised: &[u8]) -> Result<Vec<u8>> {
+    let (id, compressed) = match compression {
+        Compression::None => return Ok(serialised.to_vec()),
+        Compression::Zstd => (ZSTD_ID, zstd::encode_all(serialised, ZSTD_LEVEL)?),
+        Compression::Lz4 => (LZ4_ID, lz4_flex::compress_prepend_size(serialised)),
+    };
+    if MAGIC.len() + 1 + compressed.len() >= serialised.len() {
+        return Ok(serialised.to_vec());
+    }
+    let mut encoded = Vec::with_capacity(MAGIC.len() + 1 + compressed.len());
+    encoded.extend_from_slice(MAGIC);
+    encoded.push(id);
+    encoded.extend_from_slice(&compressed);
+    Ok(encoded)
+}
+
+/// Returns the serialised chunk from its stored form, whichever codec it was stored with.
+pub(super) fn decode(stored: Vec<u8>) -> Result<Vec<u8>> {
+    if !stored.starts_with(MAGIC) || stored.len() <= MAGIC.len() {
+        return Ok(stored);
+    }
+    let compressed = &stored[MAGIC.len() + 1..];
+    match stored[MAGIC.len()] {
+        ZSTD_ID => zstd::decode_all(compressed).map_err(|_| Error::CorruptChunk),
+        LZ4_ID => lz4_flex::decompress_size_prepended(compressed).map_err(|_| Error::CorruptChunk),
+        _ => Err(Error::CorruptChunk),
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Encryption of chunks at rest.
+//!
+//! Both the stored chunks and the names they are stored under are sealed with ChaCha20-Poly1305.
+//! A chunk is sealed under a random nonce, and stored behind a header of `MAGIC` and the nonce.
+//! A name is sealed under a nonce derived from a keyed hash of the name itself, so that an id is
+//! always stored under the same name, while the name reveals nothing about the id.
+
+use crate::{Error, Result};
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::Rng;
+use std::fmt::{self, Debug, Formatter};
+use xor_name::XorName;
+
+/// The secret key chunks are encrypted with.
+pub(crate) type ChunkKey = [u8; 32];
+
+const MAGIC: &[u8] = b"sne";
+const NONCE_LEN: usize = 12;
+
+/// Seals and opens chunks and their names with keys derived from a `ChunkKey`.
+#[derive(Clone)]
+pub(crate) struct ChunkCipher {
+    values: ChaCha20Poly1305,
+    names: ChaCha20Poly1305,
+    name_nonces: XorName,
+    check: XorName,
+}
+
+impl Debug for ChunkCipher {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "ChunkCipher({:?})", self.check)
+    }
+}
+
+impl ChunkCipher {
+    pub fn new(key: &ChunkKey) -> Self {
+        let subkey = |label: &[u8]| XorName::from_content(&[label, key]);
+        Self {
+            values: ChaCha20Poly1305::new(Key::from_slice(&subkey(b"values").0)),
+            names: ChaCha20Poly1305::new(Key::from_slice(&subkey(b"names").0)),
+            name_nonces: subkey(b"name nonces"),
+            check: subkey(b"check"),
+        }
+    }
+
+    /// A value identifying the key, which can be stored in the clear to detect using the wrong key.
+    pub(super) fn key_check(&self) -> XorName {
+        self.check
+    }
+
+    /// Seals the name a chunk is stored under.  The same name is always sealed the same way.
+    pub(super) fn seal_name(&self, name: &[u8]) -> Result<Vec<u8>> {
+        let hash = XorName::from_content(&[&self.name_nonces.0, name]);
+        let nonce = &hash.0[..NONCE_LEN];
+        let sealed = self
+            .names
+            .encrypt(Nonce::from_slice(nonce), name)
+            .map_err(|_| Error::Logic("Failed to encrypt chunk name".to_string()))?;
+        Ok([nonce, &sealed].concat())
+    }
+
+    /// Opens a sealed name, returning `None` if it wasn't sealed with this key.
+    pub(super) fn open_name(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        self.names.decrypt(Nonce::from_slice(nonce), sealed).ok()
+    }
+
+    /// Seals a chunk for storage.
+    pub(super) fn seal(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let nonce: [u8; NONCE_LEN] = rand::thread_rng().gen();
+        let sealed = self
+            .values
+            .encrypt(Nonce::from_slice(&nonce), value)
+            .map_err(|_| Error::Logic("Failed to encrypt chunk".to_string()))?;
+        Ok([MAGIC, &nonce, &sealed].concat())
+    }
+
+    /// Opens a sealed chunk.  Anything that wasn't sealed with this key is corrupt.
+    pub(super) fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if !sealed.starts_with(MAGIC) || sealed.len() < MAGIC.len() + NONCE_LEN {
+            return Err(Error::CorruptChunk);
+        }
+        let (nonce, sealed) = sealed[MAGIC.len()..].split_at(NONCE_LEN);
+        self.values
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| Error::CorruptChunk)
+    }
+}
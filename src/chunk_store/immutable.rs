@@ -7,7 +7,7 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::chunk::{Chunk, ChunkId};
-use sn_data_types::{Blob, BlobAddress};
+use sn_data_types::{Blob, BlobAddress, PrivateBlob, PublicBlob};
 
 impl Chunk for Blob {
     type Id = BlobAddress;
@@ -17,6 +17,18 @@ impl Chunk for Blob {
             Blob::Private(ref chunk) => chunk.address(),
         }
     }
+
+    // The address of a blob is derived from its content, so rebuilding the blob from the stored
+    // content must yield the same address.
+    fn is_valid(&self) -> bool {
+        let rebuilt = match self {
+            Blob::Public(ref chunk) => Blob::Public(PublicBlob::new(chunk.value().clone())),
+            Blob::Private(ref chunk) => {
+                Blob::Private(PrivateBlob::new(chunk.value().clone(), *chunk.owner()))
+            }
+        };
+        rebuilt.address() == self.address()
+    }
 }
 
 impl ChunkId for BlobAddress {}
@@ -8,7 +8,12 @@
 
 //! A simple, persistent, disk-based key-value store.
 
+mod archive;
+mod backend;
+mod cache;
 mod chunk;
+mod codec;
+mod encryption;
 mod immutable;
 mod mutable;
 mod sequence;
@@ -16,119 +21,241 @@ mod sequence;
 mod tests;
 mod used_space;
 
+use crate::config_handler::{Compression, Config, StorageBackend, StoreKind};
 use crate::error::{Error, Result};
-use crate::utils;
+use crate::{state_db, utils};
+pub use archive::{export_chunks, import_chunks, ArchiveManifest};
+use backend::{ChunkBackend, FsBackend, KvBackend, MemoryBackend};
+use bytes::Bytes;
+pub(crate) use cache::CacheStats;
+use cache::ChunkCache;
 use chunk::{Chunk, ChunkId};
-use log::{info, trace};
+use encryption::ChunkCipher;
+use log::{info, trace, warn};
+pub(crate) use mutable::{MapSnapshot, MapVersion};
 use sn_data_types::{Blob, Map, Sequence};
 use std::{
-    fs::{self, DirEntry, File, Metadata},
-    io::{Read, Write},
+    io::ErrorKind,
     marker::PhantomData,
     path::{Path, PathBuf},
 };
+use tokio::fs;
 use used_space::StoreId;
 pub use used_space::UsedSpace;
 
 const CHUNK_STORE_DIR: &str = "chunks";
-
-/// The max name length for a chunk file.
-const MAX_CHUNK_FILE_NAME_LENGTH: usize = 104;
+const QUARANTINE_DIR: &str = "quarantine";
+/// Marks a store as encrypted, holding the check value of the key it was encrypted with.
+const KEY_CHECK_FILENAME: &str = "key_check";
 
 pub(crate) type BlobChunkStore = ChunkStore<Blob>;
 pub(crate) type MapChunkStore = ChunkStore<Map>;
+pub(crate) type MapHistoryChunkStore = ChunkStore<MapSnapshot>;
 pub(crate) type SequenceChunkStore = ChunkStore<Sequence>;
 
-/// `ChunkStore` is a store of data held as serialised files on disk, implementing a maximum disk
-/// usage to restrict storage.
+/// Settings shared by all of a node's `ChunkStore`s.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StoreConfig {
+    /// Where chunks are kept.
+    pub backend: StorageBackend,
+    /// How chunks are compressed when stored.
+    pub compression: Compression,
+    /// Byte budget of the read cache of each store.
+    pub cache_capacity: u64,
+    /// How chunks are encrypted when stored, if they are.
+    pub cipher: Option<ChunkCipher>,
+    /// Directories chunks are spread over instead of the root directory, if any.
+    pub data_dirs: Vec<PathBuf>,
+}
+
+impl StoreConfig {
+    /// Takes the settings from the node config.
+    ///
+    /// Chunks are encrypted if the node has a chunk key in `root_dir`, or if encryption is enabled,
+    /// in which case the key is created the first time.  If stores have been encrypted but the key
+    /// has gone missing, returns `Error::ChunkEncryptionKeyMissing`.
+    pub async fn new(config: &Config, root_dir: &Path) -> Result<Self> {
+        let data_dirs: Vec<_> = config
+            .data_dirs()
+            .iter()
+            .map(|dir| dir.path.clone())
+            .collect();
+        let key = match state_db::get_chunk_key(root_dir).await? {
+            Some(key) => Some(key),
+            None => {
+                for root in std::iter::once(root_dir).chain(data_dirs.iter().map(PathBuf::as_path))
+                {
+                    if any_store_encrypted(root).await? {
+                        return Err(Error::ChunkEncryptionKeyMissing);
+                    }
+                }
+                if config.encrypt_chunks() {
+                    Some(state_db::store_new_chunk_key(root_dir).await?)
+                } else {
+                    None
+                }
+            }
+        };
+        Ok(Self {
+            backend: config.storage_backend(),
+            compression: config.compression(),
+            cache_capacity: config.cache_capacity(),
+            cipher: key.as_ref().map(ChunkCipher::new),
+            data_dirs,
+        })
+    }
+}
+
+/// `ChunkStore` is a store of data held as serialised chunks in one `ChunkBackend` per data dir,
+/// implementing a maximum disk usage to restrict storage.
 pub(crate) struct ChunkStore<T: Chunk> {
-    dir: PathBuf,
+    volumes: Vec<Volume>,
+    compression: Compression,
+    cipher: Option<ChunkCipher>,
+    // Recently read chunks, saving reading and deserialising them again.
+    cache: ChunkCache<T>,
     // Maximum space allowed for all `ChunkStore`s to consume.
     used_space: UsedSpace,
-    id: StoreId,
+    // The key of the last chunk scrubbed, which the next scrub continues after.
+    scrub_cursor: Option<Vec<u8>>,
     _phantom: PhantomData<T>,
 }
 
+// The part of a `ChunkStore` kept in one data dir.
+struct Volume {
+    backend: Box<dyn ChunkBackend>,
+    // Tracks the space used by the chunks in this volume.
+    id: StoreId,
+}
+
 impl<T> ChunkStore<T>
 where
     T: Chunk,
     Self: Subdir,
 {
-    /// Creates a new `ChunkStore` at location `root/CHUNK_STORE_DIR/<chunk type>`.
+    /// Creates a new `ChunkStore` at location `root/CHUNK_STORE_DIR/<chunk type>`, keeping its
+    /// chunks as set in `config`.  If `config` lists data dirs, the store is spread over the same
+    /// location within each of them instead of `root`.
     ///
     /// If the location specified already exists, the previous ChunkStore there is opened, otherwise
     /// the required folder structure is created.
     ///
     /// The maximum storage space is defined by `max_capacity`.  This specifies the max usable by
     /// _all_ `ChunkStores`, not per `ChunkStore`.
-    pub async fn new<P: AsRef<Path>>(root: P, used_space: UsedSpace) -> Result<Self> {
-        let dir = root.as_ref().join(CHUNK_STORE_DIR).join(Self::subdir());
-
-        if fs::read(&dir).is_err() {
-            Self::create_new_root(&dir)?
+    pub async fn new<P: AsRef<Path>>(
+        root: P,
+        used_space: UsedSpace,
+        config: &StoreConfig,
+    ) -> Result<Self> {
+        let roots = if config.data_dirs.is_empty() || config.backend == StorageBackend::Memory {
+            vec![root.as_ref().to_path_buf()]
+        } else {
+            config.data_dirs.clone()
+        };
+        let mut volumes = Vec::with_capacity(roots.len());
+        for root in roots {
+            volumes.push(Self::open_volume(&root, &used_space, config).await?);
         }
 
-        let id = used_space.add_local_store(&dir).await?;
         Ok(ChunkStore {
-            dir,
+            volumes,
+            compression: config.compression,
+            cipher: config.cipher.clone(),
+            cache: ChunkCache::new(config.cache_capacity),
             used_space,
-            id,
+            scrub_cursor: None,
             _phantom: PhantomData,
         })
     }
-}
 
-impl<T: Chunk> ChunkStore<T> {
-    fn create_new_root(root: &Path) -> Result<()> {
-        fs::create_dir_all(root)?;
-
-        // Verify that chunk files can be created.
-        let temp_file_path = root.join("0".repeat(MAX_CHUNK_FILE_NAME_LENGTH));
-        let _ = File::create(&temp_file_path)?;
-        fs::remove_file(temp_file_path)?;
+    // Opens the part of the store under `root`, and registers the space its chunks use.
+    async fn open_volume(
+        root: &Path,
+        used_space: &UsedSpace,
+        config: &StoreConfig,
+    ) -> Result<Volume> {
+        let dir = root.join(CHUNK_STORE_DIR).join(Self::subdir());
+        let quarantine_dir = root
+            .join(CHUNK_STORE_DIR)
+            .join(QUARANTINE_DIR)
+            .join(Self::subdir());
+
+        let (mut backend, mut stored, record_dir): (Box<dyn ChunkBackend>, _, _) =
+            match config.backend {
+                StorageBackend::Filesystem => {
+                    let (backend, stored) = FsBackend::open(&dir, &quarantine_dir).await?;
+                    (Box::new(backend), stored, Some(dir.as_path()))
+                }
+                StorageBackend::Memory => (Box::new(MemoryBackend::default()), 0, None),
+                StorageBackend::Embedded => {
+                    let (backend, stored) = KvBackend::open(&dir)?;
+                    (Box::new(backend), stored, Some(dir.as_path()))
+                }
+            };
+
+        if let Some(dir) = record_dir {
+            stored = init_encryption(backend.as_mut(), config.cipher.as_ref(), dir, stored).await?;
+        }
 
-        Ok(())
+        let id = used_space
+            .add_local_store(Self::kind(), record_dir, stored)
+            .await?;
+        Ok(Volume { backend, id })
     }
+}
 
+impl<T: Chunk> ChunkStore<T> {
     /// Stores a new data chunk.
     ///
-    /// If there is not enough storage space available, returns `Error::NotEnoughSpace`.  In case of
-    /// an IO error, it returns `Error::Io`.
+    /// If there is not enough storage space available, returns `Error::NotEnoughSpace`, or the
+    /// `NotEnoughSpaceFor*` error of the store's kind of data if its quota is used up.  In case of an
+    /// IO error, it returns `Error::Io`.
     ///
-    /// If a chunk with the same id already exists, it will be overwritten.
+    /// If a chunk with the same id already exists, it will be overwritten.  New chunks are placed in
+    /// the data dir with the most free space.
+    ///
+    /// The chunk is compressed as set in the store config, and charged the space it takes once
+    /// compressed.
     pub async fn put(&mut self, chunk: &T) -> Result<()> {
         info!("Writing chunk");
-        let serialised_chunk = utils::serialise(chunk)?;
+        let serialised_chunk = codec::encode(self.compression, &utils::serialise(chunk)?)?;
+        let serialised_chunk = match &self.cipher {
+            Some(cipher) => cipher.seal(&serialised_chunk)?,
+            None => serialised_chunk,
+        };
         let consumed_space = serialised_chunk.len() as u64;
 
         info!("consumed space: {:?}", consumed_space);
         info!("max : {:?}", self.used_space.max_capacity().await);
         info!("use space total : {:?}", self.used_space.total().await);
 
-        let file_path = self.file_path(chunk.id())?;
-        self.do_delete(&file_path).await?;
-
-        // pre-reserve space
-        self.used_space.increase(self.id, consumed_space).await?;
+        let key = self.key(chunk.id())?;
+        self.cache.remove(&key);
+        let (index, replaced_space) = match self.find(&key).await? {
+            Some(found) => found,
+            None => (self.roomiest_volume().await, 0),
+        };
+        let volume = &mut self.volumes[index];
+
+        // pre-reserve the space not already used by any chunk being replaced
+        let reserved_space = consumed_space.saturating_sub(replaced_space);
+        self.used_space.increase(volume.id, reserved_space).await?;
         trace!(
             "use space total after add: {:?}",
             self.used_space.total().await
         );
 
-        let res = File::create(&file_path).and_then(|mut file| {
-            file.write_all(&serialised_chunk)?;
-            file.sync_all()
-        });
-
-        match res {
-            Ok(_) => {
+        match volume.backend.put(&key, &serialised_chunk).await {
+            Ok(()) => {
                 info!("Writing chunk succeeded!");
-                Ok(())
+                self.used_space
+                    .decrease(volume.id, replaced_space.saturating_sub(consumed_space))
+                    .await
             }
             Err(e) => {
                 info!("Writing chunk failed!");
-                self.used_space.decrease(self.id, consumed_space).await?;
-                Err(e.into())
+                self.used_space.decrease(volume.id, reserved_space).await?;
+                Err(e)
             }
         }
     }
@@ -138,7 +265,15 @@ impl<T: Chunk> ChunkStore<T> {
     /// If the data doesn't exist, it does nothing and returns `Ok`.  In the case of an IO error, it
     /// returns `Error::Io`.
     pub async fn delete(&mut self, id: &T::Id) -> Result<()> {
-        self.do_delete(&self.file_path(id)?).await
+        let key = self.key(id)?;
+        self.cache.remove(&key);
+        if let Some((index, size)) = self.find(&key).await? {
+            let volume = &mut self.volumes[index];
+            self.used_space.decrease(volume.id, size).await?;
+            volume.backend.delete(&key).await
+        } else {
+            Ok(())
+        }
     }
 
     /// Used space to max space ratio.
@@ -154,87 +289,297 @@ impl<T: Chunk> ChunkStore<T> {
 
     /// Returns a data chunk previously stored under `id`.
     ///
-    /// If the data file can't be accessed, it returns `Error::NoSuchChunk`.
-    pub fn get(&self, id: &T::Id) -> Result<T> {
-        let mut file = File::open(self.file_path(id)?).map_err(|_| Error::NoSuchChunk)?;
-        let mut contents = vec![];
-        let _ = file.read_to_end(&mut contents)?;
-        let chunk = bincode::deserialize::<T>(&contents)?;
-        // Check it's the requested chunk variant.
-        if chunk.id() == id {
-            Ok(chunk)
+    /// If the data can't be found, it returns `Error::NoSuchChunk`.  If the stored content is not
+    /// the intact chunk stored under `id`, it returns `Error::CorruptChunk`.
+    ///
+    /// Chunks are served from the read cache when they're in it.
+    pub async fn get(&self, id: &T::Id) -> Result<T> {
+        let key = self.key(id)?;
+        if let Some(chunk) = self.cache.get(&key) {
+            return Ok(chunk);
+        }
+        let (chunk, size) = self.read(&key, id).await?;
+        self.cache.insert(key, chunk.clone(), size);
+        Ok(chunk)
+    }
+
+    /// Returns the hit and miss counters of the read cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Moves the data chunk stored under `id` into quarantine, releasing the space it used in the
+    /// store.
+    ///
+    /// If the data doesn't exist, it does nothing and returns `Ok`.
+    pub async fn quarantine(&mut self, id: &T::Id) -> Result<()> {
+        let key = self.key(id)?;
+        self.cache.remove(&key);
+        if let Some((index, size)) = self.find(&key).await? {
+            let volume = &mut self.volumes[index];
+            volume.backend.quarantine(&key).await?;
+            self.used_space.decrease(volume.id, size).await?;
+        }
+        Ok(())
+    }
+
+    /// Verifies the next `limit` stored chunks in key order, quarantining those that can't be
+    /// read back intact. Each scrub picks up after the last chunk the previous one verified, and
+    /// starts over after reaching the last chunk.
+    ///
+    /// Returns the ids of the quarantined chunks.
+    pub async fn scrub(&mut self, limit: usize) -> Result<Vec<T::Id>> {
+        let mut keys = self.backend_keys().await;
+        keys.sort();
+        keys.dedup();
+        let batch: Vec<_> = keys
+            .into_iter()
+            .filter(|key| match &self.scrub_cursor {
+                Some(cursor) => key > cursor,
+                None => true,
+            })
+            .take(limit)
+            .collect();
+        self.scrub_cursor = if batch.len() < limit {
+            None
         } else {
-            Err(Error::NoSuchChunk)
+            batch.last().cloned()
+        };
+
+        let mut corrupt = vec![];
+        for key in batch {
+            let id = match self.id_of(&key) {
+                Some(id) => id,
+                None => continue,
+            };
+            if let Err(Error::CorruptChunk) = self.read(&key, &id).await {
+                warn!("Quarantining corrupt chunk {}", hex::encode(&key));
+                self.quarantine(&id).await?;
+                corrupt.push(id);
+            }
         }
+        Ok(corrupt)
     }
 
     pub async fn total_used_space(&self) -> u64 {
         self.used_space.total().await
     }
 
+    /// Closes the store, whose chunks then no longer count against the used space. They are kept,
+    /// for the store to be opened again.
+    pub async fn close(self) -> Result<()> {
+        for volume in &self.volumes {
+            self.used_space.remove_local_store(volume.id).await?;
+        }
+        Ok(())
+    }
+
     /// Tests if a data chunk has been previously stored under `id`.
-    pub fn has(&self, id: &T::Id) -> bool {
-        if let Ok(path) = self.file_path(id) {
-            fs::metadata(path)
-                .as_ref()
-                .map(Metadata::is_file)
-                .unwrap_or(false)
+    pub async fn has(&self, id: &T::Id) -> bool {
+        if let Ok(key) = self.key(id) {
+            matches!(self.find(&key).await, Ok(Some(_)))
         } else {
             false
         }
     }
 
     /// Lists all keys of currently stored data.
-    #[cfg_attr(not(test), allow(unused))]
-    pub fn keys(&self) -> Vec<T::Id> {
-        fs::read_dir(&self.dir)
-            .map(|entries| {
-                entries
-                    .filter_map(|entry| to_chunk_id(&entry.ok()?))
-                    .collect()
-            })
-            .unwrap_or_else(|_| Vec::new())
+    pub async fn keys(&self) -> Vec<T::Id> {
+        self.backend_keys()
+            .await
+            .iter()
+            .filter_map(|key| self.id_of(key))
+            .collect()
     }
 
-    async fn do_delete(&mut self, file_path: &Path) -> Result<()> {
-        if let Ok(metadata) = fs::metadata(file_path) {
-            self.used_space.decrease(self.id, metadata.len()).await?;
-            fs::remove_file(file_path).map_err(From::from)
+    // Lists the keys the chunks are stored under in the backends.
+    async fn backend_keys(&self) -> Vec<Vec<u8>> {
+        let mut keys = vec![];
+        for volume in &self.volumes {
+            if let Ok(volume_keys) = volume.backend.keys().await {
+                keys.extend(volume_keys);
+            }
+        }
+        keys
+    }
+
+    // The id of the chunk stored under the backend `key`.
+    fn id_of(&self, key: &[u8]) -> Option<T::Id> {
+        let key = match &self.cipher {
+            Some(cipher) => cipher.open_name(key)?,
+            None => key.to_vec(),
+        };
+        bincode::deserialize(&key).ok()
+    }
+
+    // Reads the chunk stored under `key` from the backend, bypassing the cache, and returns it with
+    // its serialised size.
+    async fn read(&self, key: &[u8], id: &T::Id) -> Result<(T, u64)> {
+        let mut contents = None;
+        for volume in &self.volumes {
+            contents = volume.backend.get(key).await?;
+            if contents.is_some() {
+                break;
+            }
+        }
+        let contents = contents.ok_or(Error::NoSuchChunk)?;
+        let contents = match &self.cipher {
+            Some(cipher) => cipher.open(&contents)?,
+            None => contents,
+        };
+        let contents = codec::decode(contents)?;
+        let chunk = bincode::deserialize::<T>(&contents).map_err(|_| Error::CorruptChunk)?;
+        // Check it's the requested chunk variant, and that its content is unaltered.
+        if chunk.id() == id && chunk.is_valid() {
+            Ok((chunk, contents.len() as u64))
         } else {
-            Ok(())
+            Err(Error::CorruptChunk)
+        }
+    }
+
+    // Returns the index of the volume holding the value stored under `key`, and the value's size.
+    async fn find(&self, key: &[u8]) -> Result<Option<(usize, u64)>> {
+        for (index, volume) in self.volumes.iter().enumerate() {
+            if let Some(size) = volume.backend.size(key).await? {
+                return Ok(Some((index, size)));
+            }
         }
+        Ok(None)
     }
 
-    fn file_path(&self, id: &T::Id) -> Result<PathBuf> {
-        Ok(self.dir.join(&hex::encode(utils::serialise(id)?)))
+    // Returns the index of the volume with the most space left.
+    async fn roomiest_volume(&self) -> usize {
+        let mut roomiest = (0, 0);
+        for (index, volume) in self.volumes.iter().enumerate() {
+            let headroom = self.used_space.headroom(volume.id).await;
+            if headroom > roomiest.1 {
+                roomiest = (index, headroom);
+            }
+        }
+        roomiest.0
+    }
+
+    fn key(&self, id: &T::Id) -> Result<Bytes> {
+        let key = utils::serialise(id)?;
+        match &self.cipher {
+            Some(cipher) => Ok(cipher.seal_name(&key)?.into()),
+            None => Ok(key),
+        }
     }
 }
 
 pub(crate) trait Subdir {
     fn subdir() -> &'static Path;
+
+    // The kind of data in the store, for quotas.
+    fn kind() -> Option<StoreKind>;
 }
 
 impl Subdir for BlobChunkStore {
     fn subdir() -> &'static Path {
         Path::new("immutable")
     }
+
+    fn kind() -> Option<StoreKind> {
+        Some(StoreKind::Blob)
+    }
 }
 
 impl Subdir for MapChunkStore {
     fn subdir() -> &'static Path {
         Path::new("mutable")
     }
+
+    fn kind() -> Option<StoreKind> {
+        Some(StoreKind::Map)
+    }
+}
+
+// Earlier versions of Maps count against the quota of the Maps they are versions of.
+impl Subdir for MapHistoryChunkStore {
+    fn subdir() -> &'static Path {
+        Path::new("mutable_history")
+    }
+
+    fn kind() -> Option<StoreKind> {
+        Some(StoreKind::Map)
+    }
 }
 
 impl Subdir for SequenceChunkStore {
     fn subdir() -> &'static Path {
         Path::new("sequence")
     }
+
+    fn kind() -> Option<StoreKind> {
+        Some(StoreKind::Sequence)
+    }
+}
+
+/// Checks that the store at `dir` was encrypted with the key of `cipher`, if any.
+///
+/// The first time a store is opened with a key, the chunks it holds are encrypted and the store is
+/// marked as encrypted.  Returns the space used by the chunks of the store after that.
+async fn init_encryption(
+    backend: &mut dyn ChunkBackend,
+    cipher: Option<&ChunkCipher>,
+    dir: &Path,
+    mut stored: u64,
+) -> Result<u64> {
+    let check_path = dir.join(KEY_CHECK_FILENAME);
+    let check = match fs::read(&check_path).await {
+        Ok(check) => Some(check),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let cipher = match (cipher, check) {
+        (None, None) => return Ok(stored),
+        (None, Some(_)) => return Err(Error::ChunkEncryptionKeyMissing),
+        (Some(cipher), Some(check)) if check[..] == cipher.key_check().0[..] => return Ok(stored),
+        (Some(_), Some(_)) => return Err(Error::ChunkEncryptionKeyMismatch),
+        (Some(cipher), None) => cipher,
+    };
+
+    let mut encrypted = 0;
+    for key in backend.keys().await? {
+        // Chunks encrypted by an earlier, interrupted, run are already in place.
+        if cipher.open_name(&key).is_some() {
+            continue;
+        }
+        let value = match backend.get(&key).await? {
+            Some(value) => value,
+            None => continue,
+        };
+        let sealed_key = cipher.seal_name(&key)?;
+        let sealed = cipher.seal(&value)?;
+        let replaced = backend.size(&sealed_key).await?.unwrap_or(0);
+        backend.put(&sealed_key, &sealed).await?;
+        backend.delete(&key).await?;
+        stored = (stored + sealed.len() as u64).saturating_sub(value.len() as u64 + replaced);
+        encrypted += 1;
+    }
+    if encrypted > 0 {
+        info!("Encrypted {} chunks at {}", encrypted, dir.display());
+    }
+
+    fs::write(&check_path, cipher.key_check().0).await?;
+    Ok(stored)
 }
 
-fn to_chunk_id<T: ChunkId>(entry: &DirEntry) -> Option<T> {
-    let file_name = entry.file_name();
-    let file_name = file_name.into_string().ok()?;
-    let bytes = hex::decode(file_name).ok()?;
-    bincode::deserialize(&bytes).ok()
+// Whether any store under `root` has been encrypted.
+async fn any_store_encrypted(root: &Path) -> Result<bool> {
+    let mut entries = match fs::read_dir(root.join(CHUNK_STORE_DIR)).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        if fs::metadata(entry.path().join(KEY_CHECK_FILENAME))
+            .await
+            .is_ok()
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
 }
@@ -7,6 +7,8 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::chunk::{Chunk, ChunkId};
+use crate::ToDbKey;
+use serde::{Deserialize, Serialize};
 use sn_data_types::{Map, MapAddress};
 
 impl Chunk for Map {
@@ -20,3 +22,45 @@ impl Chunk for Map {
 }
 
 impl ChunkId for MapAddress {}
+
+/// The state a Map was in when a later version of it replaced it.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct MapSnapshot {
+    id: MapVersion,
+    map: Map,
+}
+
+/// Identifies a `MapSnapshot`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct MapVersion {
+    pub address: MapAddress,
+    pub version: u64,
+}
+
+impl MapSnapshot {
+    pub fn new(map: Map) -> Self {
+        let id = MapVersion {
+            address: *map.address(),
+            version: map.version(),
+        };
+        Self { id, map }
+    }
+
+    pub fn into_map(self) -> Map {
+        self.map
+    }
+}
+
+impl Chunk for MapSnapshot {
+    type Id = MapVersion;
+    fn id(&self) -> &Self::Id {
+        &self.id
+    }
+
+    fn is_valid(&self) -> bool {
+        self.id.address == *self.map.address() && self.id.version == self.map.version()
+    }
+}
+
+impl ToDbKey for MapVersion {}
+impl ChunkId for MapVersion {}
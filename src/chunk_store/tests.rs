@@ -7,16 +7,27 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::{
+    backend::{self, FORMAT_VERSION_FILENAME, TEMP_FILE_EXTENSION},
     chunk::{Chunk, ChunkId},
-    ChunkStore, Result as ChunkStoreResult, Subdir, UsedSpace,
+    encryption::ChunkCipher,
+    BlobChunkStore, CacheStats, ChunkStore, MapHistoryChunkStore, MapSnapshot, MapVersion,
+    Result as ChunkStoreResult, StoreConfig, Subdir, UsedSpace, CHUNK_STORE_DIR, QUARANTINE_DIR,
+};
+use crate::{
+    export_chunks, import_chunks, Compression, Config, DataDir, Error, Result, StorageBackend,
+    StoreKind, ToDbKey,
 };
-use crate::{Error, Result, ToDbKey};
 use rand::{distributions::Standard, rngs::ThreadRng, Rng};
 use serde::{Deserialize, Serialize};
-use std::{path::Path, u64};
+use sn_data_types::{Blob, Keypair, Map, PublicBlob, UnseqMap};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    u64,
+};
 use tempdir::TempDir;
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 struct Data {
     id: Id,
     value: Vec<u8>,
@@ -40,6 +51,10 @@ impl Subdir for ChunkStore<Data> {
     fn subdir() -> &'static Path {
         Path::new("test")
     }
+
+    fn kind() -> Option<StoreKind> {
+        None
+    }
 }
 
 // TODO: use seedable rng
@@ -51,6 +66,17 @@ fn temp_dir() -> ChunkStoreResult<TempDir> {
     TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
 }
 
+// The dir of a filesystem-backed `ChunkStore` under `root`.
+fn store_dir<S: Subdir>(root: &Path) -> PathBuf {
+    root.join(CHUNK_STORE_DIR).join(S::subdir())
+}
+
+// The file holding the chunk stored under `id` in a filesystem-backed `ChunkStore` at `dir`.
+fn file_path<I: Serialize>(dir: &Path, id: &I) -> Result<PathBuf> {
+    let key = bincode::serialize(id).map_err(Error::Bincode)?;
+    Ok(backend::file_path(dir, &key))
+}
+
 struct Chunks {
     data_and_sizes: Vec<(Vec<u8>, u64)>,
     total_size: u64,
@@ -87,7 +113,8 @@ async fn successful_put() -> Result<()> {
 
     let root = temp_dir()?;
     let used_space = UsedSpace::new(u64::MAX);
-    let mut chunk_store = ChunkStore::<Data>::new(root.path(), used_space.clone()).await?;
+    let mut chunk_store =
+        ChunkStore::<Data>::new(root.path(), used_space.clone(), &StoreConfig::default()).await?;
 
     for (index, (data, size)) in chunks.data_and_sizes.iter().enumerate().rev() {
         let the_data = &Data {
@@ -95,17 +122,17 @@ async fn successful_put() -> Result<()> {
             value: data.clone(),
         };
         let used_space_before = chunk_store.total_used_space().await;
-        assert!(!chunk_store.has(&the_data.id));
+        assert!(!chunk_store.has(&the_data.id).await);
         chunk_store.put(the_data).await?;
         let used_space_after = chunk_store.total_used_space().await;
         assert_eq!(used_space_after, used_space_before + size);
-        assert!(chunk_store.has(&the_data.id));
+        assert!(chunk_store.has(&the_data.id).await);
         assert!(used_space_after <= chunks.total_size);
     }
 
     assert_eq!(chunk_store.total_used_space().await, chunks.total_size);
 
-    let mut keys = chunk_store.keys();
+    let mut keys = chunk_store.keys().await;
     keys.sort();
     assert_eq!(
         (0..chunks.data_and_sizes.len())
@@ -123,7 +150,8 @@ async fn failed_put_when_not_enough_space() -> Result<()> {
     let root = temp_dir()?;
     let capacity = 32;
     let used_space = UsedSpace::new(capacity);
-    let mut chunk_store = ChunkStore::new(root.path(), used_space.clone()).await?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), used_space.clone(), &StoreConfig::default()).await?;
 
     let data = Data {
         id: Id(rng.gen()),
@@ -148,7 +176,8 @@ async fn delete() -> Result<()> {
 
     let root = temp_dir()?;
     let used_space = UsedSpace::new(u64::MAX);
-    let mut chunk_store = ChunkStore::new(root.path(), used_space.clone()).await?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), used_space.clone(), &StoreConfig::default()).await?;
 
     for (index, (data, size)) in chunks.data_and_sizes.iter().enumerate() {
         let the_data = &Data {
@@ -157,9 +186,9 @@ async fn delete() -> Result<()> {
         };
         chunk_store.put(the_data).await?;
         assert_eq!(chunk_store.total_used_space().await, *size);
-        assert!(chunk_store.has(&the_data.id));
+        assert!(chunk_store.has(&the_data.id).await);
         chunk_store.delete(&the_data.id).await?;
-        assert!(!chunk_store.has(&the_data.id));
+        assert!(!chunk_store.has(&the_data.id).await);
         assert_eq!(chunk_store.total_used_space().await, 0);
     }
 
@@ -173,7 +202,8 @@ async fn put_and_get_value_should_be_same() -> Result<()> {
 
     let root = temp_dir()?;
     let used_space = UsedSpace::new(u64::MAX);
-    let mut chunk_store = ChunkStore::new(root.path(), used_space.clone()).await?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), used_space.clone(), &StoreConfig::default()).await?;
 
     for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
         chunk_store
@@ -185,7 +215,7 @@ async fn put_and_get_value_should_be_same() -> Result<()> {
     }
 
     for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
-        let retrieved_value = chunk_store.get(&Id(index as u64))?;
+        let retrieved_value = chunk_store.get(&Id(index as u64)).await?;
         assert_eq!(*data, retrieved_value.value);
     }
 
@@ -199,7 +229,8 @@ async fn overwrite_value() -> Result<()> {
 
     let root = temp_dir()?;
     let used_space = UsedSpace::new(u64::MAX);
-    let mut chunk_store = ChunkStore::new(root.path(), used_space.clone()).await?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), used_space.clone(), &StoreConfig::default()).await?;
 
     for (data, size) in chunks.data_and_sizes {
         chunk_store
@@ -209,7 +240,7 @@ async fn overwrite_value() -> Result<()> {
             })
             .await?;
         assert_eq!(chunk_store.total_used_space().await, size);
-        let retrieved_data = chunk_store.get(&Id(0))?;
+        let retrieved_data = chunk_store.get(&Id(0)).await?;
         assert_eq!(data, retrieved_data.value);
     }
 
@@ -220,10 +251,11 @@ async fn overwrite_value() -> Result<()> {
 async fn get_fails_when_key_does_not_exist() -> Result<()> {
     let root = temp_dir()?;
     let used_space = UsedSpace::new(u64::MAX);
-    let chunk_store: ChunkStore<Data> = ChunkStore::new(root.path(), used_space.clone()).await?;
+    let chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), used_space.clone(), &StoreConfig::default()).await?;
 
     let id = Id(new_rng().gen());
-    match chunk_store.get(&id) {
+    match chunk_store.get(&id).await {
         Err(Error::NoSuchChunk) => (),
         x => return Err(crate::Error::Logic(format!("Unexpected {:?}", x))),
     }
@@ -238,11 +270,12 @@ async fn keys() -> Result<()> {
 
     let root = temp_dir()?;
     let used_space = UsedSpace::new(u64::MAX);
-    let mut chunk_store = ChunkStore::new(root.path(), used_space.clone()).await?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), used_space.clone(), &StoreConfig::default()).await?;
 
     for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
         let id = Id(index as u64);
-        assert!(!chunk_store.keys().contains(&id));
+        assert!(!chunk_store.keys().await.contains(&id));
         chunk_store
             .put(&Data {
                 id,
@@ -250,7 +283,7 @@ async fn keys() -> Result<()> {
             })
             .await?;
 
-        let keys = chunk_store.keys();
+        let keys = chunk_store.keys().await;
         assert!(keys.contains(&id));
         assert_eq!(keys.len(), index + 1);
     }
@@ -258,13 +291,765 @@ async fn keys() -> Result<()> {
     for (index, _) in chunks.data_and_sizes.iter().enumerate() {
         let id = Id(index as u64);
 
-        assert!(chunk_store.keys().contains(&id));
+        assert!(chunk_store.keys().await.contains(&id));
         chunk_store.delete(&id).await?;
 
-        let keys = chunk_store.keys();
+        let keys = chunk_store.keys().await;
         assert!(!keys.contains(&id));
         assert_eq!(keys.len(), chunks.data_and_sizes.len() - index - 1);
     }
 
     Ok(())
 }
+
+#[tokio::test]
+async fn get_fails_when_blob_content_is_altered() -> Result<()> {
+    let root = temp_dir()?;
+    let used_space = UsedSpace::new(u64::MAX);
+    let mut chunk_store =
+        BlobChunkStore::new(root.path(), used_space.clone(), &StoreConfig::default()).await?;
+
+    let value: Vec<u8> = new_rng().sample_iter(&Standard).take(64).collect();
+    let blob = Blob::Public(PublicBlob::new(value));
+    chunk_store.put(&blob).await?;
+
+    // Flip a bit of the blob value, which is serialised last.
+    let file_path = file_path(&store_dir::<BlobChunkStore>(root.path()), blob.address())?;
+    let mut contents = fs::read(&file_path)?;
+    if let Some(byte) = contents.last_mut() {
+        *byte ^= 1;
+    }
+    fs::write(&file_path, contents)?;
+
+    match chunk_store.get(blob.address()).await {
+        Err(Error::CorruptChunk) => (),
+        x => return Err(crate::Error::Logic(format!("Unexpected {:?}", x))),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn scrub_quarantines_corrupt_chunks() -> Result<()> {
+    let root = temp_dir()?;
+    let used_space = UsedSpace::new(u64::MAX);
+    let mut chunk_store =
+        ChunkStore::new(root.path(), used_space.clone(), &StoreConfig::default()).await?;
+
+    let intact = Data {
+        id: Id(0),
+        value: vec![0; 8],
+    };
+    let corrupt = Data {
+        id: Id(1),
+        value: vec![1; 8],
+    };
+    chunk_store.put(&intact).await?;
+    chunk_store.put(&corrupt).await?;
+
+    // Alter the serialised length of the value, so that the chunk no longer deserialises.
+    let file_path = file_path(&store_dir::<ChunkStore<Data>>(root.path()), &corrupt.id)?;
+    let mut contents = fs::read(&file_path)?;
+    contents[8] ^= 1;
+    fs::write(&file_path, contents)?;
+
+    assert_eq!(chunk_store.scrub(10).await?, vec![corrupt.id]);
+    assert!(chunk_store.has(&intact.id).await);
+    assert!(!chunk_store.has(&corrupt.id).await);
+    let key = bincode::serialize(&corrupt.id).map_err(Error::Bincode)?;
+    assert!(root
+        .path()
+        .join(CHUNK_STORE_DIR)
+        .join(QUARANTINE_DIR)
+        .join("test")
+        .join(hex::encode(key))
+        .is_file());
+    assert_eq!(
+        chunk_store.total_used_space().await,
+        bincode::serialized_size(&intact).map_err(Error::Bincode)?
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn scrub_verifies_chunks_a_batch_at_a_time() -> Result<()> {
+    let root = temp_dir()?;
+    let mut chunk_store = ChunkStore::new(
+        root.path(),
+        UsedSpace::new(u64::MAX),
+        &StoreConfig::default(),
+    )
+    .await?;
+
+    let dir = store_dir::<ChunkStore<Data>>(root.path());
+    for id in 0..5 {
+        let data = Data {
+            id: Id(id),
+            value: vec![id as u8; 8],
+        };
+        chunk_store.put(&data).await?;
+        let file_path = file_path(&dir, &data.id)?;
+        let mut contents = fs::read(&file_path)?;
+        contents[8] ^= 1;
+        fs::write(&file_path, contents)?;
+    }
+
+    // each scrub continues where the previous one stopped
+    let mut corrupt = vec![];
+    for expected in &[2, 2, 1] {
+        let batch = chunk_store.scrub(2).await?;
+        assert_eq!(batch.len(), *expected);
+        corrupt.extend(batch);
+    }
+    corrupt.sort();
+    assert_eq!(corrupt, (0..5).map(Id).collect::<Vec<_>>());
+    assert!(chunk_store.keys().await.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn open_removes_orphaned_temp_files() -> Result<()> {
+    let root = temp_dir()?;
+    let data = Data {
+        id: Id(0),
+        value: vec![0; 8],
+    };
+    {
+        let mut chunk_store = ChunkStore::new(
+            root.path(),
+            UsedSpace::new(u64::MAX),
+            &StoreConfig::default(),
+        )
+        .await?;
+        chunk_store.put(&data).await?;
+    }
+    let temp_file_path = file_path(&store_dir::<ChunkStore<Data>>(root.path()), &Id(1))?
+        .with_extension(TEMP_FILE_EXTENSION);
+
+    // Simulate a crash midway through writing another chunk.
+    fs::create_dir_all(temp_file_path.parent().expect("shard dir"))?;
+    fs::write(&temp_file_path, vec![1; 8])?;
+
+    let chunk_store: ChunkStore<Data> = ChunkStore::new(
+        root.path(),
+        UsedSpace::new(u64::MAX),
+        &StoreConfig::default(),
+    )
+    .await?;
+    assert!(!temp_file_path.exists());
+    assert_eq!(chunk_store.keys().await, vec![data.id]);
+    assert_eq!(
+        chunk_store.total_used_space().await,
+        bincode::serialized_size(&data).map_err(Error::Bincode)?
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn open_corrects_used_space_record() -> Result<()> {
+    let mut rng = new_rng();
+    let chunks = Chunks::gen(&mut rng)?;
+
+    let root = temp_dir()?;
+    {
+        let mut chunk_store = ChunkStore::new(
+            root.path(),
+            UsedSpace::new(u64::MAX),
+            &StoreConfig::default(),
+        )
+        .await?;
+        for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+            chunk_store
+                .put(&Data {
+                    id: Id(index as u64),
+                    value: data.clone(),
+                })
+                .await?;
+        }
+    }
+    let dir = store_dir::<ChunkStore<Data>>(root.path());
+
+    // An unparsable record.
+    fs::write(dir.join("used_space"), vec![1; 3])?;
+    let chunk_store: ChunkStore<Data> = ChunkStore::new(
+        root.path(),
+        UsedSpace::new(u64::MAX),
+        &StoreConfig::default(),
+    )
+    .await?;
+    assert_eq!(chunk_store.total_used_space().await, chunks.total_size);
+    drop(chunk_store);
+
+    // A record disagreeing with the chunks on disk.
+    fs::write(
+        dir.join("used_space"),
+        bincode::serialize(&(chunks.total_size + 1)).map_err(Error::Bincode)?,
+    )?;
+    let chunk_store: ChunkStore<Data> = ChunkStore::new(
+        root.path(),
+        UsedSpace::new(u64::MAX),
+        &StoreConfig::default(),
+    )
+    .await?;
+    assert_eq!(chunk_store.total_used_space().await, chunks.total_size);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn open_migrates_flat_store() -> Result<()> {
+    let mut rng = new_rng();
+    let chunks = Chunks::gen(&mut rng)?;
+
+    // Lay the chunks out flat in the store dir, as format version 0 did.
+    let root = temp_dir()?;
+    let dir = store_dir::<ChunkStore<Data>>(root.path());
+    fs::create_dir_all(&dir)?;
+    for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+        let data = Data {
+            id: Id(index as u64),
+            value: data.clone(),
+        };
+        let file_name = hex::encode(bincode::serialize(&data.id).map_err(Error::Bincode)?);
+        fs::write(
+            dir.join(file_name),
+            bincode::serialize(&data).map_err(Error::Bincode)?,
+        )?;
+    }
+    let temp_file_path = dir.join(format!("{}.{}", "0".repeat(16), TEMP_FILE_EXTENSION));
+    fs::write(&temp_file_path, vec![1; 8])?;
+
+    let chunk_store: ChunkStore<Data> = ChunkStore::new(
+        root.path(),
+        UsedSpace::new(u64::MAX),
+        &StoreConfig::default(),
+    )
+    .await?;
+    assert!(!temp_file_path.exists());
+    assert!(dir.join(FORMAT_VERSION_FILENAME).is_file());
+    assert_eq!(chunk_store.total_used_space().await, chunks.total_size);
+
+    let mut keys = chunk_store.keys().await;
+    keys.sort();
+    assert_eq!(
+        keys,
+        (0..chunks.data_and_sizes.len() as u64)
+            .map(Id)
+            .collect::<Vec<_>>()
+    );
+    for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+        let id = Id(index as u64);
+        assert_ne!(file_path(&dir, &id)?.parent(), Some(dir.as_path()));
+        assert_eq!(chunk_store.get(&id).await?.value, *data);
+    }
+    drop(chunk_store);
+
+    // Reopening an already migrated store leaves it as it is.
+    let chunk_store: ChunkStore<Data> = ChunkStore::new(
+        root.path(),
+        UsedSpace::new(u64::MAX),
+        &StoreConfig::default(),
+    )
+    .await?;
+    assert_eq!(chunk_store.keys().await.len(), chunks.data_and_sizes.len());
+    assert_eq!(chunk_store.total_used_space().await, chunks.total_size);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn backends_behave_identically() -> Result<()> {
+    let mut rng = new_rng();
+    let chunks = Chunks::gen(&mut rng)?;
+
+    for &backend in &[
+        StorageBackend::Filesystem,
+        StorageBackend::Embedded,
+        StorageBackend::Memory,
+    ] {
+        let root = temp_dir()?;
+        let used_space = UsedSpace::new(u64::MAX);
+        let mut chunk_store: ChunkStore<Data> = ChunkStore::new(
+            root.path(),
+            used_space.clone(),
+            &StoreConfig {
+                backend,
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+            chunk_store
+                .put(&Data {
+                    id: Id(index as u64),
+                    value: data.clone(),
+                })
+                .await?;
+        }
+        assert_eq!(used_space.total().await, chunks.total_size);
+
+        let mut keys = chunk_store.keys().await;
+        keys.sort();
+        assert_eq!(
+            keys,
+            (0..chunks.data_and_sizes.len() as u64)
+                .map(Id)
+                .collect::<Vec<_>>()
+        );
+        for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+            assert_eq!(chunk_store.get(&Id(index as u64)).await?.value, *data);
+        }
+
+        // Overwriting a chunk only charges the difference in size.
+        let data = Data {
+            id: Id(0),
+            value: vec![0; 300],
+        };
+        let replaced_size = chunks.data_and_sizes.first().map_or(0, |(_, size)| *size);
+        chunk_store.put(&data).await?;
+        let size = bincode::serialized_size(&data).map_err(Error::Bincode)?;
+        assert_eq!(
+            used_space.total().await,
+            chunks.total_size - replaced_size + size
+        );
+
+        for index in 0..chunks.data_and_sizes.len().max(1) {
+            chunk_store.delete(&Id(index as u64)).await?;
+        }
+        assert!(chunk_store.keys().await.is_empty());
+        assert_eq!(used_space.total().await, 0);
+        match chunk_store.get(&Id(0)).await {
+            Err(Error::NoSuchChunk) => (),
+            x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn compressed_chunks_are_charged_their_stored_size() -> Result<()> {
+    let root = temp_dir()?;
+    let dir = store_dir::<ChunkStore<Data>>(root.path());
+    let used_space = UsedSpace::new(u64::MAX);
+
+    // Chunks written with every codec, each by a store configured with that codec.
+    let mut stored_size = 0;
+    for (index, &compression) in [Compression::None, Compression::Zstd, Compression::Lz4]
+        .iter()
+        .enumerate()
+    {
+        let mut chunk_store: ChunkStore<Data> = ChunkStore::new(
+            root.path(),
+            UsedSpace::new(u64::MAX),
+            &StoreConfig {
+                compression,
+                ..Default::default()
+            },
+        )
+        .await?;
+        let data = Data {
+            id: Id(index as u64),
+            value: vec![index as u8; 1024],
+        };
+        chunk_store.put(&data).await?;
+
+        let size = fs::metadata(file_path(&dir, &data.id)?)?.len();
+        let serialised_size = bincode::serialized_size(&data).map_err(Error::Bincode)?;
+        if compression == Compression::None {
+            assert_eq!(size, serialised_size);
+        } else {
+            assert!(size < serialised_size);
+        }
+        assert_eq!(chunk_store.total_used_space().await, stored_size + size);
+        stored_size += size;
+    }
+
+    // Whatever the configured codec, all chunks read back, and are charged their stored size.
+    let chunk_store: ChunkStore<Data> = ChunkStore::new(
+        root.path(),
+        used_space.clone(),
+        &StoreConfig {
+            compression: Compression::Lz4,
+            ..Default::default()
+        },
+    )
+    .await?;
+    assert_eq!(used_space.total().await, stored_size);
+    for index in 0..3 {
+        assert_eq!(
+            chunk_store.get(&Id(index)).await?.value,
+            vec![index as u8; 1024]
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_serves_reads_until_invalidated() -> Result<()> {
+    let root = temp_dir()?;
+    let data = |id, byte| Data {
+        id: Id(id),
+        value: vec![byte; 64],
+    };
+    let size = bincode::serialized_size(&data(0, 0)).map_err(Error::Bincode)?;
+    let mut chunk_store: ChunkStore<Data> = ChunkStore::new(
+        root.path(),
+        UsedSpace::new(u64::MAX),
+        &StoreConfig {
+            cache_capacity: 2 * size,
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    chunk_store.put(&data(0, 0)).await?;
+    assert_eq!(chunk_store.get(&Id(0)).await?, data(0, 0));
+    assert_eq!(chunk_store.get(&Id(0)).await?, data(0, 0));
+    assert_eq!(
+        chunk_store.cache_stats(),
+        CacheStats {
+            hits: 1,
+            misses: 1,
+            len: 1,
+            size,
+        }
+    );
+
+    // Overwriting a chunk drops the stale copy from the cache.
+    chunk_store.put(&data(0, 1)).await?;
+    assert_eq!(chunk_store.cache_stats().len, 0);
+    assert_eq!(chunk_store.get(&Id(0)).await?, data(0, 1));
+
+    // The least recently used chunk is evicted to stay within the byte budget.
+    chunk_store.put(&data(1, 1)).await?;
+    chunk_store.put(&data(2, 2)).await?;
+    let _ = chunk_store.get(&Id(1)).await?;
+    let _ = chunk_store.get(&Id(0)).await?;
+    let _ = chunk_store.get(&Id(2)).await?;
+    let stats = chunk_store.cache_stats();
+    assert_eq!((stats.len, stats.size), (2, 2 * size));
+    let _ = chunk_store.get(&Id(2)).await?;
+    let _ = chunk_store.get(&Id(0)).await?;
+    assert_eq!(chunk_store.cache_stats().hits, stats.hits + 2);
+
+    // Deleting a chunk drops it from the cache.
+    chunk_store.delete(&Id(0)).await?;
+    match chunk_store.get(&Id(0)).await {
+        Err(Error::NoSuchChunk) => (),
+        x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn encrypted_store_hides_ids_and_content() -> Result<()> {
+    let root = temp_dir()?;
+    let dir = store_dir::<ChunkStore<Data>>(root.path());
+    let cipher = ChunkCipher::new(&[1; 32]);
+    let used_space = UsedSpace::new(u64::MAX);
+    let mut chunk_store: ChunkStore<Data> = ChunkStore::new(
+        root.path(),
+        used_space.clone(),
+        &StoreConfig {
+            cipher: Some(cipher.clone()),
+            ..Default::default()
+        },
+    )
+    .await?;
+    let data = Data {
+        id: Id(7),
+        value: vec![0xab; 256],
+    };
+    chunk_store.put(&data).await?;
+
+    // Neither the id nor the value can be found on disk.
+    assert!(!file_path(&dir, &data.id)?.exists());
+    let key = bincode::serialize(&data.id).map_err(Error::Bincode)?;
+    let sealed_path = backend::file_path(&dir, &cipher.seal_name(&key)?);
+    let contents = fs::read(&sealed_path)?;
+    assert!(!contents
+        .windows(16)
+        .any(|window| window == &data.value[..16]));
+    assert_eq!(used_space.total().await, contents.len() as u64);
+
+    assert_eq!(chunk_store.keys().await, vec![data.id]);
+    assert_eq!(chunk_store.get(&data.id).await?, data);
+
+    // Scrubbing finds the chunk under its sealed name.
+    let mut contents = contents;
+    contents[0] ^= 1;
+    fs::write(&sealed_path, contents)?;
+    assert_eq!(chunk_store.scrub(10).await?, vec![data.id]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn enabling_encryption_encrypts_stored_chunks() -> Result<()> {
+    let root = temp_dir()?;
+    let dir = store_dir::<ChunkStore<Data>>(root.path());
+    let config = |key: Option<[u8; 32]>| StoreConfig {
+        cipher: key.as_ref().map(ChunkCipher::new),
+        ..Default::default()
+    };
+    let data = |id| Data {
+        id: Id(id),
+        value: vec![id as u8; 128],
+    };
+
+    let mut chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), UsedSpace::new(u64::MAX), &config(None)).await?;
+    for id in 0..3 {
+        chunk_store.put(&data(id)).await?;
+    }
+    let plain_size = chunk_store.total_used_space().await;
+    drop(chunk_store);
+
+    // Opening the store with a key encrypts the chunks already in it.
+    let used_space = UsedSpace::new(u64::MAX);
+    let chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), used_space.clone(), &config(Some([1; 32]))).await?;
+    let mut encrypted_size = 0;
+    for id in 0..3 {
+        assert!(!file_path(&dir, &Id(id))?.exists());
+        assert_eq!(chunk_store.get(&Id(id)).await?, data(id));
+    }
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            for shard in fs::read_dir(path)? {
+                for file in fs::read_dir(shard?.path())? {
+                    encrypted_size += file?.metadata()?.len();
+                }
+            }
+        }
+    }
+    assert!(encrypted_size > plain_size);
+    assert_eq!(used_space.total().await, encrypted_size);
+    drop(chunk_store);
+
+    // From then on, the store can only be opened with the same key.
+    match ChunkStore::<Data>::new(root.path(), UsedSpace::new(u64::MAX), &config(None)).await {
+        Err(Error::ChunkEncryptionKeyMissing) => (),
+        x => return Err(Error::Logic(format!("Unexpected: {:?}", x.map(|_| ())))),
+    }
+    match ChunkStore::<Data>::new(
+        root.path(),
+        UsedSpace::new(u64::MAX),
+        &config(Some([2; 32])),
+    )
+    .await
+    {
+        Err(Error::ChunkEncryptionKeyMismatch) => (),
+        x => return Err(Error::Logic(format!("Unexpected: {:?}", x.map(|_| ())))),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn chunks_are_spread_over_data_dirs() -> Result<()> {
+    let root = temp_dir()?;
+    let data = |id| Data {
+        id: Id(id),
+        value: vec![id as u8; 100],
+    };
+    let size = bincode::serialized_size(&data(0)).map_err(Error::Bincode)?;
+    let dirs = vec![
+        DataDir {
+            path: root.path().join("a"),
+            max_capacity: 3 * size,
+        },
+        DataDir {
+            path: root.path().join("b"),
+            max_capacity: 2 * size,
+        },
+    ];
+    let config = StoreConfig {
+        data_dirs: dirs.iter().map(|dir| dir.path.clone()).collect(),
+        ..Default::default()
+    };
+    let used_space = UsedSpace::with_dirs(u64::MAX, &dirs);
+    let mut chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), used_space.clone(), &config).await?;
+
+    // Chunks go to whichever dir has the most free space, until all are full.
+    for id in 0..5 {
+        chunk_store.put(&data(id)).await?;
+    }
+    match chunk_store.put(&data(5)).await {
+        Err(Error::NotEnoughSpace) => (),
+        x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+    }
+    let usage = used_space.dirs().await;
+    assert_eq!(
+        usage.iter().map(|dir| dir.used).collect::<Vec<_>>(),
+        vec![3 * size, 2 * size]
+    );
+    for dir in &dirs {
+        let dir = store_dir::<ChunkStore<Data>>(&dir.path);
+        let count = (0..5)
+            .filter(|&id| matches!(file_path(&dir, &Id(id)), Ok(path) if path.exists()))
+            .count();
+        assert!(count > 0);
+    }
+    assert!(!store_dir::<ChunkStore<Data>>(root.path()).exists());
+
+    // Overwriting a chunk keeps it where it is.
+    chunk_store.put(&data(0)).await?;
+    assert_eq!(used_space.total().await, 5 * size);
+
+    // Reads find chunks in any of the dirs, also once the store is reopened.
+    drop(chunk_store);
+    let used_space = UsedSpace::with_dirs(u64::MAX, &dirs);
+    let mut chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), used_space.clone(), &config).await?;
+    assert_eq!(used_space.total().await, 5 * size);
+    let mut keys = chunk_store.keys().await;
+    keys.sort();
+    assert_eq!(keys, (0..5).map(Id).collect::<Vec<_>>());
+    for id in 0..5 {
+        assert_eq!(chunk_store.get(&Id(id)).await?, data(id));
+    }
+
+    // Deleting from a full dir frees space in it.
+    for id in 0..5 {
+        chunk_store.delete(&Id(id)).await?;
+    }
+    assert!(used_space.dirs().await.iter().all(|dir| dir.used == 0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn puts_fail_once_quota_is_used_up() -> Result<()> {
+    let root = temp_dir()?;
+    let blob = |value: &[u8]| Blob::Public(PublicBlob::new(value.to_vec()));
+    let size = bincode::serialized_size(&blob(&[0; 64])).map_err(Error::Bincode)?;
+    let used_space = UsedSpace::new(u64::MAX);
+    used_space.set_quota(StoreKind::Blob, 2 * size).await;
+    let mut chunk_store =
+        BlobChunkStore::new(root.path(), used_space.clone(), &StoreConfig::default()).await?;
+
+    chunk_store.put(&blob(&[0; 64])).await?;
+    chunk_store.put(&blob(&[1; 64])).await?;
+    match chunk_store.put(&blob(&[2; 64])).await {
+        Err(Error::NotEnoughSpaceForBlobs) => (),
+        x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+    }
+    assert_eq!(used_space.total().await, 2 * size);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn archive_moves_chunks_to_new_root() -> Result<()> {
+    let old_root = temp_dir()?;
+    let new_root = temp_dir()?;
+    let archive = old_root.path().join("chunks.archive");
+    let config = |root: &TempDir| Config {
+        root_dir: Some(root.path().to_path_buf()),
+        ..Default::default()
+    };
+    let blobs: Vec<_> = (0..10)
+        .map(|byte| Blob::Public(PublicBlob::new(vec![byte; 100])))
+        .collect();
+    let mut chunk_store = BlobChunkStore::new(
+        old_root.path(),
+        UsedSpace::new(u64::MAX),
+        &StoreConfig::default(),
+    )
+    .await?;
+    for blob in &blobs {
+        chunk_store.put(blob).await?;
+    }
+    drop(chunk_store);
+    let owner = Keypair::new_ed25519(&mut rand::thread_rng()).public_key();
+    let map = Map::from(UnseqMap::new(rand::random(), 10, owner));
+    let mut map_versions = MapHistoryChunkStore::new(
+        old_root.path(),
+        UsedSpace::new(u64::MAX),
+        &StoreConfig::default(),
+    )
+    .await?;
+    map_versions.put(&MapSnapshot::new(map.clone())).await?;
+    drop(map_versions);
+
+    let exported = export_chunks(&config(&old_root), &archive).await?;
+    assert_eq!(
+        (
+            exported.blobs,
+            exported.maps,
+            exported.map_versions,
+            exported.sequences
+        ),
+        (10, 0, 1, 0)
+    );
+    let imported = import_chunks(&config(&new_root), &archive).await?;
+    assert_eq!(imported, exported);
+
+    let used_space = UsedSpace::new(u64::MAX);
+    let chunk_store =
+        BlobChunkStore::new(new_root.path(), used_space.clone(), &StoreConfig::default()).await?;
+    for blob in &blobs {
+        assert_eq!(chunk_store.get(blob.address()).await?, *blob);
+    }
+    let map_versions =
+        MapHistoryChunkStore::new(new_root.path(), used_space.clone(), &StoreConfig::default())
+            .await?;
+    let version = MapVersion {
+        address: *map.address(),
+        version: map.version(),
+    };
+    assert_eq!(map_versions.get(&version).await?.into_map(), map);
+    assert_eq!(used_space.total().await, exported.bytes);
+
+    // Chunks are only imported into a node holding none yet.
+    match import_chunks(&config(&new_root), &archive).await {
+        Err(Error::DataExists) => (),
+        x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+    }
+
+    // An archive cut short fails to import after its chunks were stored, which are then removed
+    // again, so that the import can be retried.
+    let contents = fs::read(&archive)?;
+    let truncated = old_root.path().join("truncated.archive");
+    fs::write(&truncated, &contents[..contents.len() - 16])?;
+    let retry_root = temp_dir()?;
+    assert!(import_chunks(&config(&retry_root), &truncated)
+        .await
+        .is_err());
+    let used_space = UsedSpace::new(u64::MAX);
+    let chunk_store = BlobChunkStore::new(
+        retry_root.path(),
+        used_space.clone(),
+        &StoreConfig::default(),
+    )
+    .await?;
+    assert!(chunk_store.keys().await.is_empty());
+    assert_eq!(used_space.total().await, 0);
+    drop(chunk_store);
+    assert_eq!(
+        import_chunks(&config(&retry_root), &archive).await?,
+        exported
+    );
+
+    // Flip a byte within the first chunk, which starts after the header (13 bytes), the record
+    // length (8), the record and chunk kind tags (4 each) and the chunk length (8).
+    let mut contents = contents;
+    contents[40] ^= 1;
+    fs::write(&archive, contents)?;
+    match import_chunks(&config(&temp_dir()?), &archive).await {
+        Err(Error::InvalidArchive(_)) => (),
+        x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+    }
+
+    Ok(())
+}
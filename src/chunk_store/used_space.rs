@@ -6,9 +6,13 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{Error, Result};
+use crate::{Config, DataDir, Error, Result, StoreKind};
 use log::warn;
-use std::{path::Path, sync::Arc};
+use std::{
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tokio::{io::AsyncSeekExt, sync::Mutex};
 
 const USED_SPACE_FILENAME: &str = "used_space";
@@ -24,6 +28,29 @@ pub struct UsedSpace {
 /// used space tracking
 pub type StoreId = u64;
 
+/// A snapshot of the space used within one data dir.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirUsage {
+    /// The data dir.
+    pub path: PathBuf,
+    /// Space used by the chunks of all `ChunkStore`s in the dir.
+    pub used: u64,
+    /// The maximum value `used` can attain.
+    pub max_capacity: u64,
+}
+
+impl Display for DirUsage {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}: {}/{} bytes",
+            self.path.display(),
+            self.used,
+            self.max_capacity
+        )
+    }
+}
+
 impl UsedSpace {
     /// construct a new used space instance
     /// NOTE: this constructs a new async-safe instance,
@@ -31,8 +58,29 @@ impl UsedSpace {
     /// then use `clone()` and `add_local_store()` to ensure
     /// consistency across local `ChunkStore`s
     pub fn new(max_capacity: u64) -> Self {
+        Self::with_dirs(max_capacity, &[])
+    }
+
+    /// construct a new used space instance, additionally limiting
+    /// the space used within each of `dirs`.  Stores are counted
+    /// against the data dir they're located in.
+    pub fn with_dirs(max_capacity: u64, dirs: &[DataDir]) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(inner::UsedSpace::new(max_capacity))),
+            inner: Arc::new(Mutex::new(inner::UsedSpace::new(max_capacity, dirs))),
+        }
+    }
+
+    /// construct a new used space instance with the capacity, data dirs
+    /// and quotas set in `config`
+    pub fn from_config(config: &Config) -> Self {
+        let mut inner = inner::UsedSpace::new(config.max_capacity(), config.data_dirs());
+        for &kind in &[StoreKind::Blob, StoreKind::Map, StoreKind::Sequence] {
+            if let Some(quota) = config.quota(kind) {
+                inner.set_quota(kind, quota);
+            }
+        }
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
         }
     }
 
@@ -65,10 +113,42 @@ impl UsedSpace {
         inner::UsedSpace::local(self.inner.clone(), id).await
     }
 
+    /// Returns the space that can still be used by a local store, within
+    /// both the overall maximum capacity and that of its data dir.
+    pub async fn headroom(&self, id: StoreId) -> u64 {
+        inner::UsedSpace::headroom(self.inner.clone(), id).await
+    }
+
+    /// Returns the used space of each data dir as a snapshot
+    pub async fn dirs(&self) -> Vec<DirUsage> {
+        inner::UsedSpace::dirs(self.inner.clone()).await
+    }
+
+    /// Limits the space all local stores of `kind` may use together,
+    /// within the overall maximum capacity
+    pub async fn set_quota(&self, kind: StoreKind, quota: u64) {
+        self.inner.lock().await.set_quota(kind, quota)
+    }
+
     /// Add an object and file store to track used space of a single
-    /// `ChunkStore`
-    pub async fn add_local_store<T: AsRef<Path>>(&self, dir: T) -> Result<StoreId> {
-        inner::UsedSpace::add_local_store(self.inner.clone(), dir).await
+    /// `ChunkStore`, where `stored` is the space its chunks are found to
+    /// use. The on-disk record in `record_dir` is corrected if it disagrees.
+    /// A store with no `record_dir` is tracked in memory only.
+    /// The store counts against the quota of its `kind`, if any.
+    pub async fn add_local_store(
+        &self,
+        kind: Option<StoreKind>,
+        record_dir: Option<&Path>,
+        stored: u64,
+    ) -> Result<StoreId> {
+        inner::UsedSpace::add_local_store(self.inner.clone(), kind, record_dir, stored).await
+    }
+
+    /// Stop tracking a single `ChunkStore` which is no longer used. The space
+    /// its chunks use is no longer counted, so that it isn't counted twice when
+    /// the store is opened again. Its on-disk record is left as is.
+    pub async fn remove_local_store(&self, id: StoreId) -> Result<()> {
+        inner::UsedSpace::remove_local_store(self.inner.clone(), id).await
     }
 
     /// Increase the used amount of a single chunk store and the global used value
@@ -86,6 +166,15 @@ mod inner {
 
     use super::*;
     use std::{collections::HashMap, io::SeekFrom};
+
+    // The error for a write that would exceed the quota for `kind`.
+    fn quota_exceeded(kind: StoreKind) -> Error {
+        match kind {
+            StoreKind::Blob => Error::NotEnoughSpaceForBlobs,
+            StoreKind::Map => Error::NotEnoughSpaceForMaps,
+            StoreKind::Sequence => Error::NotEnoughSpaceForSequences,
+        }
+    }
     use tokio::{
         fs::{File, OpenOptions},
         io::{AsyncReadExt, AsyncWriteExt},
@@ -103,6 +192,10 @@ mod inner {
         local_stores: HashMap<StoreId, LocalUsedSpace>,
         /// next local `ChunkStore` id to use
         next_id: StoreId,
+        /// the used space tracking for each data dir
+        dirs: Vec<DirUsage>,
+        /// the maximum space the stores of each kind may use together
+        quotas: HashMap<StoreKind, u64>,
     }
 
     /// An entry used to track the used space of a single `ChunkStore`
@@ -110,20 +203,33 @@ mod inner {
     struct LocalUsedSpace {
         // Space consumed by this one `ChunkStore`.
         pub local_value: u64,
-        // File used to maintain on-disk record of `local_value`.
+        // File used to maintain on-disk record of `local_value`, if the store is persistent.
         // TODO: maybe a good idea to maintain a journal that is only flushed occasionally
         // to ensure stale entries aren't recorded, and to avoid holding the lock for the
         // whole inner::UsedSpace struct during the entirety of the file write.
-        pub local_record: File,
+        pub local_record: Option<File>,
+        // Index of the data dir this `ChunkStore` is located in, if any.
+        pub dir: Option<usize>,
+        // The kind of data held by this `ChunkStore`, if subject to quotas.
+        pub kind: Option<StoreKind>,
     }
 
     impl UsedSpace {
-        pub fn new(max_capacity: u64) -> Self {
+        pub fn new(max_capacity: u64, dirs: &[DataDir]) -> Self {
             Self {
                 max_capacity,
                 total_value: 0u64,
                 local_stores: HashMap::new(),
                 next_id: 0u64,
+                dirs: dirs
+                    .iter()
+                    .map(|dir| DirUsage {
+                        path: dir.path.clone(),
+                        used: 0,
+                        max_capacity: dir.max_capacity,
+                    })
+                    .collect(),
+                quotas: HashMap::new(),
             }
         }
 
@@ -133,12 +239,15 @@ mod inner {
         pub async fn reset(used_space: Arc<Mutex<UsedSpace>>) {
             let mut used_space_lock = used_space.lock().await;
             used_space_lock.total_value = 0;
+            for dir in used_space_lock.dirs.iter_mut() {
+                dir.used = 0;
+            }
             for (_id, local_used_space) in used_space_lock.local_stores.iter_mut() {
                 local_used_space.local_value = 0;
-                if let Err(err) =
-                    Self::write_local_to_file(&mut local_used_space.local_record, 0).await
-                {
-                    warn!("Error updating used_space file on disk: {}", err);
+                if let Some(record) = local_used_space.local_record.as_mut() {
+                    if let Err(err) = Self::write_local_to_file(record, 0).await {
+                        warn!("Error updating used_space file on disk: {}", err);
+                    }
                 }
             }
         }
@@ -171,45 +280,105 @@ mod inner {
                 .map_or(0, |res| res.local_value)
         }
 
+        /// Returns the space that can still be used by a local store
+        pub async fn headroom(used_space: Arc<Mutex<UsedSpace>>, id: StoreId) -> u64 {
+            let used_space_lock = used_space.lock().await;
+            let headroom = used_space_lock
+                .max_capacity
+                .saturating_sub(used_space_lock.total_value);
+            match used_space_lock
+                .local_stores
+                .get(&id)
+                .and_then(|store| store.dir)
+            {
+                Some(dir) => {
+                    let dir = &used_space_lock.dirs[dir];
+                    headroom.min(dir.max_capacity.saturating_sub(dir.used))
+                }
+                None => headroom,
+            }
+        }
+
+        /// Returns the used space of each data dir as a snapshot
+        pub async fn dirs(used_space: Arc<Mutex<UsedSpace>>) -> Vec<DirUsage> {
+            let used_space_lock = used_space.lock().await;
+            used_space_lock.dirs.clone()
+        }
+
+        /// Sets the quota of the stores of `kind`
+        pub fn set_quota(&mut self, kind: StoreKind, quota: u64) {
+            let _ = self.quotas.insert(kind, quota);
+        }
+
+        // Space used by all stores of `kind`
+        fn kind_total(&self, kind: StoreKind) -> u64 {
+            self.local_stores
+                .values()
+                .filter(|store| store.kind == Some(kind))
+                .fold(0, |sum, store| sum.saturating_add(store.local_value))
+        }
+
         /// Adds a new record for tracking the actions
         /// of a local chunk store as part of the global
-        /// used amount tracking
-        pub async fn add_local_store<T: AsRef<Path>>(
+        /// used amount tracking.
+        /// The record is rewritten with `stored` if it is
+        /// missing, unparsable or disagrees with it.
+        pub async fn add_local_store(
             used_space: Arc<Mutex<UsedSpace>>,
-            dir: T,
+            kind: Option<StoreKind>,
+            record_dir: Option<&Path>,
+            stored: u64,
         ) -> Result<StoreId> {
-            let mut local_record = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(dir.as_ref().join(USED_SPACE_FILENAME))
-                .await?;
-
-            // try read
-            let mut buffer = vec![];
-            let could_read = local_record.read_to_end(&mut buffer).await.is_ok();
-            let has_value = !buffer.is_empty();
-            let local_value = if could_read && has_value {
-                // TODO - if this can't be parsed, we should consider emptying `dir` of any chunks.
-                bincode::deserialize::<u64>(&buffer)?
-            } else {
-                let mut bytes = Vec::<u8>::new();
-                bincode::serialize_into(&mut bytes, &0_u64)?;
-                local_record.write_all(&bytes).await?;
-                0
+            let local_record = match record_dir {
+                Some(dir) => Some(Self::open_local_record(dir, stored).await?),
+                None => None,
             };
 
+            let mut used_space_lock = used_space.lock().await;
+            let dir = record_dir.and_then(|record_dir| {
+                used_space_lock
+                    .dirs
+                    .iter()
+                    .position(|dir| record_dir.starts_with(&dir.path))
+            });
+            if let Some(dir) = dir {
+                let dir = &mut used_space_lock.dirs[dir];
+                dir.used = dir.used.saturating_add(stored);
+            }
             let local_store = LocalUsedSpace {
-                local_value,
+                local_value: stored,
                 local_record,
+                dir,
+                kind,
             };
-            let mut used_space_lock = used_space.lock().await;
             let id = used_space_lock.next_id;
             used_space_lock.next_id += 1;
+            used_space_lock.total_value = used_space_lock.total_value.saturating_add(stored);
             let _ = used_space_lock.local_stores.insert(id, local_store);
             Ok(id)
         }
 
+        /// Removes the record of a local chunk store, releasing
+        /// the space it used globally and in its data dir
+        pub async fn remove_local_store(
+            used_space: Arc<Mutex<UsedSpace>>,
+            id: StoreId,
+        ) -> Result<()> {
+            let mut used_space_lock = used_space.lock().await;
+            let local_store = used_space_lock
+                .local_stores
+                .remove(&id)
+                .ok_or(Error::NoStoreId)?;
+            used_space_lock.total_value = used_space_lock
+                .total_value
+                .saturating_sub(local_store.local_value);
+            if let Some(dir) = local_store.dir {
+                let dir = &mut used_space_lock.dirs[dir];
+                dir.used = dir.used.saturating_sub(local_store.local_value);
+            }
+            Ok(())
+        }
+
         /// Asynchronous implementation to increase used space in a local store
         /// and globally at the same time
         pub async fn increase(
@@ -225,13 +394,40 @@ mod inner {
             if new_total > used_space_lock.max_capacity {
                 return Err(Error::NotEnoughSpace);
             }
-            let new_local = used_space_lock
+            let local_store = used_space_lock
                 .local_stores
                 .get(&id)
-                .ok_or(Error::NoStoreId)?
+                .ok_or(Error::NoStoreId)?;
+            let new_local = local_store
                 .local_value
                 .checked_add(consumed)
                 .ok_or(Error::NotEnoughSpace)?;
+            let dir = local_store.dir;
+            let new_dir_used = match dir {
+                Some(dir) => {
+                    let dir = &used_space_lock.dirs[dir];
+                    let new_dir_used = dir
+                        .used
+                        .checked_add(consumed)
+                        .ok_or(Error::NotEnoughSpace)?;
+                    if new_dir_used > dir.max_capacity {
+                        return Err(Error::NotEnoughSpace);
+                    }
+                    new_dir_used
+                }
+                None => 0,
+            };
+            if let Some(kind) = local_store.kind {
+                if let Some(&quota) = used_space_lock.quotas.get(&kind) {
+                    let new_kind_total = used_space_lock
+                        .kind_total(kind)
+                        .checked_add(consumed)
+                        .ok_or_else(|| quota_exceeded(kind))?;
+                    if new_kind_total > quota {
+                        return Err(quota_exceeded(kind));
+                    }
+                }
+            }
 
             {
                 let record = &mut used_space_lock
@@ -239,9 +435,14 @@ mod inner {
                     .get_mut(&id)
                     .ok_or(Error::NoStoreId)?
                     .local_record;
-                Self::write_local_to_file(record, new_local).await?;
+                if let Some(record) = record.as_mut() {
+                    Self::write_local_to_file(record, new_local).await?;
+                }
             }
             used_space_lock.total_value = new_total;
+            if let Some(dir) = dir {
+                used_space_lock.dirs[dir].used = new_dir_used;
+            }
             used_space_lock
                 .local_stores
                 .get_mut(&id)
@@ -259,12 +460,12 @@ mod inner {
             released: u64,
         ) -> Result<()> {
             let mut used_space_lock = used_space.lock().await;
-            let new_local = used_space_lock
+            let local_store = used_space_lock
                 .local_stores
-                .get_mut(&id)
-                .ok_or(Error::NoStoreId)?
-                .local_value
-                .saturating_sub(released);
+                .get(&id)
+                .ok_or(Error::NoStoreId)?;
+            let new_local = local_store.local_value.saturating_sub(released);
+            let dir = local_store.dir;
             let new_total = used_space_lock.total_value.saturating_sub(released);
             {
                 let record = &mut used_space_lock
@@ -272,9 +473,15 @@ mod inner {
                     .get_mut(&id)
                     .ok_or(Error::NoStoreId)?
                     .local_record;
-                Self::write_local_to_file(record, new_local).await?;
+                if let Some(record) = record.as_mut() {
+                    Self::write_local_to_file(record, new_local).await?;
+                }
             }
             used_space_lock.total_value = new_total;
+            if let Some(dir) = dir {
+                let dir = &mut used_space_lock.dirs[dir];
+                dir.used = dir.used.saturating_sub(released);
+            }
             used_space_lock
                 .local_stores
                 .get_mut(&id)
@@ -283,6 +490,36 @@ mod inner {
             Ok(())
         }
 
+        /// helper to open the on-disk record in `dir`, correcting it to `stored`
+        async fn open_local_record(dir: &Path, stored: u64) -> Result<File> {
+            let record_path = dir.join(USED_SPACE_FILENAME);
+            let mut local_record = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .open(&record_path)
+                .await?;
+
+            // try read
+            let mut buffer = vec![];
+            let recorded = match local_record.read_to_end(&mut buffer).await {
+                Ok(_) => bincode::deserialize::<u64>(&buffer).ok(),
+                Err(_) => None,
+            };
+            if recorded != Some(stored) {
+                if !buffer.is_empty() {
+                    warn!(
+                        "Correcting used space record at {}: recorded {:?}, found {}",
+                        record_path.display(),
+                        recorded,
+                        stored
+                    );
+                }
+                Self::write_local_to_file(&mut local_record, stored).await?;
+            }
+            Ok(local_record)
+        }
+
         /// helper to write the contents of local to file
         /// NOTE: For now, ou should hold the lock on the inner while doing this
         /// It's slow, but maintains behaviour from the previous implementation
@@ -303,6 +540,7 @@ mod inner {
 #[cfg(test)]
 mod tests {
     use super::{Error, Result, UsedSpace};
+    use crate::{DataDir, StoreKind};
     use tempdir::TempDir;
 
     const TEST_STORE_MAX_SIZE: u64 = u64::MAX;
@@ -329,7 +567,9 @@ mod tests {
         let root_dir = create_temp_root()?;
         let store_dir = create_temp_store(&root_dir)?;
         let used_space = UsedSpace::new(TEST_STORE_MAX_SIZE);
-        let id = used_space.add_local_store(&store_dir).await?;
+        let id = used_space
+            .add_local_store(None, Some(store_dir.path()), 0)
+            .await?;
         // get a random vec of u64 by adding u32 (avoid overflow)
         let mut rng = rand::thread_rng();
         let bytes = crate::utils::random_vec(&mut rng, std::mem::size_of::<u32>() * NUMS_TO_ADD);
@@ -365,4 +605,124 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn used_space_per_dir_test() -> Result<()> {
+        let root_dir = create_temp_root()?;
+        let dirs = [
+            DataDir {
+                path: root_dir.path().join("a"),
+                max_capacity: 100,
+            },
+            DataDir {
+                path: root_dir.path().join("b"),
+                max_capacity: 50,
+            },
+        ];
+        for dir in &dirs {
+            std::fs::create_dir_all(dir.path.join("store"))?;
+        }
+        let used_space = UsedSpace::with_dirs(120, &dirs);
+        let a = used_space
+            .add_local_store(None, Some(&dirs[0].path.join("store")), 10)
+            .await?;
+        let b = used_space
+            .add_local_store(None, Some(&dirs[1].path.join("store")), 0)
+            .await?;
+        assert_eq!(used_space.headroom(a).await, 90);
+        assert_eq!(used_space.headroom(b).await, 50);
+
+        // each dir is limited to its own capacity
+        used_space.increase(b, 50).await?;
+        match used_space.increase(b, 1).await {
+            Err(Error::NotEnoughSpace) => (),
+            x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+        }
+
+        // and all of them to the overall capacity
+        assert_eq!(used_space.headroom(a).await, 60);
+        match used_space.increase(a, 61).await {
+            Err(Error::NotEnoughSpace) => (),
+            x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+        }
+        used_space.increase(a, 60).await?;
+
+        used_space.decrease(b, 20).await?;
+        let usage = used_space.dirs().await;
+        assert_eq!(
+            usage.iter().map(|dir| dir.used).collect::<Vec<_>>(),
+            vec![70, 30]
+        );
+        assert_eq!(used_space.total().await, 100);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn used_space_quota_test() -> Result<()> {
+        let used_space = UsedSpace::new(100);
+        used_space.set_quota(StoreKind::Map, 30).await;
+        let maps = used_space
+            .add_local_store(Some(StoreKind::Map), None, 10)
+            .await?;
+        let more_maps = used_space
+            .add_local_store(Some(StoreKind::Map), None, 0)
+            .await?;
+        let sequences = used_space
+            .add_local_store(Some(StoreKind::Sequence), None, 0)
+            .await?;
+
+        // the quota is shared by all stores of a kind
+        used_space.increase(more_maps, 20).await?;
+        match used_space.increase(maps, 1).await {
+            Err(Error::NotEnoughSpaceForMaps) => (),
+            x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+        }
+
+        // other kinds are only limited by the overall capacity
+        used_space.increase(sequences, 70).await?;
+        match used_space.increase(sequences, 1).await {
+            Err(Error::NotEnoughSpace) => (),
+            x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+        }
+
+        used_space.decrease(maps, 10).await?;
+        used_space.decrease(sequences, 10).await?;
+        used_space.increase(maps, 10).await?;
+        assert_eq!(used_space.total().await, 90);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn used_space_remove_local_store_test() -> Result<()> {
+        let root_dir = create_temp_root()?;
+        let dirs = [DataDir {
+            path: root_dir.path().join("a"),
+            max_capacity: 100,
+        }];
+        std::fs::create_dir_all(dirs[0].path.join("store"))?;
+        let used_space = UsedSpace::with_dirs(100, &dirs);
+        let store = used_space
+            .add_local_store(None, Some(&dirs[0].path.join("store")), 10)
+            .await?;
+        used_space.increase(store, 20).await?;
+
+        // the space is released, and a reopened store counts it only once
+        used_space.remove_local_store(store).await?;
+        assert_eq!(used_space.total().await, 0);
+        assert_eq!(used_space.dirs().await[0].used, 0);
+        let reopened = used_space
+            .add_local_store(None, Some(&dirs[0].path.join("store")), 30)
+            .await?;
+        assert_eq!(used_space.total().await, 30);
+        assert_eq!(used_space.headroom(reopened).await, 70);
+
+        match used_space.increase(store, 1).await {
+            Err(Error::NoStoreId) => (),
+            x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+        }
+
+        Ok(())
+    }
 }
@@ -7,18 +7,20 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{
-    chunk_store::{BlobChunkStore, UsedSpace},
+    chunk_store::{BlobChunkStore, StoreConfig, UsedSpace},
     error::convert_to_error_message,
-    node_ops::{NodeDuty, OutgoingMsg},
+    node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
     section_funds::elder_signing,
+    utils::verify_blob_deletion,
     Error, NodeInfo, Result,
 };
-use log::{error, info};
-use sn_data_types::{Blob, BlobAddress};
+use log::{error, info, warn};
+use sn_data_types::{Blob, BlobAddress, Signature};
 use sn_messaging::{
     client::{
-        CmdError, Error as ErrorMessage, Message, NodeDataQueryResponse, NodeQuery,
-        NodeQueryResponse, NodeSystemQuery, QueryResponse,
+        CmdError, Error as ErrorMessage, Message, NodeCmd, NodeDataQueryResponse, NodeEvent,
+        NodeQuery, NodeQueryResponse, NodeSystemCmd, NodeSystemQuery, NodeSystemQueryResponse,
+        QueryResponse,
     },
     Aggregation, DstLocation, EndUser, MessageId, SrcLocation,
 };
@@ -30,6 +32,10 @@ use std::{
 };
 use xor_name::XorName;
 
+// How many chunks a scrub verifies, so that a large store is verified a little at a time rather
+// than holding up the node while all of it is read.
+const SCRUB_BATCH_SIZE: usize = 64;
+
 /// Storage of data chunks.
 pub(crate) struct ChunkStorage {
     node_name: XorName,
@@ -41,8 +47,9 @@ impl ChunkStorage {
         node_name: XorName,
         path: &Path,
         used_space: UsedSpace,
+        config: &StoreConfig,
     ) -> Result<Self> {
-        let chunks = BlobChunkStore::new(path, used_space).await?;
+        let chunks = BlobChunkStore::new(path, used_space, config).await?;
         Ok(Self { chunks, node_name })
     }
 
@@ -52,8 +59,27 @@ impl ChunkStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        if let Err(error) = self.try_store(data, origin).await {
-            Ok(NodeDuty::Send(OutgoingMsg {
+        match self.try_store(data, origin).await {
+            Ok(()) => Ok(NodeDuty::NoOp),
+            // Our section picks another holder for the chunk.
+            Err(Error::NotEnoughSpace) | Err(Error::NotEnoughSpaceForBlobs) => {
+                warn!("{}: Not enough space to store {:?}", self, data.address());
+                Ok(NodeDuty::Send(OutgoingMsg {
+                    msg: Message::NodeCmd {
+                        cmd: NodeCmd::System(NodeSystemCmd::NoSpaceForChunk {
+                            blob: data.clone(),
+                            origin,
+                            msg_id,
+                        }),
+                        id: MessageId::new(),
+                        target_section_pk: None,
+                    },
+                    section_source: false, // sent as single node
+                    dst: DstLocation::Section(*data.name()),
+                    aggregation: Aggregation::None,
+                }))
+            }
+            Err(error) => Ok(NodeDuty::Send(OutgoingMsg {
                 msg: Message::CmdError {
                     error: CmdError::Data(convert_to_error_message(error)?),
                     id: MessageId::in_response_to(&msg_id),
@@ -63,9 +89,7 @@ impl ChunkStorage {
                 section_source: false, // sent as single node
                 dst: DstLocation::EndUser(origin),
                 aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
-            }))
-        } else {
-            Ok(NodeDuty::NoOp)
+            })),
         }
     }
 
@@ -84,7 +108,7 @@ impl ChunkStorage {
             }
         }
 
-        if self.chunks.has(data.address()) {
+        if self.chunks.has(data.address()).await {
             info!(
                 "{}: Immutable chunk already exists, not storing: {:?}",
                 self,
@@ -95,17 +119,59 @@ impl ChunkStorage {
         self.chunks.put(&data).await
     }
 
+    /// Sends the chunk at `address` to the client. When an elder asked for it on the client's
+    /// behalf, the elder is told whether the chunk was sent, and a chunk we can't read is left
+    /// for the elder to ask another holder for.
     pub(crate) async fn get(
         &self,
         address: &BlobAddress,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        let result = self
-            .chunks
-            .get(address)
-            .map_err(|_| ErrorMessage::NoSuchData);
-        Ok(NodeDuty::Send(OutgoingMsg {
+        elder: Option<XorName>,
+    ) -> Result<NodeDuties> {
+        let result = self.chunks.get(address).await.map_err(|error| {
+            warn!("{}: Could not read {:?}: {:?}", self, address, error);
+            ErrorMessage::NoSuchData
+        });
+        let elder = match elder {
+            Some(elder) => elder,
+            None => return Ok(vec![Self::read_response(result, msg_id, origin)]),
+        };
+        match result {
+            Ok(blob) => Ok(vec![
+                Self::read_response(Ok(blob), msg_id, origin),
+                NodeDuty::Send(OutgoingMsg {
+                    msg: Message::NodeEvent {
+                        event: NodeEvent::ChunkServed { chunk: *address },
+                        id: MessageId::new(),
+                        correlation_id: msg_id,
+                        target_section_pk: None,
+                    },
+                    section_source: false, // sent as single node
+                    dst: DstLocation::Node(elder),
+                    aggregation: Aggregation::None,
+                }),
+            ]),
+            Err(error) => Ok(vec![NodeDuty::Send(OutgoingMsg {
+                msg: Message::NodeQueryResponse {
+                    response: NodeQueryResponse::Data(NodeDataQueryResponse::GetChunk(Err(error))),
+                    id: MessageId::in_response_to(&msg_id),
+                    correlation_id: msg_id,
+                    target_section_pk: None,
+                },
+                section_source: false, // sent as single node
+                dst: DstLocation::Node(elder),
+                aggregation: Aggregation::None,
+            })]),
+        }
+    }
+
+    fn read_response(
+        result: std::result::Result<Blob, ErrorMessage>,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> NodeDuty {
+        NodeDuty::Send(OutgoingMsg {
             msg: Message::QueryResponse {
                 id: MessageId::in_response_to(&msg_id),
                 response: QueryResponse::GetBlob(result),
@@ -115,7 +181,7 @@ impl ChunkStorage {
             section_source: false, // sent as single node
             dst: DstLocation::EndUser(origin),
             aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
-        }))
+        })
     }
 
     pub async fn replicate_chunk(
@@ -148,13 +214,8 @@ impl ChunkStorage {
         msg_id: MessageId,
         new_holder: XorName,
     ) -> Result<NodeDuty> {
-        let result = match self.chunks.get(&address) {
-            Ok(res) => Ok(res),
-            Err(error) => Err(convert_to_error_message(error)?),
-        };
-
-        if let Ok(data) = result {
-            Ok(NodeDuty::Send(OutgoingMsg {
+        match self.chunks.get(&address).await {
+            Ok(data) => Ok(NodeDuty::Send(OutgoingMsg {
                 msg: Message::NodeQueryResponse {
                     response: NodeQueryResponse::Data(NodeDataQueryResponse::GetChunk(Ok(data))),
                     id: MessageId::in_response_to(&msg_id),
@@ -164,16 +225,59 @@ impl ChunkStorage {
                 section_source: false, // sent as single node
                 dst: DstLocation::Node(new_holder),
                 aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
-            }))
-        } else {
-            log::warn!("Could not read chunk for replication: {:?}", result);
-            Ok(NodeDuty::NoOp)
+            })),
+            Err(error) => {
+                warn!(
+                    "{}: Could not read chunk for replication: {:?}",
+                    self, error
+                );
+                Ok(NodeDuty::NoOp)
+            }
         }
     }
 
+    /// Answers a storage challenge with a hash over the nonce and the chunk's content, or with
+    /// no proof if we don't have the chunk.
+    pub async fn prove_storage(
+        &self,
+        address: BlobAddress,
+        nonce: [u8; 32],
+        msg_id: MessageId,
+        origin: SrcLocation,
+    ) -> Result<NodeDuty> {
+        let proof = match self.chunks.get(&address).await {
+            Ok(blob) => Some(XorName::from_content(&[
+                &nonce[..],
+                blob.value().as_slice(),
+            ])),
+            Err(error) => {
+                warn!(
+                    "{}: Can't prove storage of {:?}: {:?}",
+                    self, address, error
+                );
+                None
+            }
+        };
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::NodeQueryResponse {
+                response: NodeQueryResponse::System(NodeSystemQueryResponse::StorageProof {
+                    address,
+                    nonce,
+                    proof,
+                }),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // sent as single node
+            dst: origin.to_dst(),
+            aggregation: Aggregation::None,
+        }))
+    }
+
     ///
     pub async fn store_for_replication(&mut self, blob: Blob) -> Result<NodeDuty> {
-        if self.chunks.has(blob.address()) {
+        if self.chunks.has(blob.address()).await {
             info!(
                 "{}: Immutable chunk already exists, not storing: {:?}",
                 self,
@@ -187,30 +291,80 @@ impl ChunkStorage {
         Ok(NodeDuty::NoOp)
     }
 
+    /// Verifies the next batch of stored chunks. Corrupt chunks are quarantined, and our
+    /// section is asked to restore our copy of them from the other holders.
+    pub async fn scrub(&mut self) -> Result<NodeDuties> {
+        info!("{}: Read cache: {}", self, self.chunks.cache_stats());
+        let corrupt = self.chunks.scrub(SCRUB_BATCH_SIZE).await?;
+        let mut ops = vec![];
+        for address in corrupt {
+            warn!(
+                "{}: Requesting repair of corrupt chunk: {:?}",
+                self, address
+            );
+            ops.push(NodeDuty::Send(OutgoingMsg {
+                msg: Message::NodeCmd {
+                    cmd: NodeCmd::System(NodeSystemCmd::RepairChunk { address }),
+                    id: MessageId::new(),
+                    target_section_pk: None,
+                },
+                section_source: false, // sent as single node
+                dst: DstLocation::Section(*address.name()),
+                aggregation: Aggregation::None,
+            }));
+        }
+        Ok(ops)
+    }
+
     pub async fn used_space_ratio(&self) -> f64 {
         self.chunks.used_space_ratio().await
     }
 
+    /// The addresses of all stored chunks.
+    pub(crate) async fn keys(&self) -> BTreeSet<BlobAddress> {
+        self.chunks.keys().await.into_iter().collect()
+    }
+
+    /// Closes the chunk store, whose chunks then no longer count against the used space.
+    pub(crate) async fn close(self) -> Result<()> {
+        self.chunks.close().await
+    }
+
+    /// Deletes all stored chunks, releasing the space they used.
+    pub(crate) async fn clear(&mut self) -> Result<()> {
+        for address in self.chunks.keys().await {
+            self.chunks.delete(&address).await?;
+        }
+        Ok(())
+    }
+
     pub(crate) async fn delete(
         &mut self,
         address: BlobAddress,
+        signature: Signature,
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        if !self.chunks.has(&address) {
+        if !self.chunks.has(&address).await {
             info!("{}: Immutable chunk doesn't exist: {:?}", self, address);
             return Ok(NodeDuty::NoOp);
         }
 
-        let result = match self.chunks.get(&address) {
+        let result = match self.chunks.get(&address).await {
             Ok(Blob::Private(data)) => {
-                if data.owner() == origin.id() {
+                if verify_blob_deletion(data.owner(), &address, &msg_id, &signature).is_ok() {
                     self.chunks
                         .delete(&address)
                         .await
                         .map_err(|_error| ErrorMessage::FailedToDelete)
                 } else {
-                    Err(ErrorMessage::InvalidOwners(*origin.id()))
+                    warn!(
+                        "{}: Invalid signature on delete of {:?} by {:?}",
+                        self,
+                        address,
+                        origin.id()
+                    );
+                    Err(ErrorMessage::AccessDenied(*origin.id()))
                 }
             }
             Ok(_) => {
@@ -245,3 +399,72 @@ impl Display for ChunkStorage {
         write!(formatter, "ChunkStorage")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sn_data_types::{Keypair, PrivateBlob};
+    use sn_messaging::client::BlobWrite;
+    use tempdir::TempDir;
+
+    #[tokio::test]
+    async fn deletes_need_the_owners_signature() -> Result<()> {
+        let root = TempDir::new("chunk_storage")
+            .map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let mut storage = ChunkStorage::new(
+            XorName::random(),
+            root.path(),
+            UsedSpace::new(u64::MAX),
+            &StoreConfig::default(),
+        )
+        .await?;
+        let mut rng = rand::thread_rng();
+        let owner = Keypair::new_ed25519(&mut rng);
+        let forger = Keypair::new_ed25519(&mut rng);
+        let blob = Blob::Private(PrivateBlob::new(vec![1, 2, 3], owner.public_key()));
+        let address = *blob.address();
+        let origin = EndUser::AllClients(owner.public_key());
+        let _ = storage.store(&blob, MessageId::new(), origin).await?;
+
+        // The forger claims to be the owner, but can't sign as them.
+        let msg_id = MessageId::new();
+        let payload = BlobWrite::delete_private_payload(&address, &msg_id)?;
+        let duty = storage
+            .delete(address, forger.sign(&payload), msg_id, origin)
+            .await?;
+        assert!(matches!(
+            duty,
+            NodeDuty::Send(OutgoingMsg {
+                msg: Message::CmdError {
+                    error: CmdError::Data(ErrorMessage::AccessDenied(_)),
+                    ..
+                },
+                ..
+            })
+        ));
+        assert!(storage.chunks.has(&address).await);
+
+        // Nor can the owner's signature be replayed in another message.
+        let duty = storage
+            .delete(address, owner.sign(&payload), MessageId::new(), origin)
+            .await?;
+        assert!(matches!(
+            duty,
+            NodeDuty::Send(OutgoingMsg {
+                msg: Message::CmdError {
+                    error: CmdError::Data(ErrorMessage::AccessDenied(_)),
+                    ..
+                },
+                ..
+            })
+        ));
+        assert!(storage.chunks.has(&address).await);
+
+        let duty = storage
+            .delete(address, owner.sign(&payload), msg_id, origin)
+            .await?;
+        assert!(matches!(duty, NodeDuty::NoOp));
+        assert!(!storage.chunks.has(&address).await);
+        Ok(())
+    }
+}
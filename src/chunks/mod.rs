@@ -8,15 +8,17 @@
 
 mod chunk_storage;
 mod reading;
+mod replicas;
 mod writing;
 
 use crate::{
-    chunk_store::UsedSpace,
+    chunk_store::{StoreConfig, UsedSpace},
     node_ops::{NodeDuties, NodeDuty},
     NodeInfo, Result,
 };
 use chunk_storage::ChunkStorage;
 use log::info;
+pub(crate) use replicas::{is_ahead, merge_maps, MutableReplicas};
 use sn_data_types::{Blob, BlobAddress};
 use sn_messaging::{
     client::{BlobRead, BlobWrite},
@@ -37,9 +39,14 @@ pub(crate) struct Chunks {
 }
 
 impl Chunks {
-    pub async fn new(node_name: XorName, path: &Path, used_space: UsedSpace) -> Result<Self> {
+    pub async fn new(
+        node_name: XorName,
+        path: &Path,
+        used_space: UsedSpace,
+        config: &StoreConfig,
+    ) -> Result<Self> {
         Ok(Self {
-            chunk_storage: ChunkStorage::new(node_name, path, used_space).await?,
+            chunk_storage: ChunkStorage::new(node_name, path, used_space, config).await?,
         })
     }
 
@@ -48,8 +55,9 @@ impl Chunks {
         read: &BlobRead,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        reading::get_result(read, msg_id, origin, &self.chunk_storage).await
+        elder: Option<XorName>,
+    ) -> Result<NodeDuties> {
+        reading::get_result(read, msg_id, origin, elder, &self.chunk_storage).await
     }
 
     pub async fn write(
@@ -70,6 +78,28 @@ impl Chunks {
         }
     }
 
+    /// The addresses of all stored chunks.
+    pub async fn addresses(&self) -> BTreeSet<BlobAddress> {
+        self.chunk_storage.keys().await
+    }
+
+    /// Closes the chunk store, whose chunks then no longer count against the used space.
+    pub async fn close(self) -> Result<()> {
+        self.chunk_storage.close().await
+    }
+
+    /// Deletes all stored chunks.
+    pub async fn clear(&mut self) -> Result<()> {
+        info!("Deleting all stored chunks");
+        self.chunk_storage.clear().await
+    }
+
+    /// Verifies the integrity of the next batch of stored chunks.
+    pub async fn scrub(&mut self) -> Result<NodeDuties> {
+        info!("Scrubbing stored chunks");
+        self.chunk_storage.scrub().await
+    }
+
     ///
     pub async fn replicate_chunk(
         &self,
@@ -96,6 +126,19 @@ impl Chunks {
             .await
     }
 
+    /// Answers a storage challenge for the chunk at `address`.
+    pub async fn prove_storage(
+        &self,
+        address: BlobAddress,
+        nonce: [u8; 32],
+        msg_id: MessageId,
+        origin: SrcLocation,
+    ) -> Result<NodeDuty> {
+        self.chunk_storage
+            .prove_storage(address, nonce, msg_id, origin)
+            .await
+    }
+
     ///
     pub async fn store_replicated_chunk(&mut self, blob: Blob) -> Result<NodeDuty> {
         self.chunk_storage.store_for_replication(blob).await
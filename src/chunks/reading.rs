@@ -7,9 +7,10 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::chunk_storage::ChunkStorage;
-use crate::node_ops::NodeDuty;
+use crate::node_ops::NodeDuties;
 use crate::Result;
 use sn_messaging::{client::BlobRead, EndUser, MessageId};
+use xor_name::XorName;
 
 /// Read operations on data chunks.
 
@@ -17,8 +18,9 @@ pub(super) async fn get_result(
     read: &BlobRead,
     msg_id: MessageId,
     origin: EndUser,
+    elder: Option<XorName>,
     storage: &ChunkStorage,
-) -> Result<NodeDuty> {
+) -> Result<NodeDuties> {
     let BlobRead::Get(address) = read;
-    storage.get(address, msg_id, origin).await
+    storage.get(address, msg_id, origin, elder).await
 }
@@ -0,0 +1,316 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    chunk_store::{MapChunkStore, SequenceChunkStore, StoreConfig, UsedSpace},
+    node_ops::{NodeDuty, OutgoingMsg},
+    Result,
+};
+use log::{info, warn};
+use sn_data_types::{
+    Map, MapAddress, MapEntryActions, MapSeqEntryActions, MapUnseqEntryActions, Sequence,
+    SequenceAddress,
+};
+use sn_messaging::{
+    client::{Message, NodeCmd, NodeSystemCmd},
+    Aggregation, DstLocation, MessageId,
+};
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+};
+use xor_name::XorName;
+
+/// Replicas of the Maps and Sequences of our section, kept by adults so that the data survives
+/// the loss of elders.
+///
+/// The stores are the ones elders keep their Maps and Sequences in, so they must be dropped
+/// before the node is promoted, and the replicas become the data it holds as an elder.
+pub(crate) struct MutableReplicas {
+    maps: MapChunkStore,
+    sequences: SequenceChunkStore,
+}
+
+impl MutableReplicas {
+    pub(crate) async fn new(
+        path: &Path,
+        used_space: UsedSpace,
+        config: &StoreConfig,
+    ) -> Result<Self> {
+        let maps = MapChunkStore::new(path, used_space.clone(), config).await?;
+        let sequences = SequenceChunkStore::new(path, used_space, config).await?;
+        Ok(Self { maps, sequences })
+    }
+
+    /// Closes the stores, so that the space they use is counted again once they are opened as
+    /// elder stores.
+    pub(crate) async fn close(self) -> Result<()> {
+        self.maps.close().await?;
+        self.sequences.close().await
+    }
+
+    /// Merges our replica into a Map sent by an elder, whose copy decides which entries the Map
+    /// holds. If our replica held later versions of its entries, the merged Map is sent back to
+    /// the elders.
+    pub(crate) async fn receive_map(&mut self, map: Map) -> Result<NodeDuty> {
+        let existing = match self.maps.get(map.address()).await {
+            Ok(existing) => existing,
+            Err(_) => {
+                self.maps.put(&map).await?;
+                return Ok(NodeDuty::NoOp);
+            }
+        };
+        let merged = merge_maps(&map, &existing)?;
+        if merged != existing {
+            self.maps.put(&merged).await?;
+        }
+        if merged == map {
+            return Ok(NodeDuty::NoOp);
+        }
+        info!("{}: Our replica of {:?} is ahead", self, map.address());
+        Ok(send_to_elders(
+            *map.name(),
+            MessageId::from_content(&merged)?,
+            NodeSystemCmd::ReplicateMap(merged),
+        ))
+    }
+
+    /// Stores a Sequence sent by an elder, unless our replica is ahead, which is then sent back
+    /// to the elders.
+    pub(crate) async fn receive_sequence(&mut self, sequence: Sequence) -> Result<NodeDuty> {
+        if let Ok(existing) = self.sequences.get(sequence.address()).await {
+            if is_ahead(&existing, &sequence)? {
+                info!("{}: Our replica of {:?} is ahead", self, sequence.address());
+                return Ok(send_to_elders(
+                    *sequence.name(),
+                    MessageId::from_content(&existing)?,
+                    NodeSystemCmd::ReplicateSequence(existing),
+                ));
+            } else if !is_ahead(&sequence, &existing)? {
+                return Ok(NodeDuty::NoOp);
+            }
+        }
+        self.sequences.put(&sequence).await?;
+        Ok(NodeDuty::NoOp)
+    }
+
+    pub(crate) async fn delete_map(&mut self, address: &MapAddress) -> Result<()> {
+        if self.maps.has(address).await {
+            self.maps.delete(address).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn delete_sequence(&mut self, address: &SequenceAddress) -> Result<()> {
+        if self.sequences.has(address).await {
+            self.sequences.delete(address).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Merges a replica of a Map into the elders' copy of it. The elders' copy decides which entries
+/// the Map holds, as deletions leave no trace to merge, so that entries held by the replica only
+/// may have been deleted since. Of two sequenced entries, the one of the later version is kept,
+/// ties being broken by content so that all replicas converge. Unsequenced entries have no
+/// versions to tell which is later, so the elders' are kept. The permissions and owner are taken
+/// from the copy of the later version, as they change only with the version.
+pub(crate) fn merge_maps(elders: &Map, replica: &Map) -> Result<Map> {
+    let mut merged = if (replica.version(), replica.shell()) > (elders.version(), elders.shell()) {
+        replica.clone()
+    } else {
+        elders.clone()
+    };
+    let owner = merged.owner();
+    let actions = match (&mut merged, elders, replica) {
+        (Map::Seq(merged), Map::Seq(elders), Map::Seq(replica)) => {
+            let mut entries = elders.entries().clone();
+            for (key, value) in entries.iter_mut() {
+                if let Some(theirs) = replica.get(key) {
+                    if (theirs.version, &theirs.data) > (value.version, &value.data) {
+                        *value = theirs.clone();
+                    }
+                }
+            }
+            let _ = merged.take_entries();
+            MapEntryActions::Seq(
+                entries
+                    .into_iter()
+                    .fold(MapSeqEntryActions::new(), |actions, (key, value)| {
+                        actions.ins(key, value.data, value.version)
+                    }),
+            )
+        }
+        (Map::Unseq(merged), Map::Unseq(elders), Map::Unseq(_)) => {
+            let _ = merged.take_entries();
+            MapEntryActions::Unseq(
+                elders
+                    .entries()
+                    .clone()
+                    .into_iter()
+                    .fold(MapUnseqEntryActions::new(), |actions, (key, value)| {
+                        actions.ins(key, value)
+                    }),
+            )
+        }
+        // the kind is part of the address, so replicas of a Map are of the same kind
+        _ => return Ok(merged),
+    };
+    merged.mutate_entries(actions, &owner)?;
+    Ok(merged)
+}
+
+/// Whether a replica of a Sequence is ahead of another one. Sequences only grow, so the longer
+/// one is ahead. Replicas of the same length which diverged are ordered by content, so that all
+/// replicas converge on the same one.
+pub(crate) fn is_ahead(sequence: &Sequence, other: &Sequence) -> Result<bool> {
+    let (len, other_len) = (sequence.len(None)?, other.len(None)?);
+    if len != other_len {
+        return Ok(len > other_len);
+    }
+    if sequence == other {
+        return Ok(false);
+    }
+    warn!("Replicas of {:?} diverged", sequence.address());
+    Ok(MessageId::from_content(sequence)? > MessageId::from_content(other)?)
+}
+
+// Elders of our section are the ones responsible for `name`.
+fn send_to_elders(name: XorName, id: MessageId, cmd: NodeSystemCmd) -> NodeDuty {
+    NodeDuty::Send(OutgoingMsg {
+        msg: Message::NodeCmd {
+            cmd: NodeCmd::System(cmd),
+            id,
+            target_section_pk: None,
+        },
+        section_source: false, // sent as single node
+        dst: DstLocation::Section(name),
+        aggregation: Aggregation::None,
+    })
+}
+
+impl Display for MutableReplicas {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "MutableReplicas")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use sn_data_types::{Keypair, MapPermissionSet, SeqMap, UnseqMap};
+    use tempdir::TempDir;
+
+    #[tokio::test]
+    async fn replicas_ahead_of_the_elders_are_sent_back() -> Result<()> {
+        let root =
+            TempDir::new("replicas").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let mut replicas = MutableReplicas::new(
+            root.path(),
+            UsedSpace::new(u64::MAX),
+            &StoreConfig::default(),
+        )
+        .await?;
+        let owner = Keypair::new_ed25519(&mut rand::thread_rng()).public_key();
+        let mut data = UnseqMap::new(XorName::random(), 10, owner);
+        let older = Map::from(data.clone());
+        data.set_user_permissions(owner, MapPermissionSet::new(), 1)?;
+        let newer = Map::from(data);
+
+        assert!(matches!(
+            replicas.receive_map(newer.clone()).await?,
+            NodeDuty::NoOp
+        ));
+
+        // an elder which missed the last write gets our replica back
+        match replicas.receive_map(older).await? {
+            NodeDuty::Send(OutgoingMsg {
+                msg:
+                    Message::NodeCmd {
+                        cmd: NodeCmd::System(NodeSystemCmd::ReplicateMap(map)),
+                        ..
+                    },
+                ..
+            }) => assert_eq!(map, newer),
+            other => panic!("Unexpected duty: {:?}", other),
+        }
+
+        // a replica which matches the elders' copy is kept as is
+        assert!(matches!(replicas.receive_map(newer).await?, NodeDuty::NoOp));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn map_replicas_are_merged_into_the_elders_copy() -> Result<()> {
+        let root =
+            TempDir::new("replicas").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let mut replicas = MutableReplicas::new(
+            root.path(),
+            UsedSpace::new(u64::MAX),
+            &StoreConfig::default(),
+        )
+        .await?;
+        let owner = Keypair::new_ed25519(&mut rand::thread_rng()).public_key();
+        let mut data = SeqMap::new(XorName::random(), 10, owner);
+        data.mutate_entries(
+            MapSeqEntryActions::new()
+                .ins(b"shared".to_vec(), vec![0], 0)
+                .ins(b"deleted".to_vec(), vec![0], 0),
+            &owner,
+        )?;
+        // two copies of the same version, each missing edits the other got
+        let mut ours = data.clone();
+        ours.mutate_entries(
+            MapSeqEntryActions::new()
+                .ins(b"ours".to_vec(), vec![1], 0)
+                .update(b"shared".to_vec(), vec![1], 1),
+            &owner,
+        )?;
+        let mut elders = data;
+        elders.mutate_entries(
+            MapSeqEntryActions::new()
+                .ins(b"elders".to_vec(), vec![2], 0)
+                .del(b"deleted".to_vec(), 1),
+            &owner,
+        )?;
+        let (ours, elders) = (Map::from(ours), Map::from(elders));
+
+        // the entries are those of the elders' copy, at their latest versions
+        let merged = merge_maps(&elders, &ours)?;
+        assert_eq!(merged.version(), elders.version());
+        match &merged {
+            Map::Seq(merged) => {
+                assert_eq!(
+                    merged.keys(),
+                    vec![b"elders".to_vec(), b"shared".to_vec()]
+                        .into_iter()
+                        .collect()
+                );
+                assert_eq!(merged.get(b"shared").map(|value| value.version), Some(1));
+            }
+            Map::Unseq(_) => return Err(Error::Logic("Unexpected Map kind".to_string())),
+        }
+
+        // our replica is not replaced by the elders' copy, but merged into it and sent back,
+        // without the entry they deleted
+        assert!(matches!(replicas.receive_map(ours).await?, NodeDuty::NoOp));
+        match replicas.receive_map(elders).await? {
+            NodeDuty::Send(OutgoingMsg {
+                msg:
+                    Message::NodeCmd {
+                        cmd: NodeCmd::System(NodeSystemCmd::ReplicateMap(map)),
+                        ..
+                    },
+                ..
+            }) => assert_eq!(map, merged),
+            other => panic!("Unexpected duty: {:?}", other),
+        }
+        Ok(())
+    }
+}
@@ -22,6 +22,10 @@ pub(super) async fn get_result(
     use BlobWrite::*;
     match &write {
         New(data) => storage.store(&data, msg_id, origin).await,
-        DeletePrivate(address) => storage.delete(*address, msg_id, origin).await, // really though, for a delete, what we should be looking at is the origin signature! That would be the source of truth!
+        DeletePrivate { address, signature } => {
+            storage
+                .delete(*address, signature.clone(), msg_id, origin)
+                .await
+        }
     }
 }
@@ -21,6 +21,7 @@ use std::{
     io::{self, BufReader},
     net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
+    str::FromStr,
 };
 use structopt::StructOpt;
 
@@ -28,6 +29,9 @@ const CONFIG_FILE: &str = "node.config";
 const CONNECTION_INFO_FILE: &str = "node_connection_info.config";
 const DEFAULT_ROOT_DIR_NAME: &str = "root_dir";
 const DEFAULT_MAX_CAPACITY: u64 = 2 * 1024 * 1024 * 1024;
+const DEFAULT_CACHE_CAPACITY: u64 = 32 * 1024 * 1024;
+const DEFAULT_MUTABLE_COPY_COUNT: usize = 4;
+const DEFAULT_MAP_HISTORY_DEPTH: u64 = 10;
 
 /// Node configuration
 #[derive(Default, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, StructOpt)]
@@ -48,6 +52,46 @@ pub struct Config {
     /// MacOS: $HOME/.safe/node/root_dir
     #[structopt(short, long, parse(from_os_str))]
     pub root_dir: Option<PathBuf>,
+    /// Directories to keep chunks in instead of the root directory, e.g. one per disk, each given
+    /// as "<path>:<capacity in bytes>". New chunks go to the directory with the most free space.
+    /// When set, the node's max capacity is the sum of their capacities, or max capacity if that
+    /// is set and lower.
+    #[structopt(long = "data-dir")]
+    #[serde(default)]
+    pub data_dirs: Vec<DataDir>,
+    /// Upper limit in bytes for the space taken by Blob chunks, within max capacity.
+    #[structopt(long)]
+    pub blob_quota: Option<u64>,
+    /// Upper limit in bytes for the space taken by Map chunks, within max capacity.
+    #[structopt(long)]
+    pub map_quota: Option<u64>,
+    /// Upper limit in bytes for the space taken by Sequence chunks, within max capacity.
+    #[structopt(long)]
+    pub sequence_quota: Option<u64>,
+    /// Where ChunkStores keep their chunks: "filesystem" (the default) as files within the root
+    /// directory, "embedded" in a key-value database within the root directory, or "memory" in
+    /// memory only, losing them when the node stops.
+    #[structopt(long)]
+    pub storage_backend: Option<StorageBackend>,
+    /// How chunks are compressed when stored: "none" (the default), "zstd" or "lz4". Chunks stored
+    /// with any codec can be read back whatever this is set to.
+    #[structopt(long)]
+    pub compression: Option<Compression>,
+    /// Upper limit in bytes for the in-memory cache of recently read chunks, per ChunkStore.
+    /// 0 disables the cache.
+    #[structopt(long)]
+    pub cache_capacity: Option<u64>,
+    /// How many adults keep a replica of each Map and Sequence, when this node is an elder.
+    #[structopt(long)]
+    pub mutable_copy_count: Option<usize>,
+    /// How many earlier versions of each Map are kept for reads, when this node is an elder.
+    /// 0 keeps none.
+    #[structopt(long)]
+    pub map_history_depth: Option<u64>,
+    /// Encrypt chunks when storing them, with a key kept in the root directory. Once chunks have
+    /// been encrypted, the node keeps encrypting them, and can't start without the key.
+    #[structopt(long)]
+    pub encrypt_chunks: bool,
     /// Verbose output. `-v` is equivalent to logging with `warn`, `-vv` to `info`, `-vvv` to
     /// `debug`, `-vvvv` to `trace`. This flag overrides RUST_LOG.
     #[structopt(short, long, parse(from_occurrences))]
@@ -79,6 +123,10 @@ pub struct Config {
     /// Delete all data from a previous node running on the same PC
     #[structopt(long)]
     pub clear_data: bool,
+    /// Moves the node's stored chunks in or out of an archive, instead of running the node.
+    #[structopt(subcommand)]
+    #[serde(skip)]
+    pub command: Option<StoreCommand>,
 }
 
 impl Config {
@@ -114,6 +162,42 @@ impl Config {
             self.root_dir = Some(root_dir.clone());
         }
 
+        if !config.data_dirs.is_empty() {
+            self.data_dirs = config.data_dirs.clone();
+        }
+
+        if let Some(blob_quota) = config.blob_quota {
+            self.blob_quota = Some(blob_quota);
+        }
+
+        if let Some(map_quota) = config.map_quota {
+            self.map_quota = Some(map_quota);
+        }
+
+        if let Some(sequence_quota) = config.sequence_quota {
+            self.sequence_quota = Some(sequence_quota);
+        }
+
+        if let Some(storage_backend) = config.storage_backend {
+            self.storage_backend = Some(storage_backend);
+        }
+
+        if let Some(compression) = config.compression {
+            self.compression = Some(compression);
+        }
+
+        if let Some(cache_capacity) = config.cache_capacity {
+            self.cache_capacity = Some(cache_capacity);
+        }
+
+        if let Some(mutable_copy_count) = config.mutable_copy_count {
+            self.mutable_copy_count = Some(mutable_copy_count);
+        }
+
+        if let Some(map_history_depth) = config.map_history_depth {
+            self.map_history_depth = Some(map_history_depth);
+        }
+
         if config.verbose > 0 {
             self.verbose = config.verbose;
         }
@@ -133,6 +217,11 @@ impl Config {
         self.update = config.update || self.update;
         self.update_only = config.update_only || self.update_only;
         self.clear_data = config.clear_data || self.clear_data;
+        self.encrypt_chunks = config.encrypt_chunks || self.encrypt_chunks;
+
+        if let Some(command) = &config.command {
+            self.command = Some(command.clone());
+        }
 
         if !config.network_config.hard_coded_contacts.is_empty() {
             self.network_config.hard_coded_contacts = config.network_config.hard_coded_contacts;
@@ -192,9 +281,34 @@ impl Config {
         self.loopback
     }
 
-    /// Upper limit in bytes for allowed network storage on this node.
+    /// Upper limit in bytes for allowed network storage on this node.  With data dirs set, this is
+    /// the sum of their capacities, capped at the max capacity if one is set.
     pub fn max_capacity(&self) -> u64 {
-        self.max_capacity.unwrap_or(DEFAULT_MAX_CAPACITY)
+        if self.data_dirs.is_empty() {
+            return self.max_capacity.unwrap_or(DEFAULT_MAX_CAPACITY);
+        }
+        let sum: u64 = self
+            .data_dirs
+            .iter()
+            .fold(0, |sum, dir| sum.saturating_add(dir.max_capacity));
+        match self.max_capacity {
+            Some(max_capacity) => sum.min(max_capacity),
+            None => sum,
+        }
+    }
+
+    /// Directories to keep chunks in.  If empty, chunks are kept in the root directory.
+    pub fn data_dirs(&self) -> &[DataDir] {
+        &self.data_dirs
+    }
+
+    /// Upper limit in bytes for the space taken by chunks of `kind`, if any.
+    pub fn quota(&self, kind: StoreKind) -> Option<u64> {
+        match kind {
+            StoreKind::Blob => self.blob_quota,
+            StoreKind::Map => self.map_quota,
+            StoreKind::Sequence => self.sequence_quota,
+        }
     }
 
     /// Root directory for `ChunkStore`s and cached state. If not set, it defaults to
@@ -212,6 +326,37 @@ impl Config {
         self.root_dir = Some(path.into())
     }
 
+    /// Where `ChunkStore`s keep their chunks.
+    pub fn storage_backend(&self) -> StorageBackend {
+        self.storage_backend.unwrap_or_default()
+    }
+
+    /// How `ChunkStore`s compress their chunks.
+    pub fn compression(&self) -> Compression {
+        self.compression.unwrap_or_default()
+    }
+
+    /// Do `ChunkStore`s encrypt their chunks?
+    pub fn encrypt_chunks(&self) -> bool {
+        self.encrypt_chunks
+    }
+
+    /// Upper limit in bytes for the read cache of each `ChunkStore`.
+    pub fn cache_capacity(&self) -> u64 {
+        self.cache_capacity.unwrap_or(DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// How many adults keep a replica of each Map and Sequence.
+    pub fn mutable_copy_count(&self) -> usize {
+        self.mutable_copy_count
+            .unwrap_or(DEFAULT_MUTABLE_COPY_COUNT)
+    }
+
+    /// How many earlier versions of each Map are kept.
+    pub fn map_history_depth(&self) -> u64 {
+        self.map_history_depth.unwrap_or(DEFAULT_MAP_HISTORY_DEPTH)
+    }
+
     /// Set the directory to write the logs.
     pub fn set_log_dir<P: Into<PathBuf>>(&mut self, path: P) {
         self.log_dir = Some(path.into())
@@ -302,6 +447,114 @@ impl Config {
     }
 }
 
+/// A directory chunks are kept in, and the space they may take in it.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct DataDir {
+    /// The directory.
+    pub path: PathBuf,
+    /// Upper limit in bytes for the chunks kept in the directory.
+    pub max_capacity: u64,
+}
+
+impl FromStr for DataDir {
+    type Err = String;
+
+    /// Parses "<path>:<capacity>".  The capacity is split off at the last colon, so that paths
+    /// with drive letters can be given.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.rsplitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(max_capacity), Some(path)) if !path.is_empty() => Ok(DataDir {
+                path: PathBuf::from(path),
+                max_capacity: max_capacity
+                    .parse()
+                    .map_err(|e| format!("Invalid data dir capacity {}: {}", max_capacity, e))?,
+            }),
+            _ => Err(format!("Data dir must be <path>:<capacity>: {}", s)),
+        }
+    }
+}
+
+/// Operations on the node's stored chunks, run in place of the node.
+#[derive(Clone, Debug, Eq, PartialEq, StructOpt)]
+pub enum StoreCommand {
+    /// Writes all stored chunks to an archive, e.g. to move them to another machine
+    ExportChunks {
+        /// The archive file to create
+        #[structopt(parse(from_os_str))]
+        archive: PathBuf,
+    },
+    /// Stores all chunks from an archive, verifying each of them. The node must hold no chunks yet
+    ImportChunks {
+        /// The archive file to read
+        #[structopt(parse(from_os_str))]
+        archive: PathBuf,
+    },
+}
+
+/// The kinds of data `ChunkStore`s hold, which can each be given a quota.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, Hash, PartialEq)]
+pub enum StoreKind {
+    /// Blob chunks, held by adults.
+    Blob,
+    /// Map chunks, held by elders.
+    Map,
+    /// Sequence chunks, held by elders.
+    Sequence,
+}
+
+/// The storage media `ChunkStore`s can keep their chunks in.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Files within the root directory.
+    #[default]
+    Filesystem,
+    /// An embedded key-value database within the root directory.
+    Embedded,
+    /// Memory only.  Chunks are lost when the node stops, so this is meant for testing.
+    Memory,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "filesystem" => Ok(StorageBackend::Filesystem),
+            "embedded" => Ok(StorageBackend::Embedded),
+            "memory" => Ok(StorageBackend::Memory),
+            _ => Err(format!("Unknown storage backend: {}", s)),
+        }
+    }
+}
+
+/// The codecs `ChunkStore`s can compress their chunks with.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Chunks are stored uncompressed.
+    #[default]
+    None,
+    /// Zstandard, for the best ratio.
+    Zstd,
+    /// LZ4, for the fastest reads and writes.
+    Lz4,
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            "lz4" => Ok(Compression::Lz4),
+            _ => Err(format!("Unknown compression: {}", s)),
+        }
+    }
+}
+
 /// Writes connection info to file for use by clients.
 ///
 /// The file is written to the `current_bin_dir()` with the appropriate file name.
@@ -339,7 +592,31 @@ fn smoke() {
     // NOTE: IF this value is being changed due to a change in the config,
     // the change in config also be handled in Config::merge()
     // and in examples/config_handling.rs
-    let expected_size = 296;
+    let expected_size = 448;
 
     assert_eq!(std::mem::size_of::<Config>(), expected_size);
 }
+
+#[test]
+fn max_capacity_caps_data_dirs() {
+    let mut config = Config {
+        data_dirs: vec![
+            DataDir {
+                path: PathBuf::from("a"),
+                max_capacity: 100,
+            },
+            DataDir {
+                path: PathBuf::from("b"),
+                max_capacity: 50,
+            },
+        ],
+        ..Default::default()
+    };
+    assert_eq!(config.max_capacity(), 150);
+
+    config.max_capacity = Some(120);
+    assert_eq!(config.max_capacity(), 120);
+
+    config.max_capacity = Some(200);
+    assert_eq!(config.max_capacity(), 150);
+}
@@ -8,7 +8,7 @@
 
 use sn_data_types::{Error as DtError, PublicKey};
 use sn_messaging::{client::Error as ErrorMessage, MessageId};
-use std::io;
+use std::{collections::BTreeMap, io};
 use thiserror::Error;
 use xor_name::XorName;
 
@@ -23,6 +23,15 @@ pub enum Error {
     /// Not enough space in `ChunkStore` to perform `put`.
     #[error("Not enough space")]
     NotEnoughSpace,
+    /// The quota for Blob chunks doesn't leave enough space to perform `put`.
+    #[error("Not enough space within the quota for Blobs")]
+    NotEnoughSpaceForBlobs,
+    /// The quota for Map chunks doesn't leave enough space to perform `put`.
+    #[error("Not enough space within the quota for Maps")]
+    NotEnoughSpaceForMaps,
+    /// The quota for Sequence chunks doesn't leave enough space to perform `put`.
+    #[error("Not enough space within the quota for Sequences")]
+    NotEnoughSpaceForSequences,
     /// Not Section PublicKey.
     #[error("Not section public key returned from routing")]
     NoSectionPublicKey,
@@ -53,6 +62,15 @@ pub enum Error {
     /// Key, Value pair not found in `ChunkStore`.
     #[error("No such chunk")]
     NoSuchChunk,
+    /// Chunk read from `ChunkStore` is not the intact chunk that was stored.
+    #[error("Chunk is corrupt")]
+    CorruptChunk,
+    /// Chunks have been stored encrypted, but the key they were encrypted with is missing.
+    #[error("Chunk encryption key not found, so the stored chunks can't be read")]
+    ChunkEncryptionKeyMissing,
+    /// The chunk encryption key is not the one the stored chunks were encrypted with.
+    #[error("Chunk encryption key doesn't match the one the stored chunks were encrypted with")]
+    ChunkEncryptionKeyMismatch,
     /// This node does not know or manage any section funds
     #[error("No section funds")]
     NoSectionFunds,
@@ -68,6 +86,17 @@ pub enum Error {
     /// Chunk already exists for this node
     #[error("Data already exists at this node")]
     DataExists,
+    /// A client has as many subscriptions as it can have.
+    #[error("Too many subscriptions")]
+    TooManySubscriptions,
+    /// Versions expected by a Map transaction which don't match those of the Map.
+    #[error("Map transaction conflict. Map version: {version:?}, entry versions: {entries:?}")]
+    MapTransactionConflict {
+        /// The version of the Map, if it isn't the expected one.
+        version: Option<u64>,
+        /// The versions of the entries which aren't the expected ones, `None` for absent entries.
+        entries: BTreeMap<Vec<u8>, Option<u64>>,
+    },
     /// I/O error.
     #[error("I/O error: {0}")]
     Io(#[from] io::Error),
@@ -86,6 +115,9 @@ pub enum Error {
     /// PickleDb error.
     #[error("PickleDb error:: {0}")]
     PickleDb(#[from] pickledb::error::Error),
+    /// Sled error.
+    #[error("Sled error:: {0}")]
+    Sled(#[from] sled::Error),
     /// NetworkData error.
     #[error("Network data error:: {0}")]
     NetworkData(#[from] sn_data_types::Error),
@@ -119,6 +151,9 @@ pub enum Error {
     /// No mapping to sn_messages::Error could be found. Either we need a new error there, or we need to handle or convert this error before sending it as a message
     #[error("No mapping to sn_messages error is set up for this NodeError {0}")]
     NoErrorMapping(String),
+    /// A chunk archive is malformed or fails verification.
+    #[error("Invalid chunk archive: {0}")]
+    InvalidArchive(String),
     /// Logic error.
     #[error("Logic error: {0}")]
     Logic(String),
@@ -131,10 +166,18 @@ pub(crate) fn convert_to_error_message(error: Error) -> Result<sn_messaging::cli
         Error::InvalidSignedTransfer(_) => Ok(ErrorMessage::InvalidSignature),
         Error::TransferAlreadyRegistered => Ok(ErrorMessage::TransactionIdExists),
         Error::NoSuchChunk => Ok(ErrorMessage::NoSuchData),
+        Error::CorruptChunk => Ok(ErrorMessage::NoSuchData),
         Error::NotEnoughSpace => Ok(ErrorMessage::NotEnoughSpace),
+        Error::NotEnoughSpaceForBlobs => Ok(ErrorMessage::NotEnoughSpaceForBlobs),
+        Error::NotEnoughSpaceForMaps => Ok(ErrorMessage::NotEnoughSpaceForMaps),
+        Error::NotEnoughSpaceForSequences => Ok(ErrorMessage::NotEnoughSpaceForSequences),
         Error::BalanceExists => Ok(ErrorMessage::BalanceExists),
         Error::TempDirCreationFailed(_) => Ok(ErrorMessage::FailedToWriteFile),
         Error::DataExists => Ok(ErrorMessage::DataExists),
+        Error::TooManySubscriptions => Ok(ErrorMessage::TooManySubscriptions),
+        Error::MapTransactionConflict { version, entries } => {
+            Ok(ErrorMessage::MapTransactionConflict { version, entries })
+        }
         Error::NetworkData(error) => convert_dt_error_to_error_message(error),
         error => Err(Error::NoErrorMapping(error.to_string())),
     }
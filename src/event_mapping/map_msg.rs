@@ -205,6 +205,22 @@ fn match_section_msg(msg: Message, origin: SrcLocation) -> NodeDuty {
             node_rewards: node_rewards.to_owned(),
             user_wallets: user_wallets.to_owned(),
         },
+        Message::NodeCmd {
+            cmd:
+                NodeCmd::System(NodeSystemCmd::ReceiveDataHandover {
+                    blob_records,
+                    maps,
+                    sequences,
+                }),
+            id,
+            ..
+        } => NodeDuty::ReceiveDataHandover {
+            blob_records: blob_records.to_owned(),
+            maps: maps.to_owned(),
+            sequences: sequences.to_owned(),
+            msg_id: *id,
+            sender: origin,
+        },
         Message::NodeCmd {
             cmd: NodeCmd::System(NodeSystemCmd::ProposeRewardPayout(proposal)),
             ..
@@ -263,6 +279,7 @@ fn match_section_msg(msg: Message, origin: SrcLocation) -> NodeDuty {
             read: query.clone(),
             msg_id: *id,
             origin: *origin,
+            elder: None,
         },
         Message::NodeCmd {
             cmd: NodeCmd::Chunks { cmd, origin },
@@ -325,6 +342,138 @@ fn match_node_msg(msg: Message, origin: SrcLocation) -> NodeDuty {
             cmd: NodeCmd::System(NodeSystemCmd::StorageFull { node_id, .. }),
             ..
         } => NodeDuty::IncrementFullNodeCount { node_id: *node_id },
+        Message::NodeCmd {
+            cmd: NodeCmd::System(NodeSystemCmd::RepairChunk { address }),
+            ..
+        } => match origin {
+            SrcLocation::Node(holder) => NodeDuty::RepairChunk {
+                address: *address,
+                holder,
+            },
+            _ => NodeDuty::NoOp,
+        },
+        // ------ storage challenges ------
+        Message::NodeQuery {
+            query: NodeQuery::System(NodeSystemQuery::StorageChallenge { address, nonce }),
+            id,
+            ..
+        } => NodeDuty::ProveStorage {
+            address: *address,
+            nonce: *nonce,
+            msg_id: *id,
+            origin,
+        },
+        Message::NodeQueryResponse {
+            response:
+                NodeQueryResponse::System(NodeSystemQueryResponse::StorageProof {
+                    address,
+                    nonce,
+                    proof,
+                }),
+            correlation_id,
+            ..
+        } => match origin {
+            SrcLocation::Node(holder) => NodeDuty::ReceiveStorageProof {
+                address: *address,
+                nonce: *nonce,
+                proof: *proof,
+                holder,
+                correlation_id: *correlation_id,
+            },
+            _ => NodeDuty::NoOp,
+        },
+        Message::NodeCmd {
+            cmd: NodeCmd::System(NodeSystemCmd::HandOffChunks { addresses }),
+            ..
+        } => match origin {
+            SrcLocation::Node(holder) => NodeDuty::HandOffChunks {
+                holder,
+                addresses: addresses.clone(),
+            },
+            _ => NodeDuty::NoOp,
+        },
+        // ------ Map and Sequence replicas ------
+        Message::NodeCmd {
+            cmd: NodeCmd::System(NodeSystemCmd::ReplicateMap(map)),
+            id,
+            ..
+        } => match origin {
+            SrcLocation::Node(sender) => NodeDuty::ReceiveMapReplica {
+                map: map.clone(),
+                msg_id: *id,
+                sender,
+            },
+            _ => NodeDuty::NoOp,
+        },
+        Message::NodeCmd {
+            cmd: NodeCmd::System(NodeSystemCmd::ReplicateSequence(sequence)),
+            id,
+            ..
+        } => match origin {
+            SrcLocation::Node(sender) => NodeDuty::ReceiveSequenceReplica {
+                sequence: sequence.clone(),
+                msg_id: *id,
+                sender,
+            },
+            _ => NodeDuty::NoOp,
+        },
+        Message::NodeCmd {
+            cmd: NodeCmd::System(NodeSystemCmd::DeleteMapReplica(address)),
+            ..
+        } => match origin {
+            SrcLocation::Node(sender) => NodeDuty::DeleteMapReplica {
+                address: *address,
+                sender,
+            },
+            _ => NodeDuty::NoOp,
+        },
+        Message::NodeCmd {
+            cmd: NodeCmd::System(NodeSystemCmd::DeleteSequenceReplica(address)),
+            ..
+        } => match origin {
+            SrcLocation::Node(sender) => NodeDuty::DeleteSequenceReplica {
+                address: *address,
+                sender,
+            },
+            _ => NodeDuty::NoOp,
+        },
+        Message::NodeEvent {
+            event: NodeEvent::DataHandoverCompleted,
+            correlation_id,
+            ..
+        } => NodeDuty::CompleteDataHandover {
+            correlation_id: *correlation_id,
+            sender: origin,
+        },
+        Message::NodeEvent {
+            event: NodeEvent::ReplicationCompleted { chunk, .. },
+            correlation_id,
+            ..
+        } => match origin {
+            SrcLocation::Node(holder) => NodeDuty::ReplicationCompleted {
+                address: *chunk,
+                holder,
+                msg_id: *correlation_id,
+            },
+            _ => NodeDuty::NoOp,
+        },
+        Message::NodeCmd {
+            cmd:
+                NodeCmd::System(NodeSystemCmd::NoSpaceForChunk {
+                    blob,
+                    origin: user,
+                    msg_id,
+                }),
+            ..
+        } => match origin {
+            SrcLocation::Node(holder) => NodeDuty::RetryChunkStore {
+                data: blob.clone(),
+                holder,
+                msg_id: *msg_id,
+                origin: *user,
+            },
+            _ => NodeDuty::NoOp,
+        },
         // ------ chunk replication ------
         // query response from adult cannot be accumulated
         Message::NodeQueryResponse {
@@ -333,16 +482,31 @@ fn match_node_msg(msg: Message, origin: SrcLocation) -> NodeDuty {
             ..
         } => {
             log::info!("Verifying GetChunk NodeQueryResponse!");
-            if let Ok(data) = result {
-                NodeDuty::StoreChunkForReplication {
+            match (result, origin) {
+                (Ok(data), _) => NodeDuty::StoreChunkForReplication {
                     data: data.clone(),
                     correlation_id: *correlation_id,
-                }
-            } else {
-                log::warn!("Got error when reading chunk for replication: {:?}", result);
-                NodeDuty::NoOp
+                },
+                (Err(error), SrcLocation::Node(holder)) => NodeDuty::ChunkReadFailed {
+                    error: error.clone(),
+                    holder,
+                    correlation_id: *correlation_id,
+                },
+                (Err(_), _) => NodeDuty::NoOp,
             }
         }
+        Message::NodeEvent {
+            event: NodeEvent::ChunkServed { chunk },
+            correlation_id,
+            ..
+        } => match origin {
+            SrcLocation::Node(holder) => NodeDuty::ChunkServed {
+                address: *chunk,
+                holder,
+                correlation_id: *correlation_id,
+            },
+            _ => NodeDuty::NoOp,
+        },
         //
         // ------ transfers ------
         Message::NodeQuery {
@@ -355,13 +519,21 @@ fn match_node_msg(msg: Message, origin: SrcLocation) -> NodeDuty {
         },
         // --- Adult ---
         Message::NodeQuery {
-            query: NodeQuery::Chunks { query, origin },
+            query:
+                NodeQuery::Chunks {
+                    query,
+                    origin: user,
+                },
             id,
             ..
         } => NodeDuty::ReadChunk {
             read: query.clone(),
             msg_id: *id,
-            origin: *origin,
+            origin: *user,
+            elder: match origin {
+                SrcLocation::Node(elder) => Some(elder),
+                _ => None,
+            },
         },
         Message::NodeCmd {
             cmd: NodeCmd::Chunks { cmd, origin },
@@ -53,7 +53,11 @@ pub mod utils;
 pub(crate) use to_db_key::ToDbKey;
 
 pub use crate::{
-    config_handler::{write_connection_info, Config},
+    chunk_store::{export_chunks, import_chunks, ArchiveManifest},
+    config_handler::{
+        write_connection_info, Compression, Config, DataDir, StorageBackend, StoreCommand,
+        StoreKind,
+    },
     error::{Error, Result},
     network::Network,
     node::Node,
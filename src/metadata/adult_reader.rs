@@ -1,4 +1,5 @@
 use sn_routing::XorName;
+use std::collections::BTreeSet;
 
 use crate::Network;
 
@@ -12,18 +13,70 @@ use crate::Network;
 
 #[derive(Clone)]
 pub struct AdultReader {
-    network: Network,
+    section: Section,
+}
+
+#[derive(Clone)]
+enum Section {
+    Network(Network),
+    // A section which doesn't change, for tests which have no network.
+    #[cfg(test)]
+    Fixed {
+        our_name: XorName,
+        elders: BTreeSet<XorName>,
+        adults: Vec<XorName>,
+    },
 }
 
 impl AdultReader {
     /// Access to the current state of our adult constellation
     pub fn new(network: Network) -> Self {
-        Self { network }
+        Self {
+            section: Section::Network(network),
+        }
+    }
+
+    /// A section of the given elders and adults, without a network.
+    #[cfg(test)]
+    pub(crate) fn fixed(
+        our_name: XorName,
+        elders: BTreeSet<XorName>,
+        adults: Vec<XorName>,
+    ) -> Self {
+        Self {
+            section: Section::Fixed {
+                our_name,
+                elders,
+                adults,
+            },
+        }
+    }
+
+    /// Our own name, for adults to send responses to.
+    pub async fn our_name(&self) -> XorName {
+        match &self.section {
+            Section::Network(network) => network.our_name().await,
+            #[cfg(test)]
+            Section::Fixed { our_name, .. } => *our_name,
+        }
+    }
+
+    /// Dynamic state
+    pub async fn our_elders(&self) -> BTreeSet<XorName> {
+        match &self.section {
+            Section::Network(network) => network.our_elder_names().await,
+            #[cfg(test)]
+            Section::Fixed { elders, .. } => elders.clone(),
+        }
     }
 
     /// Dynamic state
     pub async fn our_adults(&self) -> Vec<XorName> {
-        self.network.our_adults().await
+        match &self.section {
+            Section::Network(network) => network.our_adults().await,
+            #[cfg(test)]
+            Section::Fixed { adults, .. } => adults.clone(),
+        }
     }
 
     /// Dynamic state
@@ -32,8 +85,17 @@ impl AdultReader {
         name: &XorName,
         count: usize,
     ) -> Vec<XorName> {
-        self.network
-            .our_adults_sorted_by_distance_to(name, count)
-            .await
+        match &self.section {
+            Section::Network(network) => {
+                network.our_adults_sorted_by_distance_to(name, count).await
+            }
+            #[cfg(test)]
+            Section::Fixed { adults, .. } => {
+                let mut adults = adults.clone();
+                adults.sort_by(|lhs, rhs| name.cmp_distance(lhs, rhs));
+                adults.truncate(count);
+                adults
+            }
+        }
     }
 }
@@ -7,18 +7,18 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{
-    capacity::ChunkHolderDbs,
+    capacity::{ChunkHolderBatch, ChunkHolderDbs, ChunkMetadata, HolderMetadata},
     error::convert_to_error_message,
     node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
-    Error, Network, Result, ToDbKey,
+    utils::verify_blob_deletion,
+    Error, Network, Result,
 };
 use log::{info, trace, warn};
-use serde::{Deserialize, Serialize};
-use sn_data_types::{Blob, BlobAddress, Error as DtError, PublicKey, Result as NdResult};
+use sn_data_types::{Blob, BlobAddress, Error as DtError, PublicKey, Signature};
 use sn_messaging::{
     client::{
         BlobRead, BlobWrite, CmdError, Error as ErrorMessage, Message, NodeCmd, NodeQuery,
-        NodeSystemCmd, QueryResponse,
+        NodeSystemCmd, NodeSystemQuery, QueryResponse,
     },
     Aggregation, DstLocation, EndUser, MessageId, SrcLocation,
 };
@@ -26,34 +26,108 @@ use sn_messaging::{
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt::{self, Display, Formatter},
+    time::{Duration, Instant},
 };
-use xor_name::XorName;
+use xor_name::{Prefix, XorName};
 
 use super::adult_reader::AdultReader;
 
 // The number of separate copies of a blob chunk which should be maintained.
 const CHUNK_COPY_COUNT: usize = 4;
+// How many chunks the replication audit reads from the db at a time.
+const AUDIT_PAGE_SIZE: usize = 256;
+// The most copies a single replication audit asks for, so that catching up on many
+// under-replicated chunks doesn't flood the section.
+const MAX_AUDIT_REPLICATIONS: usize = 64;
+// How long a new holder has to report a replicated chunk as stored, before the copy is asked
+// for again.
+const REPLICATION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+// How long holders have to answer a storage challenge.
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+// How many times the holders of a chunk are challenged when no majority of them agrees on a proof,
+// before they are all counted as failing.
+const MAX_CHALLENGE_ROUNDS: usize = 3;
+// How long a holder has to answer a read, before the next holder is asked.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
 
-#[derive(Default, Debug, Serialize, Deserialize)]
-struct ChunkMetadata {
-    holders: BTreeSet<XorName>,
-    owner: Option<PublicKey>,
+// A client's read of a chunk, waiting for the answer of one of its holders.
+struct PendingRead {
+    address: BlobAddress,
+    origin: EndUser,
+    // The id of the client's query, which the holder's answer correlates to.
+    msg_id: MessageId,
+    holder: XorName,
+    // The holders to ask next, in order of preference.
+    untried: Vec<XorName>,
+    sent: Instant,
+}
+
+// How well a holder has been serving reads.
+#[derive(Default)]
+struct HolderStats {
+    successes: u64,
+    failures: u64,
+    // Moving average of the time taken to answer, where a failure counts as `READ_TIMEOUT`.
+    latency: Duration,
+}
+
+impl HolderStats {
+    fn record(&mut self, success: bool, latency: Duration) {
+        if success {
+            self.successes += 1;
+        } else {
+            self.failures += 1;
+        }
+        let latency = if success { latency } else { READ_TIMEOUT };
+        self.latency = if self.successes + self.failures == 1 {
+            latency
+        } else {
+            (self.latency * 3 + latency) / 4
+        };
+    }
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
-struct HolderMetadata {
-    chunks: BTreeSet<BlobAddress>,
+// A storage challenge sent to all holders of a chunk, and the proofs they answered with.
+struct StorageChallenge {
+    address: BlobAddress,
+    nonce: [u8; 32],
+    holders: BTreeSet<XorName>,
+    proofs: BTreeMap<XorName, Option<XorName>>,
+    sent: Instant,
+    // Starts at 1, and grows each time the chunk is challenged again for want of a majority.
+    round: usize,
 }
 
 /// Operations over the data type Blob.
 pub(super) struct BlobRegister {
     dbs: ChunkHolderDbs,
     reader: AdultReader,
+    // Copies asked for from new holders, which aren't in the chunk metadata until stored.
+    pending_replications: BTreeMap<(BlobAddress, XorName), Instant>,
+    // The last chunk checked by the replication audit, which resumes after it.
+    audit_cursor: Option<BlobAddress>,
+    // Storage challenges awaiting answers, by the id of the challenge msg.
+    challenges: BTreeMap<MessageId, StorageChallenge>,
+    // Reads awaiting a holder's answer, by the id of the client's query.
+    pending_reads: BTreeMap<MessageId, PendingRead>,
+    read_stats: BTreeMap<XorName, HolderStats>,
 }
 
 impl BlobRegister {
     pub(super) fn new(dbs: ChunkHolderDbs, reader: AdultReader) -> Self {
-        Self { dbs, reader }
+        Self {
+            dbs,
+            reader,
+            pending_replications: BTreeMap::new(),
+            audit_cursor: None,
+            challenges: BTreeMap::new(),
+            pending_reads: BTreeMap::new(),
+            read_stats: BTreeMap::new(),
+        }
+    }
+
+    pub(super) fn dbs(&self) -> &ChunkHolderDbs {
+        &self.dbs
     }
 
     pub(super) async fn write(
@@ -65,7 +139,9 @@ impl BlobRegister {
         use BlobWrite::*;
         match write {
             New(data) => self.store(data, msg_id, origin).await,
-            DeletePrivate(address) => self.delete(address, msg_id, origin).await,
+            DeletePrivate { address, signature } => {
+                self.delete(address, signature, msg_id, origin).await
+            }
         }
     }
 
@@ -168,6 +244,7 @@ impl BlobRegister {
     async fn delete(
         &mut self,
         address: BlobAddress,
+        signature: Signature,
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
@@ -176,18 +253,18 @@ impl BlobRegister {
             Err(error) => return self.send_blob_cmd_error(error, msg_id, origin).await,
         };
 
-        // todo: use signature verification instead
-        if let Some(data_owner) = metadata.owner {
-            if &data_owner != origin.id() {
-                return self
-                    .send_blob_cmd_error(
-                        Error::NetworkData(DtError::AccessDenied(*origin.id())),
-                        msg_id,
-                        origin,
-                    )
-                    .await;
-            }
-        };
+        if let Err(error) =
+            authorise_deletion(metadata.owner, &address, &msg_id, &signature, &origin)
+        {
+            warn!(
+                "{}: Rejecting delete of {:?} by {:?}: {}",
+                self,
+                address,
+                origin.id(),
+                error
+            );
+            return self.send_blob_cmd_error(error, msg_id, origin).await;
+        }
 
         let mut results = vec![];
         for holder_name in &metadata.holders {
@@ -198,7 +275,7 @@ impl BlobRegister {
 
         let msg = Message::NodeCmd {
             cmd: NodeCmd::Chunks {
-                cmd: BlobWrite::DeletePrivate(address),
+                cmd: BlobWrite::DeletePrivate { address, signature },
                 origin,
             },
             id: msg_id,
@@ -216,13 +293,8 @@ impl BlobRegister {
         holder: XorName,
         origin: EndUser,
     ) -> Result<()> {
-        // TODO -
-        // - if Err, we need to flag this sender as "full" (i.e. add to self.full_adults, try on
-        //   next closest non-full adult, or elder if none.  Also update the metadata for this
-        //   chunk.  Not known yet where we'll get the chunk from to do that.
         info!("Setting chunk holder");
 
-        let db_key = blob_address.to_db_key()?;
         let mut metadata = self
             .get_metadata_for(blob_address)
             .await
@@ -233,24 +305,16 @@ impl BlobRegister {
 
         let _ = metadata.holders.insert(holder);
 
-        if let Err(error) = self.dbs.metadata.lock().await.set(&db_key, &metadata) {
-            warn!("{}: Failed to write metadata to DB: {:?}", self, error);
-            return Err(error.into());
-        }
-
         // We're acting as data handler, received request from client handlers
         let mut holders_metadata = self.get_holder(holder).await.unwrap_or_default();
         let _ = holders_metadata.chunks.insert(blob_address);
 
-        if let Err(error) = self
-            .dbs
-            .holders
-            .lock()
-            .await
-            .set(&holder.to_db_key()?, &holders_metadata)
-        {
+        let mut batch = ChunkHolderBatch::default();
+        batch.set_chunk(&blob_address, &metadata)?;
+        batch.set_holder(&holder, &holders_metadata)?;
+        if let Err(error) = self.dbs.apply(batch).await {
             warn!("{}: Failed to write metadata to DB: {:?}", self, error);
-            return Err(error.into());
+            return Err(error);
         }
         Ok(())
     }
@@ -260,48 +324,23 @@ impl BlobRegister {
         blob_address: BlobAddress,
         holder_name: XorName,
     ) -> Result<()> {
-        let db_key = blob_address.to_db_key()?;
         let metadata = self.get_metadata_for(blob_address).await;
         if let Ok(mut metadata) = metadata {
-            let holder = self.get_holder(holder_name).await;
+            let mut batch = ChunkHolderBatch::default();
 
             // Remove the chunk from the holder metadata
-            if let Ok(mut holder) = holder {
+            if let Ok(mut holder) = self.get_holder(holder_name).await {
                 let _ = holder.chunks.remove(&blob_address);
-                if holder.chunks.is_empty() {
-                    if let Err(error) = self.dbs.holders.lock().await.rem(&holder_name.to_db_key()?)
-                    {
-                        warn!(
-                            "{}: Failed to delete holder metadata from DB: {:?}",
-                            self, error
-                        );
-                    }
-                } else if let Err(error) = self
-                    .dbs
-                    .holders
-                    .lock()
-                    .await
-                    .set(&holder_name.to_db_key()?, &holder)
-                {
-                    warn!(
-                        "{}: Failed to write holder metadata to DB: {:?}",
-                        self, error
-                    );
-                }
+                batch.set_holder(&holder_name, &holder)?;
             }
 
             // Remove the holder from the chunk metadata
             let _ = metadata.holders.remove(&holder_name);
-            if metadata.holders.is_empty() {
-                if let Err(error) = self.dbs.metadata.lock().await.rem(&db_key) {
-                    warn!(
-                        "{}: Failed to delete chunk metadata from DB: {:?}",
-                        self, error
-                    );
-                }
-            } else if let Err(error) = self.dbs.metadata.lock().await.set(&db_key, &metadata) {
+            batch.set_chunk(&blob_address, &metadata)?;
+
+            if let Err(error) = self.dbs.apply(batch).await {
                 warn!(
-                    "{}: Failed to write chunk metadata to DB: {:?}",
+                    "{}: Failed to write chunk holder metadata to DB: {:?}",
                     self, error
                 );
             }
@@ -309,6 +348,164 @@ impl BlobRegister {
         Ok(())
     }
 
+    /// The addresses of the chunks which `prefix` doesn't cover, and which thus belong to our
+    /// sibling section after a split.
+    pub(super) fn addresses_outside(&self, prefix: &Prefix) -> Result<Vec<BlobAddress>> {
+        Ok(self
+            .addresses()?
+            .into_iter()
+            .filter(|address| !prefix.matches(address.name()))
+            .collect())
+    }
+
+    /// The addresses of all chunks we have records of.
+    pub(super) fn addresses(&self) -> Result<Vec<BlobAddress>> {
+        let mut addresses = vec![];
+        let mut cursor = None;
+        loop {
+            let chunks = self.dbs.chunks(cursor.as_ref(), AUDIT_PAGE_SIZE)?;
+            match chunks.last() {
+                Some((address, _)) => cursor = Some(*address),
+                None => break,
+            }
+            addresses.extend(chunks.into_iter().map(|(address, _)| address));
+        }
+        Ok(addresses)
+    }
+
+    /// The records of the chunks at `addresses` which we still have.
+    pub(super) fn records(
+        &self,
+        addresses: &[BlobAddress],
+    ) -> Result<Vec<(BlobAddress, ChunkMetadata)>> {
+        let mut records = vec![];
+        for address in addresses {
+            if let Some(metadata) = self.dbs.chunk(address)? {
+                records.push((*address, metadata));
+            }
+        }
+        Ok(records)
+    }
+
+    /// Adds chunk records handed over by another section to ours, merging them with any records
+    /// we already have. See `merge_record` for which records are taken.
+    pub(super) async fn receive_records(
+        &mut self,
+        records: Vec<(BlobAddress, ChunkMetadata)>,
+    ) -> Result<()> {
+        let adults: BTreeSet<XorName> = self.reader.our_adults().await.into_iter().collect();
+        let mut holders: BTreeMap<XorName, HolderMetadata> = BTreeMap::new();
+        let mut batch = ChunkHolderBatch::default();
+        for (address, record) in records {
+            let metadata = match merge_record(&address, self.dbs.chunk(&address)?, record, &adults)
+            {
+                Some(metadata) => metadata,
+                None => {
+                    warn!("{}: Dropping handed over record of {:?}", self, address);
+                    continue;
+                }
+            };
+            for holder in &metadata.holders {
+                if !holders.contains_key(holder) {
+                    let existing = self.dbs.holder(holder)?.unwrap_or_default();
+                    let _ = holders.insert(*holder, existing);
+                }
+                if let Some(holder) = holders.get_mut(holder) {
+                    let _ = holder.chunks.insert(address);
+                }
+            }
+            batch.set_chunk(&address, &metadata)?;
+        }
+        for (name, metadata) in &holders {
+            batch.set_holder(name, metadata)?;
+        }
+        self.dbs.apply(batch).await
+    }
+
+    /// Removes the records of chunks which another section has taken over, from both indexes.
+    pub(super) async fn remove_records(&mut self, addresses: &[BlobAddress]) -> Result<()> {
+        let mut holders: BTreeMap<XorName, HolderMetadata> = BTreeMap::new();
+        let mut batch = ChunkHolderBatch::default();
+        for address in addresses {
+            let metadata = match self.dbs.chunk(address)? {
+                Some(metadata) => metadata,
+                None => continue,
+            };
+            for holder in &metadata.holders {
+                if !holders.contains_key(holder) {
+                    let existing = self.dbs.holder(holder)?.unwrap_or_default();
+                    let _ = holders.insert(*holder, existing);
+                }
+                if let Some(holder) = holders.get_mut(holder) {
+                    let _ = holder.chunks.remove(address);
+                }
+            }
+            batch.set_chunk(address, &ChunkMetadata::default())?;
+        }
+        for (name, metadata) in &holders {
+            batch.set_holder(name, metadata)?;
+        }
+        self.dbs.apply(batch).await
+    }
+
+    // Called when a holder didn't have the space to store a chunk. The holder is flagged as
+    // full, and the chunk is sent on to the closest non-full adult which doesn't hold it yet.
+    pub(super) async fn retry_store(
+        &mut self,
+        data: Blob,
+        holder: XorName,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        let address = *data.address();
+        let metadata = match self.get_metadata_for(address).await {
+            Ok(metadata) if metadata.holders.contains(&holder) => metadata,
+            _ => {
+                warn!(
+                    "{}: Retry of {:?} requested by non-holder {:?}",
+                    self, address, holder
+                );
+                return Ok(NodeDuty::NoOp);
+            }
+        };
+
+        info!("{}: Holder {:?} is full", self, holder);
+        if let Err(error) = self.dbs.add_full_adult(&holder).await {
+            warn!("{}: Failed to flag full adult in DB: {:?}", self, error);
+        }
+        self.remove_chunk_holder(address, holder).await?;
+
+        let new_holder = self
+            .get_holders_for_chunk(data.name())
+            .await
+            .into_iter()
+            .find(|adult| !metadata.holders.contains(adult));
+        let new_holder = match new_holder {
+            Some(new_holder) => new_holder,
+            None => {
+                warn!("{}: No adult left with space for {:?}", self, address);
+                return self
+                    .send_blob_cmd_error(Error::NotEnoughSpace, msg_id, origin)
+                    .await;
+            }
+        };
+
+        info!("Retrying store of {:?} at {:?}", address, new_holder);
+        self.set_chunk_holder(address, new_holder, origin).await?;
+        let msg = Message::NodeCmd {
+            cmd: NodeCmd::Chunks {
+                cmd: BlobWrite::New(data),
+                origin,
+            },
+            id: msg_id,
+            target_section_pk: None,
+        };
+        Ok(NodeDuty::SendToNodes {
+            targets: std::iter::once(new_holder).collect(),
+            msg,
+        })
+    }
+
     pub(super) async fn replicate_chunks(&mut self, holder: XorName) -> Result<NodeDuties> {
         trace!("Replicating chunks of holder {:?}", holder);
 
@@ -323,47 +520,222 @@ impl BlobRegister {
         Ok(cmds)
     }
 
+    // Called when a holder is no longer an adult, but still has its chunks. The holder is dropped
+    // from the chunks' records, and new holders are asked to copy them from it or the other holders.
+    pub(super) async fn hand_off_chunks(
+        &mut self,
+        holder: XorName,
+        addresses: BTreeSet<BlobAddress>,
+    ) -> Result<NodeDuties> {
+        let mut holder_metadata = self.dbs.holder(&holder)?.unwrap_or_default();
+        let mut batch = ChunkHolderBatch::default();
+        let mut sources = BTreeMap::new();
+        for address in addresses {
+            let _ = holder_metadata.chunks.remove(&address);
+            let mut metadata = match self.dbs.chunk(&address)? {
+                Some(metadata) => metadata,
+                None => {
+                    warn!(
+                        "{}: {:?} hands off {:?}, which we have no record of",
+                        self, holder, address
+                    );
+                    continue;
+                }
+            };
+            let _ = metadata.holders.remove(&holder);
+            batch.set_chunk(&address, &metadata)?;
+            let mut current_holders = metadata.holders;
+            let _ = current_holders.insert(holder);
+            let _ = sources.insert(address, current_holders);
+        }
+        batch.set_holder(&holder, &holder_metadata)?;
+        self.dbs.apply(batch).await?;
+
+        let mut cmds = vec![];
+        for (address, current_holders) in sources {
+            cmds.extend(self.get_replication_msgs(address, current_holders).await);
+        }
+        info!(
+            "{}: {:?} hands off its chunks, asking for {} new copies",
+            self,
+            holder,
+            cmds.len()
+        );
+        Ok(cmds)
+    }
+
     async fn get_replication_msgs(
-        &self,
+        &mut self,
         address: BlobAddress,
         current_holders: BTreeSet<XorName>,
     ) -> NodeDuties {
-        use NodeCmd::*;
-        let mut node_ops = Vec::new();
-        let messages = self
-            .get_new_holders_for_chunk(&address)
+        let now = Instant::now();
+        self.get_new_holders_for_chunk(&address)
             .await
             .into_iter()
             .map(|new_holder| {
-                let message_id = MessageId::combine(vec![*address.name(), new_holder]);
                 info!("Sending replicate-chunk cmd to NewHolder {:?}", new_holder);
-                (
-                    Message::NodeCmd {
-                        cmd: System(NodeSystemCmd::ReplicateChunk {
-                            new_holder,
-                            address,
-                            current_holders: current_holders.clone(),
-                        }),
-                        id: message_id,
-                        target_section_pk: None,
-                    },
-                    new_holder,
-                )
+                let _ = self.pending_replications.insert((address, new_holder), now);
+                Self::replicate_chunk_msg(address, new_holder, current_holders.clone())
             })
-            .collect::<Vec<_>>();
-        for (msg, dst) in messages {
-            node_ops.push(NodeDuty::Send(OutgoingMsg {
-                msg,
-                section_source: true, // i.e. errors go to our section
-                dst: DstLocation::Node(dst),
-                aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
-            }));
+            .collect()
+    }
+
+    // Checks the next chunks in the db for holders which aren't among our adults anymore, and
+    // asks new holders for copies of chunks which are short of `CHUNK_COPY_COUNT`. Each audit
+    // picks up where the previous one stopped once it has asked for `MAX_AUDIT_REPLICATIONS`
+    // copies, and starts over after reaching the last chunk.
+    pub(super) async fn audit_replication(&mut self) -> Result<NodeDuties> {
+        let adults: BTreeSet<XorName> = self.reader.our_adults().await.into_iter().collect();
+        let now = Instant::now();
+        self.pending_replications
+            .retain(|_, asked| now.duration_since(*asked) < REPLICATION_TIMEOUT);
+
+        let mut cmds = vec![];
+        let mut audited = 0;
+        'audit: loop {
+            let chunks = self
+                .dbs
+                .chunks(self.audit_cursor.as_ref(), AUDIT_PAGE_SIZE)?;
+            if chunks.is_empty() {
+                self.audit_cursor = None;
+                break;
+            }
+            for (address, metadata) in chunks {
+                self.audit_cursor = Some(address);
+                audited += 1;
+                cmds.extend(self.audit_chunk(address, metadata, &adults).await?);
+                if cmds.len() >= MAX_AUDIT_REPLICATIONS {
+                    break 'audit;
+                }
+            }
         }
-        node_ops
+
+        info!(
+            "{}: Audited {} chunks, asking for {} new copies",
+            self,
+            audited,
+            cmds.len()
+        );
+        Ok(cmds)
     }
 
-    pub(super) async fn read(
+    async fn audit_chunk(
+        &mut self,
+        address: BlobAddress,
+        mut metadata: ChunkMetadata,
+        adults: &BTreeSet<XorName>,
+    ) -> Result<NodeDuties> {
+        let lost: Vec<XorName> = metadata.holders.difference(adults).copied().collect();
+        if !lost.is_empty() {
+            warn!("{}: Holders {:?} of {:?} are gone", self, lost, address);
+            let mut batch = ChunkHolderBatch::default();
+            for holder in lost {
+                let _ = metadata.holders.remove(&holder);
+                if let Ok(mut held) = self.get_holder(holder).await {
+                    let _ = held.chunks.remove(&address);
+                    batch.set_holder(&holder, &held)?;
+                }
+            }
+            batch.set_chunk(&address, &metadata)?;
+            self.dbs.apply(batch).await?;
+        }
+        if metadata.holders.is_empty() {
+            warn!("{}: No holder left to replicate {:?} from", self, address);
+            return Ok(vec![]);
+        }
+
+        let pending: BTreeSet<XorName> = self
+            .pending_replications
+            .keys()
+            .filter(|(pending_address, _)| *pending_address == address)
+            .map(|(_, holder)| *holder)
+            .collect();
+        let copies = metadata.holders.len() + pending.len();
+        if copies >= CHUNK_COPY_COUNT {
+            return Ok(vec![]);
+        }
+
+        let new_holders: Vec<XorName> = self
+            .get_holders_for_chunk(address.name())
+            .await
+            .into_iter()
+            .filter(|adult| !metadata.holders.contains(adult) && !pending.contains(adult))
+            .take(CHUNK_COPY_COUNT - copies)
+            .collect();
+        let now = Instant::now();
+        Ok(new_holders
+            .into_iter()
+            .map(|new_holder| {
+                info!(
+                    "{}: {:?} is short of copies, replicating to {:?}",
+                    self, address, new_holder
+                );
+                let _ = self.pending_replications.insert((address, new_holder), now);
+                Self::replicate_chunk_msg(address, new_holder, metadata.holders.clone())
+            })
+            .collect())
+    }
+
+    fn replicate_chunk_msg(
+        address: BlobAddress,
+        new_holder: XorName,
+        current_holders: BTreeSet<XorName>,
+    ) -> NodeDuty {
+        let message_id = MessageId::combine(vec![*address.name(), new_holder]);
+        NodeDuty::Send(OutgoingMsg {
+            msg: Message::NodeCmd {
+                cmd: NodeCmd::System(NodeSystemCmd::ReplicateChunk {
+                    new_holder,
+                    address,
+                    current_holders,
+                }),
+                id: message_id,
+                target_section_pk: None,
+            },
+            section_source: true, // i.e. errors go to our section
+            dst: DstLocation::Node(new_holder),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        })
+    }
+
+    // Called when a holder has found its copy of a chunk to be corrupt. The holder is
+    // asked to fetch the chunk again from the remaining holders.
+    pub(super) async fn repair_chunk(
         &self,
+        address: BlobAddress,
+        holder: XorName,
+    ) -> Result<NodeDuties> {
+        let metadata = match self.get_metadata_for(address).await {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(vec![]),
+        };
+        if !metadata.holders.contains(&holder) {
+            warn!(
+                "{}: Repair of {:?} requested by non-holder {:?}",
+                self, address, holder
+            );
+            return Ok(vec![]);
+        }
+        let mut current_holders = metadata.holders;
+        let _ = current_holders.remove(&holder);
+        if current_holders.is_empty() {
+            warn!(
+                "{}: No intact copies left to repair {:?} from",
+                self, address
+            );
+            return Ok(vec![]);
+        }
+        info!("Sending repair of {:?} to holder {:?}", address, holder);
+        Ok(NodeDuties::from(Self::replicate_chunk_msg(
+            address,
+            holder,
+            current_holders,
+        )))
+    }
+
+    pub(super) async fn read(
+        &mut self,
         read: &BlobRead,
         msg_id: MessageId,
         origin: EndUser,
@@ -375,7 +747,7 @@ impl BlobRegister {
     }
 
     async fn get(
-        &self,
+        &mut self,
         address: BlobAddress,
         msg_id: MessageId,
         origin: EndUser,
@@ -406,52 +778,358 @@ impl BlobRegister {
                 return query_error(Error::NetworkData(DtError::AccessDenied(*origin.id()))).await;
             }
         };
-        let msg = Message::NodeQuery {
-            query: NodeQuery::Chunks {
-                query: BlobRead::Get(address),
+
+        let untried = holders_by_preference(&self.read_stats, &address, metadata.holders);
+        self.read_from_next_holder(address, msg_id, origin, untried)
+            .await
+    }
+
+    // Asks the first of `untried` for the chunk, on behalf of the client, or tells the client that
+    // the chunk couldn't be read if no holder is left.
+    async fn read_from_next_holder(
+        &mut self,
+        address: BlobAddress,
+        msg_id: MessageId,
+        origin: EndUser,
+        mut untried: Vec<XorName>,
+    ) -> Result<NodeDuty> {
+        if untried.is_empty() {
+            warn!("{}: No holder could serve {:?}", self, address);
+            return Ok(Self::read_response(
+                Err(ErrorMessage::NoSuchData),
+                msg_id,
                 origin,
+            ));
+        }
+        let holder = untried.remove(0);
+        let _ = self.pending_reads.insert(
+            msg_id,
+            PendingRead {
+                address,
+                origin,
+                msg_id,
+                holder,
+                untried,
+                sent: Instant::now(),
             },
-            id: msg_id,
-            target_section_pk: None,
+        );
+        trace!("{}: Reading {:?} from {:?}", self, address, holder);
+        // The holder sends the chunk to the client itself, and only tells us whether it did.
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::NodeQuery {
+                query: NodeQuery::Chunks {
+                    query: BlobRead::Get(address),
+                    origin,
+                },
+                id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // sent as single node
+            dst: DstLocation::Node(holder),
+            aggregation: Aggregation::None,
+        }))
+    }
+
+    // Called when a holder tells us whether it sent the client the chunk. On an error the next
+    // holder is asked at once.
+    pub(super) async fn receive_read_outcome(
+        &mut self,
+        outcome: std::result::Result<BlobAddress, ErrorMessage>,
+        holder: XorName,
+        correlation_id: MessageId,
+    ) -> Result<NodeDuty> {
+        let read = match self.pending_reads.remove(&correlation_id) {
+            Some(read) if read.holder == holder => read,
+            Some(read) => {
+                warn!(
+                    "{}: {:?} answered the read of {:?} asked of {:?}",
+                    self, holder, read.address, read.holder
+                );
+                let _ = self.pending_reads.insert(correlation_id, read);
+                return Ok(NodeDuty::NoOp);
+            }
+            None => return Ok(NodeDuty::NoOp),
         };
-        Ok(NodeDuty::SendToNodes {
-            targets: metadata.holders,
-            msg,
+        let latency = read.sent.elapsed();
+        match outcome {
+            Ok(address) if address == read.address => {
+                self.read_stats
+                    .entry(read.holder)
+                    .or_default()
+                    .record(true, latency);
+                Ok(NodeDuty::NoOp)
+            }
+            Ok(address) => {
+                warn!(
+                    "{}: {:?} answered the read of {:?} with {:?}",
+                    self, read.holder, read.address, address
+                );
+                self.fail_over(read, latency).await
+            }
+            Err(error) => {
+                info!(
+                    "{}: {:?} failed the read of {:?}: {:?}",
+                    self, read.holder, read.address, error
+                );
+                self.fail_over(read, latency).await
+            }
+        }
+    }
+
+    // Counts a failed read against its holder, and asks the next holder.
+    async fn fail_over(&mut self, read: PendingRead, latency: Duration) -> Result<NodeDuty> {
+        self.read_stats
+            .entry(read.holder)
+            .or_default()
+            .record(false, latency);
+        self.read_from_next_holder(read.address, read.msg_id, read.origin, read.untried)
+            .await
+    }
+
+    // Asks the next holder for the chunks of reads which their holder didn't answer in time.
+    pub(super) async fn retry_reads(&mut self) -> Result<NodeDuties> {
+        let now = Instant::now();
+        let expired: Vec<MessageId> = self
+            .pending_reads
+            .iter()
+            .filter(|(_, read)| now.duration_since(read.sent) >= READ_TIMEOUT)
+            .map(|(msg_id, _)| *msg_id)
+            .collect();
+        let mut cmds = vec![];
+        for msg_id in expired {
+            if let Some(read) = self.pending_reads.remove(&msg_id) {
+                info!(
+                    "{}: {:?} didn't answer the read of {:?} in time",
+                    self, read.holder, read.address
+                );
+                cmds.push(self.fail_over(read, READ_TIMEOUT).await?);
+            }
+        }
+        Ok(cmds)
+    }
+
+    /// Logs how well each holder served reads so far.
+    pub(super) fn log_read_stats(&self) {
+        for (holder, stats) in &self.read_stats {
+            info!(
+                "{}: Reads from {:?}: {} served, {} failed, {:?} latency",
+                self, holder, stats.successes, stats.failures, stats.latency
+            );
+        }
+    }
+
+    fn read_response(
+        result: std::result::Result<Blob, ErrorMessage>,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> NodeDuty {
+        NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::GetBlob(result),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to an error..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
         })
     }
 
-    #[allow(unused)]
+    // Challenges all holders of a randomly picked chunk to prove that they still store it, by
+    // hashing it along with a random nonce. Challenges which weren't fully answered in time are
+    // judged first.
+    pub(super) async fn challenge_storage(&mut self) -> Result<NodeDuties> {
+        let now = Instant::now();
+        let expired: Vec<MessageId> = self
+            .challenges
+            .iter()
+            .filter(|(_, challenge)| now.duration_since(challenge.sent) >= CHALLENGE_TIMEOUT)
+            .map(|(msg_id, _)| *msg_id)
+            .collect();
+        let mut cmds = vec![];
+        for msg_id in expired {
+            if let Some(challenge) = self.challenges.remove(&msg_id) {
+                cmds.extend(self.judge_challenge(challenge).await?);
+            }
+        }
+
+        let (address, metadata) = match self.dbs.random_chunk()? {
+            Some(chunk) => chunk,
+            None => return Ok(cmds),
+        };
+        info!(
+            "{}: Challenging holders of {:?} to prove storage",
+            self, address
+        );
+        cmds.push(self.challenge(address, metadata.holders, 1));
+        Ok(cmds)
+    }
+
+    // Challenges `holders` to prove storage of the chunk at `address`, with a fresh nonce.
+    fn challenge(
+        &mut self,
+        address: BlobAddress,
+        holders: BTreeSet<XorName>,
+        round: usize,
+    ) -> NodeDuty {
+        let nonce: [u8; 32] = rand::random();
+        let msg_id = MessageId::new();
+        let _ = self.challenges.insert(
+            msg_id,
+            StorageChallenge {
+                address,
+                nonce,
+                holders: holders.clone(),
+                proofs: BTreeMap::new(),
+                sent: Instant::now(),
+                round,
+            },
+        );
+        NodeDuty::SendToNodes {
+            targets: holders,
+            msg: Message::NodeQuery {
+                query: NodeQuery::System(NodeSystemQuery::StorageChallenge { address, nonce }),
+                id: msg_id,
+                target_section_pk: None,
+            },
+        }
+    }
+
+    // Called when a holder answers a storage challenge. The challenge is judged once all
+    // challenged holders have answered.
+    pub(super) async fn receive_storage_proof(
+        &mut self,
+        address: BlobAddress,
+        nonce: [u8; 32],
+        proof: Option<XorName>,
+        holder: XorName,
+        correlation_id: MessageId,
+    ) -> Result<NodeDuties> {
+        let challenge = match self.challenges.get_mut(&correlation_id) {
+            Some(challenge)
+                if challenge.address == address
+                    && challenge.nonce == nonce
+                    && challenge.holders.contains(&holder) =>
+            {
+                challenge
+            }
+            _ => {
+                warn!(
+                    "{}: Unexpected storage proof for {:?} from {:?}",
+                    self, address, holder
+                );
+                return Ok(vec![]);
+            }
+        };
+        let _ = challenge.proofs.insert(holder, proof);
+        if challenge.proofs.len() < challenge.holders.len() {
+            return Ok(vec![]);
+        }
+        match self.challenges.remove(&correlation_id) {
+            Some(challenge) => self.judge_challenge(challenge).await,
+            None => Ok(vec![]),
+        }
+    }
+
+    // Counts a failure for each holder which didn't prove storage, drops it from the chunk's
+    // holders, and replicates the chunk to replace it. If the remaining holders don't agree on a
+    // proof, they are challenged again, and once out of rounds, they are all counted as failing
+    // and the chunk is replicated to more adults, without dropping any of them, as one of them
+    // may hold the only good copy.
+    async fn judge_challenge(&mut self, challenge: StorageChallenge) -> Result<NodeDuties> {
+        let address = challenge.address;
+        let (failed, inconclusive) = match judge_proofs(&challenge.holders, &challenge.proofs) {
+            Verdict::Judged(failed) => (failed, false),
+            Verdict::Inconclusive(failed) => (failed, true),
+        };
+        for holder in &failed {
+            self.count_storage_failure(holder, address).await;
+            self.remove_chunk_holder(address, *holder).await?;
+        }
+        let remaining: BTreeSet<XorName> = challenge.holders.difference(&failed).copied().collect();
+        if remaining.is_empty() {
+            if !failed.is_empty() {
+                warn!("{}: No holder left to replicate {:?} from", self, address);
+            }
+            return Ok(vec![]);
+        }
+        if !inconclusive {
+            if failed.is_empty() {
+                return Ok(vec![]);
+            }
+            return Ok(self.get_replication_msgs(address, remaining).await);
+        }
+        if challenge.round < MAX_CHALLENGE_ROUNDS {
+            info!(
+                "{}: Holders of {:?} disagree on its storage proof, challenging them again",
+                self, address
+            );
+            let mut cmds = if failed.is_empty() {
+                vec![]
+            } else {
+                self.get_replication_msgs(address, remaining.clone()).await
+            };
+            cmds.push(self.challenge(address, remaining, challenge.round + 1));
+            return Ok(cmds);
+        }
+        warn!(
+            "{}: Holders of {:?} still disagree on its storage proof after {} rounds",
+            self, address, challenge.round
+        );
+        for holder in &remaining {
+            self.count_storage_failure(holder, address).await;
+        }
+        Ok(self.get_replication_msgs(address, remaining).await)
+    }
+
+    async fn count_storage_failure(&self, holder: &XorName, address: BlobAddress) {
+        match self.dbs.add_storage_failure(holder).await {
+            Ok(failures) => warn!(
+                "{}: {:?} failed to prove storage of {:?} ({} failures)",
+                self, holder, address, failures
+            ),
+            Err(error) => warn!(
+                "{}: Failed to count storage failure in DB: {:?}",
+                self, error
+            ),
+        }
+    }
+
+    /// The number of storage challenges failed by each adult which failed any, for use in
+    /// reward calculation.
+    pub(super) fn storage_failures(&self) -> Result<BTreeMap<XorName, u64>> {
+        self.dbs.storage_failures()
+    }
+
+    // Called when a new holder reports that it stored its copy of a chunk.
     pub(super) async fn update_holders(
         &mut self,
         address: BlobAddress,
         holder: XorName,
-        result: NdResult<()>,
         message_id: MessageId,
     ) -> Result<NodeDuty> {
-        let mut chunk_metadata = self.get_metadata_for(address).await.unwrap_or_default();
-        let _ = chunk_metadata.holders.insert(holder);
-        if let Err(error) = self
-            .dbs
-            .metadata
-            .lock()
-            .await
-            .set(&address.to_db_key()?, &chunk_metadata)
+        if self
+            .pending_replications
+            .remove(&(address, holder))
+            .is_none()
         {
-            warn!("{}: Failed to write metadata to DB: {:?}", self, error);
+            warn!(
+                "{}: Unexpected replication of {:?} to {:?}",
+                self, address, holder
+            );
+            return Ok(NodeDuty::NoOp);
         }
+        let mut chunk_metadata = self.get_metadata_for(address).await.unwrap_or_default();
+        let _ = chunk_metadata.holders.insert(holder);
         let mut holders_metadata = self.get_holder(holder).await.unwrap_or_default();
         let _ = holders_metadata.chunks.insert(address);
-        if let Err(error) = self
-            .dbs
-            .holders
-            .lock()
-            .await
-            .set(&holder.to_db_key()?, &holders_metadata)
-        {
-            warn!(
-                "{}: Failed to write holder metadata to DB: {:?}",
-                self, error
-            );
+
+        let mut batch = ChunkHolderBatch::default();
+        batch.set_chunk(&address, &chunk_metadata)?;
+        batch.set_holder(&holder, &holders_metadata)?;
+        if let Err(error) = self.dbs.apply(batch).await {
+            warn!("{}: Failed to write metadata to DB: {:?}", self, error);
         }
         info!("Replication process completed for: {:?}", message_id);
         Ok(NodeDuty::NoOp)
@@ -465,10 +1143,10 @@ impl BlobRegister {
     ) -> Result<BTreeMap<BlobAddress, BTreeSet<XorName>>> {
         let mut blob_addresses: BTreeMap<BlobAddress, BTreeSet<XorName>> = BTreeMap::new();
         let chunk_holder = self.get_holder(node).await;
+        let mut batch = ChunkHolderBatch::default();
 
         if let Ok(holder) = chunk_holder {
             for chunk_address in holder.chunks {
-                let db_key = chunk_address.to_db_key()?;
                 let chunk_metadata = self.get_metadata_for(chunk_address).await;
 
                 if let Ok(mut metadata) = chunk_metadata {
@@ -477,36 +1155,22 @@ impl BlobRegister {
                     }
 
                     let _ = blob_addresses.insert(chunk_address, metadata.holders.clone());
-
-                    if metadata.holders.is_empty() {
-                        if let Err(error) = self.dbs.metadata.lock().await.rem(&db_key) {
-                            warn!("{}: Failed to write metadata to DB: {:?}", self, error);
-                        }
-                    } else if let Err(error) =
-                        self.dbs.metadata.lock().await.set(&db_key, &metadata)
-                    {
-                        warn!("{}: Failed to write metadata to DB: {:?}", self, error);
-                    }
+                    batch.set_chunk(&chunk_address, &metadata)?;
                 }
             }
         }
 
         // Since the node has left the section, remove it from the holders DB
-        if let Err(error) = self.dbs.holders.lock().await.rem(&node.to_db_key()?) {
-            warn!("{}: Failed to delete metadata from DB: {:?}", self, error);
+        batch.remove_holder(&node)?;
+        if let Err(error) = self.dbs.apply(batch).await {
+            warn!("{}: Failed to write metadata to DB: {:?}", self, error);
         };
 
         Ok(blob_addresses)
     }
 
     async fn get_holder(&self, holder: XorName) -> Result<HolderMetadata> {
-        match self
-            .dbs
-            .holders
-            .lock()
-            .await
-            .get::<HolderMetadata>(&holder.to_db_key()?)
-        {
+        match self.dbs.holder(&holder)? {
             Some(metadata) => {
                 if metadata.chunks.is_empty() {
                     //warn!("{}: is not responsible for any chunk", holder);
@@ -523,13 +1187,7 @@ impl BlobRegister {
     }
 
     async fn get_metadata_for(&self, address: BlobAddress) -> Result<ChunkMetadata> {
-        match self
-            .dbs
-            .metadata
-            .lock()
-            .await
-            .get::<ChunkMetadata>(&address.to_db_key()?)
-        {
+        match self.dbs.chunk(&address)? {
             Some(metadata) => {
                 if metadata.holders.is_empty() {
                     warn!("{}: Metadata holders is empty for: {:?}", self, address);
@@ -547,10 +1205,26 @@ impl BlobRegister {
 
     // Returns `XorName`s of the target holders for an Blob chunk.
     // Used to fetch the list of holders for a new chunk.
+    // Adults flagged as full are passed over for the next closest ones.
     async fn get_holders_for_chunk(&self, target: &XorName) -> Vec<XorName> {
+        let adult_count = self.reader.our_adults().await.len();
         self.reader
-            .our_adults_sorted_by_distance_to(&target, CHUNK_COPY_COUNT)
+            .our_adults_sorted_by_distance_to(&target, adult_count)
             .await
+            .into_iter()
+            .filter(|adult| !self.is_full(adult))
+            .take(CHUNK_COPY_COUNT)
+            .collect()
+    }
+
+    fn is_full(&self, adult: &XorName) -> bool {
+        match self.dbs.is_full_adult(adult) {
+            Ok(is_full) => is_full,
+            Err(error) => {
+                warn!("{}: Failed to read full adults from DB: {:?}", self, error);
+                false
+            }
+        }
     }
 
     // Returns `XorName`s of the new target holders for an Blob chunk.
@@ -572,8 +1246,330 @@ impl BlobRegister {
     }
 }
 
+// Merges a chunk record handed over by another section with the one we have, if any. Only holders
+// among our `adults` are taken, and the record is dropped if its owner doesn't match the address,
+// which is public without one and private with one, or differs from the owner we already know of.
+fn merge_record(
+    address: &BlobAddress,
+    existing: Option<ChunkMetadata>,
+    record: ChunkMetadata,
+    adults: &BTreeSet<XorName>,
+) -> Option<ChunkMetadata> {
+    if address.is_private() != record.owner.is_some() {
+        return None;
+    }
+    let mut metadata = match existing {
+        Some(existing) if !existing.holders.is_empty() => {
+            if existing.owner != record.owner {
+                return None;
+            }
+            existing
+        }
+        _ => ChunkMetadata {
+            holders: BTreeSet::new(),
+            owner: record.owner,
+        },
+    };
+    metadata.holders.extend(
+        record
+            .holders
+            .into_iter()
+            .filter(|holder| adults.contains(holder)),
+    );
+    if metadata.holders.is_empty() {
+        return None;
+    }
+    Some(metadata)
+}
+
+// Checks that the owner of the private blob at `address` signed its deletion in the message with
+// id `msg_id`. The origin is only who the client claims to be, so it proves nothing on its own.
+fn authorise_deletion(
+    owner: Option<PublicKey>,
+    address: &BlobAddress,
+    msg_id: &MessageId,
+    signature: &Signature,
+    origin: &EndUser,
+) -> Result<()> {
+    match owner {
+        Some(owner) if address.is_private() => {
+            verify_blob_deletion(&owner, address, msg_id, signature)
+                .map_err(|_| Error::NetworkData(DtError::AccessDenied(*origin.id())))
+        }
+        _ => Err(Error::InvalidOperation(format!(
+            "{:?} is not a private blob",
+            address
+        ))),
+    }
+}
+
+// Orders holders by how fast they answered reads so far, and holders as fast as each other by
+// their distance to the chunk. Holders without a record are taken to be as fast as the mean,
+// so that they are neither always tried first nor never tried.
+fn holders_by_preference(
+    read_stats: &BTreeMap<XorName, HolderStats>,
+    address: &BlobAddress,
+    holders: BTreeSet<XorName>,
+) -> Vec<XorName> {
+    let mean_latency = if read_stats.is_empty() {
+        Duration::from_secs(0)
+    } else {
+        read_stats
+            .values()
+            .map(|stats| stats.latency)
+            .sum::<Duration>()
+            / read_stats.len() as u32
+    };
+    let mut holders: Vec<XorName> = holders.into_iter().collect();
+    holders.sort_by(|lhs, rhs| {
+        let latency = |holder| {
+            read_stats
+                .get(holder)
+                .map_or(mean_latency, |stats| stats.latency)
+        };
+        latency(lhs)
+            .cmp(&latency(rhs))
+            .then_with(|| address.name().cmp_distance(lhs, rhs))
+    });
+    holders
+}
+
+// The outcome of a storage challenge.
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    // The holders which failed the challenge; all others passed it.
+    Judged(BTreeSet<XorName>),
+    // No proof was given by a majority of the holders, so the proofs given can't be told apart
+    // from forged ones. Holds the holders which failed regardless, by not giving any proof.
+    Inconclusive(BTreeSet<XorName>),
+}
+
+// Judges the proofs given by the holders of a chunk: those which didn't answer, or which didn't
+// have the chunk, fail, as do those whose proof differs from the one given by a majority of the
+// holders. The elder doesn't hold the chunk, so without such a majority no proof can be checked.
+fn judge_proofs(
+    holders: &BTreeSet<XorName>,
+    proofs: &BTreeMap<XorName, Option<XorName>>,
+) -> Verdict {
+    let mut counts: BTreeMap<XorName, usize> = BTreeMap::new();
+    for proof in proofs.values().flatten() {
+        *counts.entry(*proof).or_insert(0) += 1;
+    }
+    let expected = counts
+        .into_iter()
+        .find(|(_, count)| *count > holders.len() / 2)
+        .map(|(proof, _)| proof);
+    let failed = holders
+        .iter()
+        .filter(|holder| match proofs.get(holder) {
+            Some(Some(proof)) => matches!(expected, Some(expected) if expected != *proof),
+            _ => true,
+        })
+        .copied()
+        .collect();
+    if expected.is_some() || proofs.values().flatten().next().is_none() {
+        Verdict::Judged(failed)
+    } else {
+        Verdict::Inconclusive(failed)
+    }
+}
+
 impl Display for BlobRegister {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         write!(formatter, "BlobRegister")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sn_data_types::{Keypair, PrivateBlob};
+
+    #[test]
+    fn holders_disagreeing_with_majority_fail_challenge() {
+        let holders: BTreeSet<XorName> = (0..4).map(|_| XorName::random()).collect();
+        let names: Vec<XorName> = holders.iter().copied().collect();
+        let good = XorName::random();
+        let mut proofs = BTreeMap::new();
+        let _ = proofs.insert(names[0], Some(good));
+        let _ = proofs.insert(names[1], Some(good));
+        let _ = proofs.insert(names[2], Some(good));
+        let _ = proofs.insert(names[3], Some(XorName::random()));
+        assert_eq!(
+            judge_proofs(&holders, &proofs),
+            Verdict::Judged(vec![names[3]].into_iter().collect())
+        );
+
+        // Missing chunks and missing answers fail too.
+        let _ = proofs.insert(names[3], None);
+        assert_eq!(
+            judge_proofs(&holders, &proofs),
+            Verdict::Judged(vec![names[3]].into_iter().collect())
+        );
+        let _ = proofs.remove(&names[3]);
+        assert_eq!(
+            judge_proofs(&holders, &proofs),
+            Verdict::Judged(vec![names[3]].into_iter().collect())
+        );
+    }
+
+    #[test]
+    fn proofs_without_majority_are_inconclusive() {
+        let holders: BTreeSet<XorName> = (0..4).map(|_| XorName::random()).collect();
+        let names: Vec<XorName> = holders.iter().copied().collect();
+        let mut proofs = BTreeMap::new();
+        let _ = proofs.insert(names[0], Some(XorName::random()));
+        let _ = proofs.insert(names[1], Some(XorName::random()));
+        assert_eq!(
+            judge_proofs(&holders, &proofs),
+            Verdict::Inconclusive(vec![names[2], names[3]].into_iter().collect())
+        );
+
+        // Even agreeing proofs can't be checked, unless they're from a majority of the holders.
+        let good = XorName::random();
+        let _ = proofs.insert(names[0], Some(good));
+        let _ = proofs.insert(names[1], Some(good));
+        assert_eq!(
+            judge_proofs(&holders, &proofs),
+            Verdict::Inconclusive(vec![names[2], names[3]].into_iter().collect())
+        );
+        let _ = proofs.insert(names[2], Some(good));
+        assert_eq!(
+            judge_proofs(&holders, &proofs),
+            Verdict::Judged(vec![names[3]].into_iter().collect())
+        );
+    }
+
+    #[test]
+    fn deletes_with_forged_origin_are_denied() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let owner = Keypair::new_ed25519(&mut rng);
+        let forger = Keypair::new_ed25519(&mut rng);
+        let address = *Blob::Private(PrivateBlob::new(vec![1, 2, 3], owner.public_key())).address();
+        // The forger claims to be the owner.
+        let origin = EndUser::AllClients(owner.public_key());
+        let msg_id = MessageId::new();
+        let payload = BlobWrite::delete_private_payload(&address, &msg_id)?;
+
+        let forged = forger.sign(&payload);
+        match authorise_deletion(
+            Some(owner.public_key()),
+            &address,
+            &msg_id,
+            &forged,
+            &origin,
+        ) {
+            Err(Error::NetworkData(DtError::AccessDenied(_))) => (),
+            x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+        }
+        // The owner's signature can't be replayed in another message.
+        let signature = owner.sign(&payload);
+        match authorise_deletion(
+            Some(owner.public_key()),
+            &address,
+            &MessageId::new(),
+            &signature,
+            &origin,
+        ) {
+            Err(Error::NetworkData(DtError::AccessDenied(_))) => (),
+            x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+        }
+        // Nor can public blobs be deleted, which have no owner to sign.
+        let public = BlobAddress::Public(*address.name());
+        match authorise_deletion(None, &public, &msg_id, &signature, &origin) {
+            Err(Error::InvalidOperation(_)) => (),
+            x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+        }
+
+        authorise_deletion(
+            Some(owner.public_key()),
+            &address,
+            &msg_id,
+            &signature,
+            &origin,
+        )
+    }
+
+    #[test]
+    fn handed_over_records_cant_plant_owners_or_holders() {
+        let adults: BTreeSet<XorName> = (0..4).map(|_| XorName::random()).collect();
+        let adult = *adults.iter().next().unwrap_or(&XorName::random());
+        let (stranger, owner) = (
+            XorName::random(),
+            PublicKey::from(bls::SecretKey::random().public_key()),
+        );
+        let record = |holders: Vec<XorName>, owner| ChunkMetadata {
+            holders: holders.into_iter().collect(),
+            owner,
+        };
+        let private = BlobAddress::Private(XorName::random());
+        let public = BlobAddress::Public(XorName::random());
+
+        // holders which aren't our adults are dropped
+        let merged = merge_record(
+            &private,
+            None,
+            record(vec![adult, stranger], Some(owner)),
+            &adults,
+        );
+        assert_eq!(merged, Some(record(vec![adult], Some(owner))));
+        assert_eq!(
+            merge_record(&private, None, record(vec![stranger], Some(owner)), &adults),
+            None
+        );
+
+        // the owner must match the address
+        assert_eq!(
+            merge_record(&public, None, record(vec![adult], Some(owner)), &adults),
+            None
+        );
+        assert_eq!(
+            merge_record(&private, None, record(vec![adult], None), &adults),
+            None
+        );
+
+        // and the owner we know of can't be replaced
+        let existing = record(vec![adult], Some(owner));
+        let forger = PublicKey::from(bls::SecretKey::random().public_key());
+        assert_eq!(
+            merge_record(
+                &private,
+                Some(existing),
+                record(vec![adult], Some(forger)),
+                &adults
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn holders_without_stats_rank_as_mean() {
+        let address = BlobAddress::Public(XorName::random());
+        let (fast, slow, unknown) = (XorName::random(), XorName::random(), XorName::random());
+        let mut read_stats = BTreeMap::new();
+        let mut stats = HolderStats::default();
+        stats.record(true, Duration::from_secs(1));
+        let _ = read_stats.insert(fast, stats);
+        let mut stats = HolderStats::default();
+        stats.record(true, Duration::from_secs(3));
+        let _ = read_stats.insert(slow, stats);
+        let holders = vec![fast, slow, unknown].into_iter().collect();
+        assert_eq!(
+            holders_by_preference(&read_stats, &address, holders),
+            vec![fast, unknown, slow]
+        );
+    }
+
+    #[test]
+    fn failed_reads_count_as_timeouts() {
+        let mut stats = HolderStats::default();
+        stats.record(true, Duration::from_secs(2));
+        assert_eq!(stats.latency, Duration::from_secs(2));
+        stats.record(false, Duration::from_secs(1));
+        assert_eq!(
+            stats.latency,
+            (Duration::from_secs(2) * 3 + READ_TIMEOUT) / 4
+        );
+        assert_eq!((stats.successes, stats.failures), (1, 1));
+    }
+}
@@ -9,6 +9,7 @@
 use super::{
     blob_register::BlobRegister, map_storage::MapStorage, sequence_storage::SequenceStorage,
 };
+use crate::Result;
 
 /// The various data type stores,
 /// that are only managed at Elders.
@@ -31,6 +32,12 @@ impl ElderStores {
         }
     }
 
+    /// Closes the Map and Sequence stores, whose chunks are then held as replicas.
+    pub async fn close(self) -> Result<()> {
+        self.map_storage.close().await?;
+        self.sequence_storage.close().await
+    }
+
     pub fn blob_register(&self) -> &BlobRegister {
         &self.blob_register
     }
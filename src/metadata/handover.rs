@@ -0,0 +1,339 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Handover of data to other elders, after a split or when we stop being an elder.
+
+use super::Metadata;
+use crate::{
+    capacity::ChunkMetadata,
+    node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
+    Result,
+};
+use log::{info, warn};
+use sn_data_types::{BlobAddress, Map, MapAddress, PublicKey, Sequence, SequenceAddress};
+use sn_messaging::{
+    client::{Message, NodeCmd, NodeEvent, NodeSystemCmd},
+    Aggregation, DstLocation, MessageId, SrcLocation,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
+use xor_name::{Prefix, XorName};
+
+// How many Blob records are handed over per msg.
+const BLOB_RECORDS_PER_HANDOVER: usize = 1000;
+// How many Map or Sequence chunks are handed over per msg, as these can be large.
+const CHUNKS_PER_HANDOVER: usize = 20;
+// How long the receiving section has to confirm a handover, before it is sent again.
+const HANDOVER_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
+/// A batch of data handed over to another section. We keep the data until that section confirms
+/// that it took it over.
+pub(super) struct PendingHandover {
+    section: Prefix,
+    // Whether the handover is sent by all our elders, for the receiving section to aggregate, or
+    // by us alone.
+    section_source: bool,
+    blobs: Vec<BlobAddress>,
+    maps: Vec<MapAddress>,
+    sequences: Vec<SequenceAddress>,
+    sent: Instant,
+}
+
+impl PendingHandover {
+    fn new(section: Prefix, section_source: bool) -> Self {
+        Self {
+            section,
+            section_source,
+            blobs: vec![],
+            maps: vec![],
+            sequences: vec![],
+            sent: Instant::now(),
+        }
+    }
+
+    // The same batch is handed over by each of our elders, so the first msg id is derived from
+    // its content, for the duplicates to be dropped.
+    fn first_msg_id(&self) -> MessageId {
+        let names = self
+            .blobs
+            .iter()
+            .map(|address| *address.name())
+            .chain(self.maps.iter().map(|address| *address.name()))
+            .chain(self.sequences.iter().map(|address| *address.name()))
+            .chain(std::iter::once(self.section.name()))
+            .collect();
+        MessageId::combine(names)
+    }
+}
+
+impl Metadata {
+    /// Hands the data which our sibling section is now responsible for over to its elders.
+    pub async fn split_section(&mut self, our_prefix: Prefix) -> Result<NodeDuties> {
+        let blobs = self
+            .elder_stores
+            .blob_register()
+            .addresses_outside(&our_prefix)?;
+        let maps = self
+            .elder_stores
+            .map_storage()
+            .keys_outside(&our_prefix)
+            .await;
+        let sequences = self
+            .elder_stores
+            .sequence_storage()
+            .keys_outside(&our_prefix)
+            .await;
+        self.hand_over(our_prefix.sibling(), true, blobs, maps, sequences)
+            .await
+    }
+
+    /// Hands all our data over to the remaining elders of our section, as we are no longer one
+    /// of them. The data is kept until they confirm it, like that handed over on a split.
+    pub async fn hand_over_all(&mut self, our_prefix: Prefix) -> Result<NodeDuties> {
+        let blobs = self.elder_stores.blob_register().addresses()?;
+        let maps = self.elder_stores.map_storage().keys().await;
+        let sequences = self.elder_stores.sequence_storage().keys().await;
+        self.hand_over(our_prefix, false, blobs, maps, sequences)
+            .await
+    }
+
+    async fn hand_over(
+        &mut self,
+        section: Prefix,
+        section_source: bool,
+        blobs: Vec<BlobAddress>,
+        maps: Vec<MapAddress>,
+        sequences: Vec<SequenceAddress>,
+    ) -> Result<NodeDuties> {
+        info!(
+            "Handing over {} Blob records, {} Maps and {} Sequences to {:?}",
+            blobs.len(),
+            maps.len(),
+            sequences.len(),
+            section
+        );
+
+        let mut handovers = vec![];
+        for batch in blobs.chunks(BLOB_RECORDS_PER_HANDOVER) {
+            let mut handover = PendingHandover::new(section, section_source);
+            handover.blobs = batch.to_vec();
+            handovers.push(handover);
+        }
+        for batch in maps.chunks(CHUNKS_PER_HANDOVER) {
+            let mut handover = PendingHandover::new(section, section_source);
+            handover.maps = batch.to_vec();
+            handovers.push(handover);
+        }
+        for batch in sequences.chunks(CHUNKS_PER_HANDOVER) {
+            let mut handover = PendingHandover::new(section, section_source);
+            handover.sequences = batch.to_vec();
+            handovers.push(handover);
+        }
+
+        let mut ops = vec![];
+        for handover in handovers {
+            let msg_id = handover.first_msg_id();
+            ops.push(self.send_handover(&handover, msg_id).await?);
+            let _ = self.handovers.insert(msg_id, handover);
+        }
+        Ok(ops)
+    }
+
+    async fn send_handover(
+        &self,
+        handover: &PendingHandover,
+        msg_id: MessageId,
+    ) -> Result<NodeDuty> {
+        let blob_records = self
+            .elder_stores
+            .blob_register()
+            .records(&handover.blobs)?
+            .into_iter()
+            .map(|(address, metadata)| (address, (metadata.holders, metadata.owner)))
+            .collect();
+        let maps = self
+            .elder_stores
+            .map_storage()
+            .chunks(&handover.maps)
+            .await?;
+        let sequences = self
+            .elder_stores
+            .sequence_storage()
+            .chunks(&handover.sequences)
+            .await?;
+        let dst = DstLocation::Section(handover.section.name());
+        let (section_source, aggregation) = if handover.section_source {
+            (true, Aggregation::AtDestination)
+        } else {
+            (false, Aggregation::None)
+        };
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::NodeCmd {
+                cmd: NodeCmd::System(NodeSystemCmd::ReceiveDataHandover {
+                    blob_records,
+                    maps,
+                    sequences,
+                }),
+                id: msg_id,
+                target_section_pk: None,
+            },
+            section_source,
+            dst,
+            aggregation,
+        }))
+    }
+
+    /// Takes over data handed over by other elders, and confirms it to the sender. Handovers are
+    /// only taken from a section, whose elders all sent it, or from a single elder of ours, and
+    /// only the data which our section is responsible for is kept. The confirmation is sent by
+    /// all our elders, for the sender to aggregate.
+    pub async fn receive_handover(
+        &mut self,
+        blob_records: BTreeMap<BlobAddress, (BTreeSet<XorName>, Option<PublicKey>)>,
+        maps: Vec<Map>,
+        sequences: Vec<Sequence>,
+        msg_id: MessageId,
+        sender: SrcLocation,
+        our_prefix: Prefix,
+    ) -> Result<NodeDuties> {
+        let dst = match sender {
+            SrcLocation::Section(name) => DstLocation::Section(name),
+            SrcLocation::Node(name) if self.is_known_elder(&name) => DstLocation::Node(name),
+            _ => {
+                warn!("Dropping handover {:?} from {:?}", msg_id, sender);
+                return Ok(vec![]);
+            }
+        };
+        let records = blob_records
+            .into_iter()
+            .filter(|(address, _)| our_prefix.matches(address.name()))
+            .map(|(address, (holders, owner))| (address, ChunkMetadata { holders, owner }))
+            .collect();
+        self.elder_stores
+            .blob_register_mut()
+            .receive_records(records)
+            .await?;
+        // subscribers to the Maps we hold are notified of the entries the handover changes
+        let mut ops = vec![];
+        for map in maps
+            .into_iter()
+            .filter(|map| our_prefix.matches(map.name()))
+        {
+            ops.extend(
+                self.elder_stores
+                    .map_storage_mut()
+                    .receive_chunk(map, msg_id)
+                    .await?,
+            );
+        }
+        for sequence in sequences
+            .into_iter()
+            .filter(|sequence| our_prefix.matches(sequence.name()))
+        {
+            self.elder_stores
+                .sequence_storage_mut()
+                .receive_chunk(sequence)
+                .await?;
+        }
+        ops.push(NodeDuty::Send(OutgoingMsg {
+            msg: Message::NodeEvent {
+                event: NodeEvent::DataHandoverCompleted,
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: true,
+            dst,
+            aggregation: Aggregation::AtDestination,
+        }));
+        Ok(ops)
+    }
+
+    /// Called when the receiving section confirms a handover, after which we drop the data we
+    /// handed over. Only a confirmation aggregated from the elders of that section counts, as a
+    /// single node could confirm data it never took over.
+    pub async fn complete_handover(
+        &mut self,
+        correlation_id: MessageId,
+        sender: SrcLocation,
+    ) -> Result<()> {
+        let handover = match self.handovers.remove(&correlation_id) {
+            Some(handover) => handover,
+            None => return Ok(()),
+        };
+        let confirmed = match sender {
+            SrcLocation::Section(name) => handover.section.matches(&name),
+            _ => false,
+        };
+        if !confirmed {
+            warn!(
+                "Handover {:?} confirmed by {:?}, which is not the receiving section",
+                correlation_id, sender
+            );
+            let _ = self.handovers.insert(correlation_id, handover);
+            return Ok(());
+        }
+        self.elder_stores
+            .blob_register_mut()
+            .remove_records(&handover.blobs)
+            .await?;
+        self.elder_stores
+            .map_storage_mut()
+            .remove_chunks(&handover.maps)
+            .await?;
+        self.elder_stores
+            .sequence_storage_mut()
+            .remove_chunks(&handover.sequences)
+            .await?;
+        info!(
+            "Handover {:?} to {:?} completed, {} handovers remaining",
+            correlation_id,
+            handover.section,
+            self.handovers.len()
+        );
+        Ok(())
+    }
+
+    /// Whether any handover is still awaiting confirmation.
+    pub fn has_pending_handovers(&self) -> bool {
+        !self.handovers.is_empty()
+    }
+
+    /// Takes the data we were handing over on demotion back, as we were promoted again before
+    /// the remaining elders confirmed it.
+    pub async fn resume(&mut self) {
+        self.handovers.clear();
+        self.update_elders().await;
+    }
+
+    /// Sends the handovers which the receiving section didn't confirm in time again.
+    pub async fn retry_handovers(&mut self) -> Result<NodeDuties> {
+        let now = Instant::now();
+        let expired: Vec<MessageId> = self
+            .handovers
+            .iter()
+            .filter(|(_, handover)| now.duration_since(handover.sent) >= HANDOVER_TIMEOUT)
+            .map(|(msg_id, _)| *msg_id)
+            .collect();
+        let mut ops = vec![];
+        for msg_id in expired {
+            if let Some(mut handover) = self.handovers.remove(&msg_id) {
+                warn!("Handover {:?} was not confirmed, sending it again", msg_id);
+                // The section may have seen the first msg, so this one needs a new id, which
+                // all our elders derive alike, for the receiving section to aggregate it.
+                let msg_id = MessageId::in_response_to(&msg_id);
+                ops.push(self.send_handover(&handover, msg_id).await?);
+                handover.sent = Instant::now();
+                let _ = self.handovers.insert(msg_id, handover);
+            }
+        }
+        Ok(ops)
+    }
+}
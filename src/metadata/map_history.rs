@@ -0,0 +1,162 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Earlier versions of Maps, which can still be read after later ones replace them.
+//!
+//! The version of a Map is that of its fields, i.e. its owner and permissions. The snapshot kept
+//! of a version is the Map as it was when the next version replaced it, entries included.
+
+use crate::{
+    chunk_store::{MapHistoryChunkStore, MapSnapshot, MapVersion, StoreConfig, UsedSpace},
+    Result,
+};
+use log::info;
+use sn_data_types::{Map, MapAddress};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+/// The last `depth` versions of each Map before its current one.
+pub(super) struct MapHistory {
+    chunks: MapHistoryChunkStore,
+    depth: u64,
+    // The versions we hold of each Map.
+    versions: BTreeMap<MapAddress, BTreeSet<u64>>,
+}
+
+impl MapHistory {
+    pub(super) async fn new(
+        path: &Path,
+        used_space: UsedSpace,
+        config: &StoreConfig,
+        depth: u64,
+    ) -> Result<Self> {
+        let chunks = MapHistoryChunkStore::new(path, used_space, config).await?;
+        let mut versions = BTreeMap::<_, BTreeSet<_>>::new();
+        for id in chunks.keys().await {
+            let _ = versions.entry(id.address).or_default().insert(id.version);
+        }
+        let mut history = Self {
+            chunks,
+            depth,
+            versions,
+        };
+        // the depth may have been lowered since the node last ran
+        let addresses: Vec<_> = history.versions.keys().copied().collect();
+        for address in addresses {
+            history.prune(&address).await?;
+        }
+        Ok(history)
+    }
+
+    pub(super) async fn close(self) -> Result<()> {
+        self.chunks.close().await
+    }
+
+    /// Keeps `map`, which a later version of it is replacing, and drops the versions of it
+    /// which are then older than the history depth.
+    pub(super) async fn record(&mut self, map: Map) -> Result<()> {
+        if self.depth == 0 {
+            return Ok(());
+        }
+        let (address, version) = (*map.address(), map.version());
+        self.chunks.put(&MapSnapshot::new(map)).await?;
+        let _ = self.versions.entry(address).or_default().insert(version);
+        self.prune(&address).await
+    }
+
+    /// The Map at `address` as of `version`, if we still hold it.
+    pub(super) async fn get(&self, address: MapAddress, version: u64) -> Result<Map> {
+        let snapshot = self.chunks.get(&MapVersion { address, version }).await?;
+        Ok(snapshot.into_map())
+    }
+
+    /// Drops all versions of the Map at `address`, after it is deleted or handed over.
+    pub(super) async fn remove(&mut self, address: &MapAddress) -> Result<()> {
+        for version in self.versions.remove(address).unwrap_or_default() {
+            self.chunks
+                .delete(&MapVersion {
+                    address: *address,
+                    version,
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Drops the oldest versions of the Map at `address` beyond the history depth.
+    async fn prune(&mut self, address: &MapAddress) -> Result<()> {
+        let versions = match self.versions.get_mut(address) {
+            Some(versions) => versions,
+            None => return Ok(()),
+        };
+        let excess = versions.len().saturating_sub(self.depth as usize);
+        let pruned: Vec<_> = versions.iter().take(excess).copied().collect();
+        for version in &pruned {
+            let _ = versions.remove(version);
+        }
+        if versions.is_empty() {
+            let _ = self.versions.remove(address);
+        }
+        if !pruned.is_empty() {
+            info!("Pruning {} versions of {:?}", pruned.len(), address);
+        }
+        for version in pruned {
+            self.chunks
+                .delete(&MapVersion {
+                    address: *address,
+                    version,
+                })
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use sn_data_types::{Keypair, MapPermissionSet, UnseqMap};
+    use tempdir::TempDir;
+    use xor_name::XorName;
+
+    #[tokio::test]
+    async fn history_keeps_the_latest_versions() -> Result<()> {
+        let root =
+            TempDir::new("map_history").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let used_space = UsedSpace::new(u64::MAX);
+        let mut history =
+            MapHistory::new(root.path(), used_space.clone(), &StoreConfig::default(), 2).await?;
+        let owner = Keypair::new_ed25519(&mut rand::thread_rng()).public_key();
+        let mut data = UnseqMap::new(XorName::random(), 10, owner);
+        let address = *data.address();
+        for version in 1..=3 {
+            history.record(Map::from(data.clone())).await?;
+            data.set_user_permissions(owner, MapPermissionSet::new(), version)?;
+        }
+
+        assert!(matches!(
+            history.get(address, 0).await,
+            Err(Error::NoSuchChunk)
+        ));
+        assert_eq!(history.get(address, 1).await?.version(), 1);
+        assert_eq!(history.get(address, 2).await?.version(), 2);
+
+        // versions are counted in the used space, until they are dropped
+        assert!(used_space.total().await > 0);
+        history.remove(&address).await?;
+        assert!(matches!(
+            history.get(address, 2).await,
+            Err(Error::NoSuchChunk)
+        ));
+        assert_eq!(used_space.total().await, 0);
+        Ok(())
+    }
+}
@@ -6,40 +6,196 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use super::{
+    map_history::MapHistory,
+    pagination,
+    replication::{ReplicaAddress, ReplicaUpdate},
+    subscriptions::{Subscriptions, SUBSCRIPTION_TTL},
+};
 use crate::{
-    chunk_store::{MapChunkStore, UsedSpace},
+    chunk_store::{CacheStats, MapChunkStore, StoreConfig, UsedSpace},
+    chunks::merge_maps,
     error::convert_to_error_message,
-    node_ops::{NodeDuty, OutgoingMsg},
+    node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
     Error, Network, Result,
 };
-use log::info;
+use log::{info, warn};
 use sn_data_types::{
-    Error as DtError, Map, MapAction, MapAddress, MapEntryActions, MapPermissionSet, MapValue,
-    PublicKey, Result as NdResult,
+    Error as DtError, Map, MapAction, MapAddress, MapEntries, MapEntryActions, MapPermissionSet,
+    MapSeqEntries, MapUnseqEntries, MapValue, MapValues, PublicKey,
 };
 use sn_messaging::{
-    client::{CmdError, MapRead, MapWrite, Message, QueryResponse},
+    client::{CmdError, Event, MapRead, MapWrite, Message, QueryResponse},
     Aggregation, DstLocation, EndUser, MessageId, SrcLocation,
 };
 
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fmt::{self, Display, Formatter},
+    ops::Bound,
     path::Path,
 };
+use xor_name::Prefix;
 
 /// Operations over the data type Map.
 pub(super) struct MapStorage {
     chunks: MapChunkStore,
+    // Earlier versions of our Maps.
+    history: MapHistory,
+    subscriptions: Subscriptions<MapAddress>,
 }
 
 impl MapStorage {
-    pub(super) async fn new(path: &Path, used_space: UsedSpace) -> Result<Self> {
-        let chunks = MapChunkStore::new(path, used_space).await?;
-        Ok(Self { chunks })
+    pub(super) async fn new(
+        path: &Path,
+        used_space: UsedSpace,
+        config: &StoreConfig,
+        history_depth: u64,
+    ) -> Result<Self> {
+        let chunks = MapChunkStore::new(path, used_space.clone(), config).await?;
+        let history = MapHistory::new(path, used_space, config, history_depth).await?;
+        Ok(Self {
+            chunks,
+            history,
+            subscriptions: Subscriptions::new(),
+        })
+    }
+
+    pub(super) async fn close(self) -> Result<()> {
+        self.chunks.close().await?;
+        self.history.close().await
+    }
+
+    pub(super) fn cache_stats(&self) -> CacheStats {
+        self.chunks.cache_stats()
+    }
+
+    /// The Maps which `prefix` doesn't cover, and which thus belong to our sibling section after
+    /// a split.
+    pub(super) async fn keys_outside(&self, prefix: &Prefix) -> Vec<MapAddress> {
+        self.keys()
+            .await
+            .into_iter()
+            .filter(|address| !prefix.matches(address.name()))
+            .collect()
+    }
+
+    /// The addresses of all Maps we hold.
+    pub(super) async fn keys(&self) -> Vec<MapAddress> {
+        self.chunks.keys().await
+    }
+
+    /// The Maps at `addresses` which we still hold.
+    pub(super) async fn chunks(&self, addresses: &[MapAddress]) -> Result<Vec<Map>> {
+        let mut maps = vec![];
+        for address in addresses {
+            if self.chunks.has(address).await {
+                maps.push(self.chunks.get(address).await?);
+            }
+        }
+        Ok(maps)
+    }
+
+    /// Stores a Map handed over by another section, merged into our copy if we hold one, as
+    /// entry edits don't show in the version. Subscribers are notified of the entries this
+    /// changes, as of the handover msg with `msg_id`.
+    pub(super) async fn receive_chunk(
+        &mut self,
+        map: Map,
+        msg_id: MessageId,
+    ) -> Result<NodeDuties> {
+        match self.chunks.get(map.address()).await {
+            Ok(existing) => self.merge(existing, &map, msg_id).await,
+            Err(_) => {
+                self.chunks.put(&map).await?;
+                Ok(vec![])
+            }
+        }
+    }
+
+    /// Merges in a replica which an adult found to be ahead of our copy, sent in the msg with
+    /// `msg_id`. Replicas of Maps we don't hold are ignored, as we may have deleted them since.
+    /// Only the owner and those it permits can change the permissions, so a replica whose owner
+    /// or permissions differ from ours is dropped; a replica can thus only bring the sequenced
+    /// entries we hold to later versions.
+    pub(super) async fn merge_replica(
+        &mut self,
+        map: Map,
+        msg_id: MessageId,
+    ) -> Result<NodeDuties> {
+        let existing = match self.chunks.get(map.address()).await {
+            Ok(existing) => existing,
+            Err(_) => return Ok(vec![]),
+        };
+        if map.shell() != existing.shell() {
+            warn!(
+                "Dropping replica of {:?}, whose owner or permissions differ from ours",
+                map.address()
+            );
+            return Ok(vec![]);
+        }
+        self.merge(existing, &map, msg_id).await
+    }
+
+    async fn merge(&mut self, existing: Map, map: &Map, msg_id: MessageId) -> Result<NodeDuties> {
+        let merged = merge_maps(&existing, map)?;
+        if merged == existing {
+            return Ok(vec![]);
+        }
+        let keys = differing_keys(&existing, &merged);
+        self.replace(existing, &merged).await?;
+        Ok(self.notify(&merged, keys, msg_id))
+    }
+
+    // Notifies the subscribers to `map` which can still read it of its new version and the `keys`
+    // of the changed entries, caused by the msg with `msg_id`.
+    fn notify(&mut self, map: &Map, keys: BTreeSet<Vec<u8>>, msg_id: MessageId) -> NodeDuties {
+        let address = map.address();
+        let event = Event::MapChanged {
+            address: *address,
+            version: map.version(),
+            keys,
+        };
+        self.subscriptions
+            .notify(address, *address.name(), event, msg_id, |subscriber| {
+                map.check_permissions(MapAction::Read, subscriber.id())
+                    .is_ok()
+            })
+    }
+
+    // Stores `map` in place of `previous`, which is kept in the history if `map` moves the Map to
+    // a new version. Entry edits don't change the version, so each version is kept as it was
+    // when the next one replaced it, which is the state reads as of that version get.
+    async fn replace(&mut self, previous: Map, map: &Map) -> Result<()> {
+        self.chunks.put(map).await?;
+        let previous_version = previous.version();
+        if previous_version != map.version() {
+            // the write is done, whether or not the earlier version can be kept
+            if let Err(error) = self.history.record(previous).await {
+                warn!(
+                    "Could not keep version {} of {:?}: {}",
+                    previous_version,
+                    map.address(),
+                    error
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes Maps which another section has taken over.
+    pub(super) async fn remove_chunks(&mut self, addresses: &[MapAddress]) -> Result<()> {
+        for address in addresses {
+            if self.chunks.has(address).await {
+                self.chunks.delete(address).await?;
+            }
+            self.history.remove(address).await?;
+        }
+        Ok(())
     }
 
     pub(super) async fn read(
-        &self,
+        &mut self,
         read: &MapRead,
         msg_id: MessageId,
         origin: EndUser,
@@ -49,27 +205,65 @@ impl MapStorage {
             Get(address) => self.get(*address, msg_id, origin).await,
             GetValue { address, ref key } => self.get_value(*address, key, msg_id, origin).await,
             GetShell(address) => self.get_shell(*address, msg_id, origin).await,
+            GetShellAt { address, version } => {
+                self.get_shell_at(*address, *version, msg_id, origin).await
+            }
+            GetValueAt {
+                address,
+                ref key,
+                version,
+            } => {
+                self.get_value_at(*address, key, *version, msg_id, origin)
+                    .await
+            }
             GetVersion(address) => self.get_version(*address, msg_id, origin).await,
             ListEntries(address) => self.list_entries(*address, msg_id, origin).await,
             ListKeys(address) => self.list_keys(*address, msg_id, origin).await,
             ListValues(address) => self.list_values(*address, msg_id, origin).await,
+            ListEntriesPage {
+                address,
+                start,
+                limit,
+            } => {
+                self.list_entries_page(*address, start.as_ref(), *limit, msg_id, origin)
+                    .await
+            }
+            ListKeysPage {
+                address,
+                start,
+                limit,
+            } => {
+                self.list_keys_page(*address, start.as_ref(), *limit, msg_id, origin)
+                    .await
+            }
+            ListValuesPage {
+                address,
+                start,
+                limit,
+            } => {
+                self.list_values_page(*address, start.as_ref(), *limit, msg_id, origin)
+                    .await
+            }
             ListPermissions(address) => self.list_permissions(*address, msg_id, origin).await,
             ListUserPermissions { address, user } => {
                 self.list_user_permissions(*address, *user, msg_id, origin)
                     .await
             }
+            Subscribe(address) => self.subscribe(*address, msg_id, origin).await,
+            Unsubscribe(address) => Ok(self.unsubscribe(*address, msg_id, origin)),
         }
     }
 
+    /// Applies `write`, returning the new state of the Map along with the duties, if it changed.
     pub(super) async fn write(
         &mut self,
         write: MapWrite,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
         use MapWrite::*;
         match write {
-            New(data) => self.create(&data, msg_id, origin).await,
+            New(data) => self.create(data, msg_id, origin).await,
             Delete(address) => self.delete(address, msg_id, origin).await,
             SetUserPermissions {
                 address,
@@ -89,6 +283,22 @@ impl MapStorage {
                     .await
             }
             Edit { address, changes } => self.edit_entries(address, changes, msg_id, origin).await,
+            Transaction {
+                address,
+                expected_version,
+                expected_entries,
+                changes,
+            } => {
+                self.transact(
+                    address,
+                    expected_version,
+                    expected_entries,
+                    changes,
+                    msg_id,
+                    origin,
+                )
+                .await
+            }
         }
     }
 
@@ -96,8 +306,13 @@ impl MapStorage {
     /// Returns `Some(Result<..>)` if the flow should be continued, returns
     /// `None` if there was a logic error encountered and the flow should be
     /// terminated.
-    fn get_chunk(&self, address: &MapAddress, origin: EndUser, action: MapAction) -> Result<Map> {
-        self.chunks.get(&address).and_then(move |map| {
+    async fn get_chunk(
+        &self,
+        address: &MapAddress,
+        origin: EndUser,
+        action: MapAction,
+    ) -> Result<Map> {
+        self.chunks.get(&address).await.and_then(move |map| {
             map.check_permissions(action, origin.id())
                 .map(move |_| map)
                 .map_err(|error| error.into())
@@ -105,35 +320,65 @@ impl MapStorage {
     }
 
     /// Get Map from the chunk store, update it, and overwrite the stored chunk.
+    /// The Map is kept in the history if the update moves it to a new version.
+    /// Subscribers are then notified of the new version and the `keys` of the changed entries.
     async fn edit_chunk<F>(
         &mut self,
         address: &MapAddress,
+        keys: BTreeSet<Vec<u8>>,
         origin: EndUser,
         msg_id: MessageId,
         mutation_fn: F,
-    ) -> Result<NodeDuty>
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)>
     where
-        F: FnOnce(Map) -> NdResult<Map>,
+        F: FnOnce(Map) -> Result<Map>,
     {
-        let result = match self.chunks.get(address) {
-            Ok(data) => match mutation_fn(data) {
-                Ok(map) => self.chunks.put(&map).await,
-                Err(error) => Err(error.into()),
-            },
+        let result = match self.chunks.get(address).await {
+            Ok(data) => {
+                let previous = data.clone();
+                match mutation_fn(data) {
+                    Ok(map) => self.replace(previous, &map).await.map(|()| map),
+                    Err(error) => Err(error),
+                }
+            }
             Err(error) => Err(error),
         };
 
-        self.ok_or_error(result, msg_id, origin).await
+        let map = match result {
+            Ok(map) => map,
+            Err(error) => {
+                return Ok((
+                    vec![self.ok_or_error(Err(error), msg_id, origin).await?],
+                    None,
+                ))
+            }
+        };
+        info!("MapStorage: Writing chunk PASSED!");
+        let ops = self.notify(&map, keys, msg_id);
+        Ok((ops, Some(ReplicaUpdate::Map(map))))
     }
 
     /// Put Map.
-    async fn create(&mut self, data: &Map, msg_id: MessageId, origin: EndUser) -> Result<NodeDuty> {
-        let result = if self.chunks.has(data.address()) {
+    async fn create(
+        &mut self,
+        data: Map,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
+        let result = if self.chunks.has(data.address()).await {
             Err(Error::DataExists)
         } else {
             self.chunks.put(&data).await
         };
-        self.ok_or_error(result, msg_id, origin).await
+        let update = if result.is_ok() {
+            Some(ReplicaUpdate::Map(data))
+        } else {
+            None
+        };
+        Ok((
+            vec![self.ok_or_error(result, msg_id, origin).await?],
+            update,
+        ))
     }
 
     async fn delete(
@@ -141,12 +386,19 @@ impl MapStorage {
         address: MapAddress,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        let result = match self.chunks.get(&address) {
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
+        let result = match self.chunks.get(&address).await {
             Ok(map) => match map.check_is_owner(origin.id()) {
                 Ok(()) => {
                     info!("Deleting Map");
-                    self.chunks.delete(&address).await
+                    let result = match self.chunks.delete(&address).await {
+                        Ok(()) => self.history.remove(&address).await,
+                        Err(error) => Err(error),
+                    };
+                    if result.is_ok() {
+                        self.subscriptions.remove(&address);
+                    }
+                    result
                 }
                 Err(_e) => {
                     info!("Error: Delete Map called by non-owner");
@@ -155,8 +407,15 @@ impl MapStorage {
             },
             Err(error) => Err(error),
         };
-
-        self.ok_or_error(result, msg_id, origin).await
+        let update = if result.is_ok() {
+            Some(ReplicaUpdate::Deleted(ReplicaAddress::Map(address)))
+        } else {
+            None
+        };
+        Ok((
+            vec![self.ok_or_error(result, msg_id, origin).await?],
+            update,
+        ))
     }
 
     /// Set Map user permissions.
@@ -168,12 +427,18 @@ impl MapStorage {
         version: u64,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        self.edit_chunk(&address, origin, msg_id, move |mut data| {
-            data.check_permissions(MapAction::ManagePermissions, origin.id())?;
-            data.set_user_permissions(user, permissions.clone(), version)?;
-            Ok(data)
-        })
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
+        self.edit_chunk(
+            &address,
+            BTreeSet::new(),
+            origin,
+            msg_id,
+            move |mut data| {
+                data.check_permissions(MapAction::ManagePermissions, origin.id())?;
+                data.set_user_permissions(user, permissions.clone(), version)?;
+                Ok(data)
+            },
+        )
         .await
     }
 
@@ -185,12 +450,18 @@ impl MapStorage {
         version: u64,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        self.edit_chunk(&address, origin, msg_id, move |mut data| {
-            data.check_permissions(MapAction::ManagePermissions, origin.id())?;
-            data.del_user_permissions(user, version)?;
-            Ok(data)
-        })
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
+        self.edit_chunk(
+            &address,
+            BTreeSet::new(),
+            origin,
+            msg_id,
+            move |mut data| {
+                data.check_permissions(MapAction::ManagePermissions, origin.id())?;
+                data.del_user_permissions(user, version)?;
+                Ok(data)
+            },
+        )
         .await
     }
 
@@ -201,14 +472,37 @@ impl MapStorage {
         actions: MapEntryActions,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        self.edit_chunk(&address, origin, msg_id, move |mut data| {
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
+        let keys = changed_keys(&actions);
+        self.edit_chunk(&address, keys, origin, msg_id, move |mut data| {
             data.mutate_entries(actions, origin.id())?;
             Ok(data)
         })
         .await
     }
 
+    /// Edit Map entries as one transaction: all of `changes` are applied if the Map and its
+    /// entries have the expected versions, and none of them otherwise.
+    async fn transact(
+        &mut self,
+        address: MapAddress,
+        expected_version: Option<u64>,
+        expected_entries: BTreeMap<Vec<u8>, Option<u64>>,
+        changes: MapEntryActions,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
+        let keys = changed_keys(&changes);
+        self.edit_chunk(&address, keys, origin, msg_id, move |mut data| {
+            // the versions are only disclosed to those who can read them
+            data.check_permissions(MapAction::Read, origin.id())?;
+            check_expected_versions(&data, expected_version, &expected_entries)?;
+            data.mutate_entries(changes, origin.id())?;
+            Ok(data)
+        })
+        .await
+    }
+
     /// Get entire Map.
     async fn get(
         &self,
@@ -216,7 +510,7 @@ impl MapStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let result = match self.get_chunk(&address, origin, MapAction::Read) {
+        let result = match self.get_chunk(&address, origin, MapAction::Read).await {
             Ok(res) => Ok(res),
             Err(error) => Err(convert_to_error_message(error)?),
         };
@@ -243,6 +537,7 @@ impl MapStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(&address, origin, MapAction::Read)
+            .await
             .map(|data| data.shell())
         {
             Ok(res) => Ok(res),
@@ -271,6 +566,7 @@ impl MapStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(&address, origin, MapAction::Read)
+            .await
             .map(|data| data.version())
         {
             Ok(res) => Ok(res),
@@ -298,7 +594,7 @@ impl MapStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let res = self.get_chunk(&address, origin, MapAction::Read);
+        let res = self.get_chunk(&address, origin, MapAction::Read).await;
         let result = match res.and_then(|data| match data {
             Map::Seq(map) => map
                 .get(key)
@@ -328,6 +624,95 @@ impl MapStorage {
         }))
     }
 
+    /// Get the Map shell as of an earlier `version`, which requires permission to read the
+    /// Map as it is now.
+    async fn get_shell_at(
+        &self,
+        address: MapAddress,
+        version: u64,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        let result = match self
+            .get_version_of(address, version, origin)
+            .await
+            .map(|data| data.shell())
+        {
+            Ok(res) => Ok(res),
+            Err(error) => Err(convert_to_error_message(error)?),
+        };
+
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::GetMapShell(result),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to a response..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        }))
+    }
+
+    /// Get a Map value as of an earlier `version`, which requires permission to read the Map
+    /// as it is now.
+    async fn get_value_at(
+        &self,
+        address: MapAddress,
+        key: &[u8],
+        version: u64,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        let res = self.get_version_of(address, version, origin).await;
+        let result = match res.and_then(|data| match data {
+            Map::Seq(map) => map
+                .get(key)
+                .cloned()
+                .map(MapValue::from)
+                .ok_or(Error::NetworkData(DtError::NoSuchEntry)),
+            Map::Unseq(map) => map
+                .get(key)
+                .cloned()
+                .map(MapValue::from)
+                .ok_or(Error::NetworkData(DtError::NoSuchEntry)),
+        }) {
+            Ok(res) => Ok(res),
+            Err(error) => Err(convert_to_error_message(error)?),
+        };
+
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::GetMapValue(result),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to a response..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        }))
+    }
+
+    // The Map at `address` as of `version`, which is the current Map if it has that version.
+    // Versions which are pruned, or yet to come, are reported as missing.
+    async fn get_version_of(
+        &self,
+        address: MapAddress,
+        version: u64,
+        origin: EndUser,
+    ) -> Result<Map> {
+        let map = self.get_chunk(&address, origin, MapAction::Read).await?;
+        if map.version() == version {
+            Ok(map)
+        } else if version < map.version() {
+            self.history.get(address, version).await
+        } else {
+            Err(Error::NoSuchChunk)
+        }
+    }
+
     /// Get Map keys.
     async fn list_keys(
         &self,
@@ -337,6 +722,7 @@ impl MapStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(&address, origin, MapAction::Read)
+            .await
             .map(|data| data.keys())
         {
             Ok(res) => Ok(res),
@@ -363,7 +749,7 @@ impl MapStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let res = self.get_chunk(&address, origin, MapAction::Read);
+        let res = self.get_chunk(&address, origin, MapAction::Read).await;
         let result = match res.map(|data| match data {
             Map::Seq(map) => map.values().into(),
             Map::Unseq(map) => map.values().into(),
@@ -392,7 +778,7 @@ impl MapStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let res = self.get_chunk(&address, origin, MapAction::Read);
+        let res = self.get_chunk(&address, origin, MapAction::Read).await;
         let result = match res.map(|data| match data {
             Map::Seq(map) => map.entries().clone().into(),
             Map::Unseq(map) => map.entries().clone().into(),
@@ -414,6 +800,111 @@ impl MapStorage {
         }))
     }
 
+    /// Get a page of Map entries, in key order, from the `start` key on. The key the next page
+    /// starts from is returned along with the page.
+    async fn list_entries_page(
+        &self,
+        address: MapAddress,
+        start: Option<&Vec<u8>>,
+        limit: u64,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        let res = self.get_chunk(&address, origin, MapAction::Read).await;
+        let result = match res.map(|data| entries_page(&data, start, limit)) {
+            Ok(res) => Ok(res),
+            Err(error) => Err(convert_to_error_message(error)?),
+        };
+
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::ListMapEntriesPage(result),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to a response..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        }))
+    }
+
+    /// Get a page of Map keys, in order, from the `start` key on. The key the next page starts
+    /// from is returned along with the page.
+    async fn list_keys_page(
+        &self,
+        address: MapAddress,
+        start: Option<&Vec<u8>>,
+        limit: u64,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        let res = self.get_chunk(&address, origin, MapAction::Read).await;
+        let result = match res.map(|data| {
+            let (entries, next) = entries_page(&data, start, limit);
+            let keys = match entries {
+                MapEntries::Seq(entries) => entries.into_keys().collect(),
+                MapEntries::Unseq(entries) => entries.into_keys().collect(),
+            };
+            (keys, next)
+        }) {
+            Ok(res) => Ok(res),
+            Err(error) => Err(convert_to_error_message(error)?),
+        };
+
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::ListMapKeysPage(result),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to a response..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        }))
+    }
+
+    /// Get a page of Map values, in the order of their keys, from the `start` key on. The key the
+    /// next page starts from is returned along with the page.
+    async fn list_values_page(
+        &self,
+        address: MapAddress,
+        start: Option<&Vec<u8>>,
+        limit: u64,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        let res = self.get_chunk(&address, origin, MapAction::Read).await;
+        let result = match res.map(|data| {
+            let (entries, next) = entries_page(&data, start, limit);
+            let values = match entries {
+                MapEntries::Seq(entries) => {
+                    MapValues::from(entries.into_values().collect::<Vec<_>>())
+                }
+                MapEntries::Unseq(entries) => {
+                    MapValues::from(entries.into_values().collect::<Vec<_>>())
+                }
+            };
+            (values, next)
+        }) {
+            Ok(res) => Ok(res),
+            Err(error) => Err(convert_to_error_message(error)?),
+        };
+
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::ListMapValuesPage(result),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to a response..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        }))
+    }
+
     /// Get Map permissions.
     async fn list_permissions(
         &self,
@@ -423,6 +914,7 @@ impl MapStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(&address, origin, MapAction::Read)
+            .await
             .map(|data| data.permissions())
         {
             Ok(res) => Ok(res),
@@ -452,6 +944,7 @@ impl MapStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(&address, origin, MapAction::Read)
+            .await
             .and_then(|data| {
                 data.user_permissions(&user)
                     .map_err(|e| e.into())
@@ -474,6 +967,53 @@ impl MapStorage {
         }))
     }
 
+    /// Subscribe to the changes of a Map, which requires permission to read it. Responds with
+    /// the seconds the subscription lasts unless renewed.
+    async fn subscribe(
+        &mut self,
+        address: MapAddress,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        let result = match self
+            .get_chunk(&address, origin, MapAction::Read)
+            .await
+            .and_then(|_| self.subscriptions.subscribe(address, origin))
+        {
+            Ok(()) => Ok(SUBSCRIPTION_TTL.as_secs()),
+            Err(error) => Err(convert_to_error_message(error)?),
+        };
+
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::Subscribe(result),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to a response..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        }))
+    }
+
+    /// Unsubscribe from the changes of a Map.
+    fn unsubscribe(&mut self, address: MapAddress, msg_id: MessageId, origin: EndUser) -> NodeDuty {
+        self.subscriptions.unsubscribe(&address, &origin);
+
+        NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::Unsubscribe(Ok(())),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to a response..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        })
+    }
+
     async fn ok_or_error(
         &self,
         result: Result<()>,
@@ -502,8 +1042,245 @@ impl MapStorage {
     }
 }
 
+/// The keys of the entries which `actions` insert, update or delete.
+fn changed_keys(actions: &MapEntryActions) -> BTreeSet<Vec<u8>> {
+    match actions {
+        MapEntryActions::Seq(actions) => actions.actions().keys().cloned().collect(),
+        MapEntryActions::Unseq(actions) => actions.actions().keys().cloned().collect(),
+    }
+}
+
+/// The keys of the entries which differ between two copies of a Map, including those held by
+/// one of them only.
+fn differing_keys(previous: &Map, map: &Map) -> BTreeSet<Vec<u8>> {
+    fn differing<V: PartialEq>(
+        previous: &BTreeMap<Vec<u8>, V>,
+        entries: &BTreeMap<Vec<u8>, V>,
+    ) -> BTreeSet<Vec<u8>> {
+        previous
+            .keys()
+            .chain(entries.keys())
+            .filter(|key| previous.get(*key) != entries.get(*key))
+            .cloned()
+            .collect()
+    }
+    match (previous, map) {
+        (Map::Seq(previous), Map::Seq(map)) => differing(previous.entries(), map.entries()),
+        (Map::Unseq(previous), Map::Unseq(map)) => differing(previous.entries(), map.entries()),
+        // the kind is part of the address, so copies of a Map are of the same kind
+        _ => BTreeSet::new(),
+    }
+}
+
+/// Checks that `map` has the `expected_version`, if any, and that its entries have the
+/// `expected_entries` versions, `None` standing for an absent entry. Entries of unsequenced Maps
+/// have no versions, so none can be expected of them.
+///
+/// Returns `Error::MapTransactionConflict` with the actual versions, for all checks which fail.
+fn check_expected_versions(
+    map: &Map,
+    expected_version: Option<u64>,
+    expected_entries: &BTreeMap<Vec<u8>, Option<u64>>,
+) -> Result<()> {
+    let version = match expected_version {
+        Some(expected) if expected != map.version() => Some(map.version()),
+        _ => None,
+    };
+    let mut entries = BTreeMap::new();
+    for (key, expected) in expected_entries {
+        let actual = match map {
+            Map::Seq(map) => map.get(key).map(|value| value.version),
+            Map::Unseq(_) => return Err(Error::NetworkData(DtError::InvalidOperation)),
+        };
+        if actual != *expected {
+            let _ = entries.insert(key.clone(), actual);
+        }
+    }
+    if version.is_none() && entries.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::MapTransactionConflict { version, entries })
+    }
+}
+
+/// A page of the entries of `map`, in key order, from the `start` key on, along with the key the
+/// next page starts from, if any.
+fn entries_page(map: &Map, start: Option<&Vec<u8>>, limit: u64) -> (MapEntries, Option<Vec<u8>>) {
+    let range = (
+        start.map_or(Bound::Unbounded, Bound::Included),
+        Bound::Unbounded,
+    );
+    match map {
+        Map::Seq(map) => {
+            let entries = map
+                .entries()
+                .range::<Vec<u8>, _>(range)
+                .map(|(key, value)| (key.clone(), (key.clone(), value.clone())));
+            let (page, next) =
+                pagination::page(entries, limit, |(key, value)| key.len() + value.data.len());
+            (page.into_iter().collect::<MapSeqEntries>().into(), next)
+        }
+        Map::Unseq(map) => {
+            let entries = map
+                .entries()
+                .range::<Vec<u8>, _>(range)
+                .map(|(key, value)| (key.clone(), (key.clone(), value.clone())));
+            let (page, next) =
+                pagination::page(entries, limit, |(key, value)| key.len() + value.len());
+            (page.into_iter().collect::<MapUnseqEntries>().into(), next)
+        }
+    }
+}
+
 impl Display for MapStorage {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         write!(formatter, "MapStorage")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sn_data_types::{Keypair, MapSeqEntryActions, SeqMap};
+    use tempdir::TempDir;
+    use xor_name::XorName;
+
+    #[test]
+    fn transactions_report_all_conflicts() -> Result<()> {
+        let owner = Keypair::new_ed25519(&mut rand::thread_rng()).public_key();
+        let mut data = SeqMap::new(XorName::random(), 10, owner);
+        let actions = MapSeqEntryActions::new()
+            .ins(b"a".to_vec(), b"1".to_vec(), 0)
+            .ins(b"b".to_vec(), b"1".to_vec(), 0);
+        data.mutate_entries(actions, &owner)?;
+        let map = Map::from(data);
+
+        let mut expected = BTreeMap::new();
+        let _ = expected.insert(b"a".to_vec(), Some(0));
+        let _ = expected.insert(b"c".to_vec(), None);
+        check_expected_versions(&map, Some(0), &expected)?;
+
+        let _ = expected.insert(b"b".to_vec(), Some(1));
+        let _ = expected.insert(b"c".to_vec(), Some(0));
+        match check_expected_versions(&map, Some(1), &expected) {
+            Err(Error::MapTransactionConflict { version, entries }) => {
+                assert_eq!(version, Some(0));
+                let mut actual = BTreeMap::new();
+                let _ = actual.insert(b"b".to_vec(), Some(0));
+                let _ = actual.insert(b"c".to_vec(), None);
+                assert_eq!(entries, actual);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn merged_and_handed_over_maps_keep_earlier_versions() -> Result<()> {
+        let root =
+            TempDir::new("map_storage").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let mut storage = MapStorage::new(
+            root.path(),
+            UsedSpace::new(u64::MAX),
+            &StoreConfig::default(),
+            10,
+        )
+        .await?;
+        let owner = Keypair::new_ed25519(&mut rand::thread_rng()).public_key();
+        let mut data = SeqMap::new(XorName::random(), 10, owner);
+        let address = *data.address();
+        data.mutate_entries(
+            MapSeqEntryActions::new().ins(b"a".to_vec(), b"1".to_vec(), 0),
+            &owner,
+        )?;
+        let _ = storage
+            .receive_chunk(Map::from(data.clone()), MessageId::new())
+            .await?;
+
+        // an edit of the entries only, which a later version handed over also holds
+        data.mutate_entries(
+            MapSeqEntryActions::new().update(b"a".to_vec(), b"2".to_vec(), 1),
+            &owner,
+        )?;
+        let edited = Map::from(data.clone());
+        let _ = storage
+            .merge_replica(edited.clone(), MessageId::new())
+            .await?;
+        assert_eq!(storage.chunks.get(&address).await?, edited);
+        data.set_user_permissions(owner, MapPermissionSet::new(), 1)?;
+        let _ = storage
+            .receive_chunk(Map::from(data.clone()), MessageId::new())
+            .await?;
+        assert_eq!(storage.history.get(address, 0).await?, edited);
+
+        data.set_user_permissions(owner, MapPermissionSet::new(), 2)?;
+        let _ = storage
+            .receive_chunk(Map::from(data.clone()), MessageId::new())
+            .await?;
+        assert_eq!(storage.history.get(address, 1).await?.version(), 1);
+        assert_eq!(storage.chunks.get(&address).await?, Map::from(data.clone()));
+
+        // the permissions only change through their owner, not through a replica
+        data.set_user_permissions(owner, MapPermissionSet::new(), 3)?;
+        let _ = storage
+            .merge_replica(Map::from(data), MessageId::new())
+            .await?;
+        assert_eq!(storage.chunks.get(&address).await?.version(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_notified_of_merged_changes_while_they_can_read() -> Result<()> {
+        let root =
+            TempDir::new("map_storage").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let mut storage = MapStorage::new(
+            root.path(),
+            UsedSpace::new(u64::MAX),
+            &StoreConfig::default(),
+            10,
+        )
+        .await?;
+        let owner = Keypair::new_ed25519(&mut rand::thread_rng()).public_key();
+        let reader = Keypair::new_ed25519(&mut rand::thread_rng()).public_key();
+        let mut data = SeqMap::new(XorName::random(), 10, owner);
+        data.set_user_permissions(reader, MapPermissionSet::new().allow(MapAction::Read), 1)?;
+        data.mutate_entries(
+            MapSeqEntryActions::new().ins(b"a".to_vec(), b"1".to_vec(), 0),
+            &owner,
+        )?;
+        let _ = storage
+            .receive_chunk(Map::from(data.clone()), MessageId::new())
+            .await?;
+        storage
+            .subscriptions
+            .subscribe(*data.address(), EndUser::AllClients(reader))?;
+
+        // a replica with a later version of an entry
+        data.mutate_entries(
+            MapSeqEntryActions::new().update(b"a".to_vec(), b"2".to_vec(), 1),
+            &owner,
+        )?;
+        let ops = storage
+            .merge_replica(Map::from(data.clone()), MessageId::new())
+            .await?;
+        match &ops[..] {
+            [NodeDuty::Send(OutgoingMsg {
+                msg:
+                    Message::Event {
+                        event: Event::MapChanged { keys, .. },
+                        ..
+                    },
+                ..
+            })] => assert_eq!(keys, &vec![b"a".to_vec()].into_iter().collect()),
+            x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+        }
+
+        // a handover which takes the permission to read away
+        data.del_user_permissions(reader, 2)?;
+        let ops = storage
+            .receive_chunk(Map::from(data), MessageId::new())
+            .await?;
+        assert!(ops.is_empty());
+        Ok(())
+    }
+}
@@ -9,25 +9,37 @@
 pub mod adult_reader;
 mod blob_register;
 mod elder_stores;
+mod handover;
+mod map_history;
 mod map_storage;
+mod pagination;
 mod reading;
+mod replication;
 mod sequence_storage;
+mod subscriptions;
 mod writing;
 
 use self::adult_reader::AdultReader;
 use super::node_ops::NodeDuty;
 use crate::{
-    capacity::ChunkHolderDbs, chunk_store::UsedSpace, node_ops::NodeDuties, Network, Result,
+    capacity::ChunkHolderDbs,
+    chunk_store::{StoreConfig, UsedSpace},
+    node_ops::NodeDuties,
+    Network, Result,
 };
 use blob_register::BlobRegister;
 use elder_stores::ElderStores;
+use handover::PendingHandover;
+use log::info;
 use map_storage::MapStorage;
 use sequence_storage::SequenceStorage;
+use sn_data_types::{Blob, BlobAddress};
 use sn_messaging::{
-    client::{DataCmd, DataQuery},
+    client::{DataCmd, DataQuery, Error as ErrorMessage},
     EndUser, MessageId,
 };
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fmt::{self, Display, Formatter},
     path::Path,
 };
@@ -41,24 +53,107 @@ use xor_name::XorName;
 /// all underlying data being chunks stored at `Adults`.
 pub struct Metadata {
     elder_stores: ElderStores,
+    // Data handed over to another section, awaiting confirmation, by the id of the handover msg.
+    handovers: BTreeMap<MessageId, PendingHandover>,
+    reader: AdultReader,
+    // Our elders, and those before the last churn, who may still be handing data over to us.
+    elders: BTreeSet<XorName>,
+    former_elders: BTreeSet<XorName>,
+    // How many adults keep a replica of each Map and Sequence.
+    copy_count: usize,
+    // Where the next round of replica reconciliation starts, among our Maps and Sequences.
+    reconcile_cursor: usize,
 }
 
 impl Metadata {
     pub async fn new(
         path: &Path,
         used_space: &UsedSpace,
+        config: &StoreConfig,
         dbs: ChunkHolderDbs,
         reader: AdultReader,
+        copy_count: usize,
+        map_history_depth: u64,
     ) -> Result<Self> {
-        let blob_register = BlobRegister::new(dbs, reader);
-        let map_storage = MapStorage::new(path, used_space.clone()).await?;
-        let sequence_storage = SequenceStorage::new(path, used_space.clone()).await?;
+        let blob_register = BlobRegister::new(dbs, reader.clone());
+        let map_storage =
+            MapStorage::new(path, used_space.clone(), config, map_history_depth).await?;
+        let sequence_storage = SequenceStorage::new(path, used_space.clone(), config).await?;
         let elder_stores = ElderStores::new(blob_register, map_storage, sequence_storage);
-        Ok(Self { elder_stores })
+        let elders = reader.our_elders().await;
+        Ok(Self {
+            elder_stores,
+            handovers: BTreeMap::new(),
+            reader,
+            elders,
+            former_elders: BTreeSet::new(),
+            copy_count,
+            reconcile_cursor: 0,
+        })
     }
 
-    pub async fn read(&self, query: DataQuery, id: MessageId, origin: EndUser) -> Result<NodeDuty> {
-        reading::get_result(query, id, origin, &self.elder_stores).await
+    /// Takes note of a change of our elders. Those before it are still known for a while, as
+    /// they hand their data over to the remaining elders when demoted.
+    pub async fn update_elders(&mut self) {
+        let elders = self.reader.our_elders().await;
+        if elders != self.elders {
+            self.former_elders = std::mem::replace(&mut self.elders, elders);
+        }
+    }
+
+    fn is_known_elder(&self, name: &XorName) -> bool {
+        self.elders.contains(name) || self.former_elders.contains(name)
+    }
+
+    /// The chunk holder dbs, which can only be opened once, for the transfers to share them.
+    pub fn chunk_holder_dbs(&self) -> ChunkHolderDbs {
+        self.elder_stores.blob_register().dbs().clone()
+    }
+
+    /// Closes our stores as we stop being an elder, so that the space used by the Maps and
+    /// Sequences in them is counted only once they are opened as replicas.
+    pub async fn close(self) -> Result<()> {
+        self.elder_stores.close().await
+    }
+
+    /// Logs the counters of the read caches of the Map and Sequence stores, and the read stats
+    /// of Blob holders.
+    pub fn log_cache_stats(&self) {
+        info!(
+            "Read caches: Map: {}; Sequence: {}",
+            self.elder_stores.map_storage().cache_stats(),
+            self.elder_stores.sequence_storage().cache_stats()
+        );
+        self.elder_stores.blob_register().log_read_stats();
+    }
+
+    pub async fn read(
+        &mut self,
+        query: DataQuery,
+        id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        reading::get_result(query, id, origin, &mut self.elder_stores).await
+    }
+
+    // This should be called when a holder tells us whether it sent a client the chunk we asked
+    // it to.
+    pub async fn receive_read_outcome(
+        &mut self,
+        outcome: std::result::Result<BlobAddress, ErrorMessage>,
+        holder: XorName,
+        correlation_id: MessageId,
+    ) -> Result<NodeDuty> {
+        self.elder_stores
+            .blob_register_mut()
+            .receive_read_outcome(outcome, holder, correlation_id)
+            .await
+    }
+
+    // This should be called periodically, to move on to the next holder for reads which weren't
+    // answered in time.
+    pub async fn retry_reads(&mut self) -> Result<NodeDuties> {
+        self.elder_stores.blob_register_mut().retry_reads().await
     }
 
     pub async fn write(
@@ -66,17 +161,117 @@ impl Metadata {
         cmd: DataCmd,
         id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        writing::get_result(cmd, id, origin, &mut self.elder_stores).await
+    ) -> Result<NodeDuties> {
+        let (mut ops, update) =
+            writing::get_result(cmd, id, origin, &mut self.elder_stores).await?;
+        // Maps and Sequences are replicated to adults when a write changes them.
+        if let Some(update) = update {
+            ops.extend(self.replicate_write(update).await?);
+        }
+        Ok(ops)
     }
 
     // This should be called whenever a node leaves the section. It fetches the list of data that was
     // previously held by the node and requests the other holders to store an additional copy.
-    // The list of holders is also updated by removing the node that left.
+    // The list of holders is also updated by removing the node that left. Replicas of Maps and
+    // Sequences it held are sent to the adults which take its place.
     pub async fn trigger_chunk_replication(&mut self, node: XorName) -> Result<NodeDuties> {
-        self.elder_stores
+        let mut ops = self
+            .elder_stores
             .blob_register_mut()
             .replicate_chunks(node)
+            .await?;
+        ops.extend(self.replace_lost_replicas(node).await?);
+        Ok(ops)
+    }
+
+    // This should be called when a holder is no longer an adult, but still has its chunks. New
+    // holders are asked to copy them.
+    pub async fn hand_off_chunks(
+        &mut self,
+        holder: XorName,
+        addresses: BTreeSet<BlobAddress>,
+    ) -> Result<NodeDuties> {
+        self.elder_stores
+            .blob_register_mut()
+            .hand_off_chunks(holder, addresses)
+            .await
+    }
+
+    // This should be called periodically, to restore the copies of chunks which went missing
+    // without their holders being reported lost, and to reconcile the replicas of Maps and
+    // Sequences with our copies.
+    pub async fn audit_replication(&mut self) -> Result<NodeDuties> {
+        let mut ops = self
+            .elder_stores
+            .blob_register_mut()
+            .audit_replication()
+            .await?;
+        ops.extend(self.reconcile_replicas().await?);
+        Ok(ops)
+    }
+
+    // This should be called periodically, to check that holders still store the chunks they
+    // claim to.
+    pub async fn challenge_storage(&mut self) -> Result<NodeDuties> {
+        self.elder_stores
+            .blob_register_mut()
+            .challenge_storage()
+            .await
+    }
+
+    // This should be called when a holder answers a storage challenge.
+    pub async fn receive_storage_proof(
+        &mut self,
+        address: BlobAddress,
+        nonce: [u8; 32],
+        proof: Option<XorName>,
+        holder: XorName,
+        correlation_id: MessageId,
+    ) -> Result<NodeDuties> {
+        self.elder_stores
+            .blob_register_mut()
+            .receive_storage_proof(address, nonce, proof, holder, correlation_id)
+            .await
+    }
+
+    /// The number of storage challenges failed by each adult which failed any.
+    pub fn storage_failures(&self) -> Result<BTreeMap<XorName, u64>> {
+        self.elder_stores.blob_register().storage_failures()
+    }
+
+    // This should be called when a new holder reports that it stored a replicated chunk.
+    pub async fn finish_replication(
+        &mut self,
+        address: BlobAddress,
+        holder: XorName,
+        msg_id: MessageId,
+    ) -> Result<NodeDuty> {
+        self.elder_stores
+            .blob_register_mut()
+            .update_holders(address, holder, msg_id)
+            .await
+    }
+
+    // This should be called when a holder reports that its copy of a chunk is corrupt.
+    pub async fn repair_chunk(&self, address: BlobAddress, holder: XorName) -> Result<NodeDuties> {
+        self.elder_stores
+            .blob_register()
+            .repair_chunk(address, holder)
+            .await
+    }
+
+    // This should be called when a holder reports that it had no space to store a chunk.
+    pub async fn retry_chunk_store(
+        &mut self,
+        data: Blob,
+        holder: XorName,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        self.elder_stores
+            .blob_register_mut()
+            .retry_store(data, holder, msg_id, origin)
             .await
     }
 }
@@ -0,0 +1,81 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Pages of Map and Sequence listings, which would otherwise not fit in a single msg.
+
+// Upper limit for the number of items in a page, whatever limit was asked for.
+const MAX_PAGE_LEN: u64 = 1000;
+// Upper limit for the bytes taken by the items of a page, so that it fits in a msg.
+const MAX_PAGE_SIZE: usize = 1024 * 1024;
+
+/// Takes a page of up to `limit` items from `items`, which are listed in a stable order, each
+/// along with its cursor. Returns the items, and the cursor of the first item left out, from
+/// which the next page starts, if any.
+///
+/// A page stops short of `limit` when its items take `MAX_PAGE_SIZE` bytes, as measured by
+/// `size`, but always has at least one item, if any are left.
+pub(super) fn page<C, T>(
+    items: impl IntoIterator<Item = (C, T)>,
+    limit: u64,
+    size: impl Fn(&T) -> usize,
+) -> (Vec<T>, Option<C>) {
+    let limit = limit.clamp(1, MAX_PAGE_LEN) as usize;
+    let mut page = vec![];
+    let mut page_size = 0;
+    for (cursor, item) in items {
+        let item_size = size(&item);
+        let full =
+            page.len() >= limit || (!page.is_empty() && page_size + item_size > MAX_PAGE_SIZE);
+        if full {
+            return (page, Some(cursor));
+        }
+        page_size += item_size;
+        page.push(item);
+    }
+    (page, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_cover_all_items_in_order() {
+        let items: Vec<(u64, Vec<u8>)> = (0..25).map(|i| (i, vec![i as u8])).collect();
+
+        let mut start = 0;
+        let mut listed = vec![];
+        loop {
+            let remaining = items.iter().skip(start as usize).cloned();
+            let (items, next) = page(remaining, 10, Vec::len);
+            assert!(items.len() <= 10);
+            listed.extend(items);
+            match next {
+                Some(cursor) => start = cursor,
+                None => break,
+            }
+        }
+        assert_eq!(listed, (0..25).map(|i| vec![i as u8]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn pages_are_bounded_in_size() {
+        let large = vec![0; MAX_PAGE_SIZE / 2 + 1];
+        let items = vec![(0, large.clone()), (1, large.clone()), (2, large)];
+
+        // one item per page, as two don't fit
+        let (items, next) = page(items, 10, Vec::len);
+        assert_eq!(items.len(), 1);
+        assert_eq!(next, Some(1));
+
+        // an item larger than a page still gets one
+        let (items, next) = page(vec![(0, vec![0; MAX_PAGE_SIZE + 1])], 10, Vec::len);
+        assert_eq!(items.len(), 1);
+        assert_eq!(next, None);
+    }
+}
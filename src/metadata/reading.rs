@@ -23,19 +23,19 @@ pub(super) async fn get_result(
     query: DataQuery,
     msg_id: MessageId,
     origin: EndUser,
-    stores: &ElderStores,
+    stores: &mut ElderStores,
 ) -> Result<NodeDuty> {
     use DataQuery::*;
     match &query {
-        Blob(read) => blob(read, stores.blob_register(), msg_id, origin).await,
-        Map(read) => map(read, stores.map_storage(), msg_id, origin).await,
-        Sequence(read) => sequence(read, stores.sequence_storage(), msg_id, origin).await,
+        Blob(read) => blob(read, stores.blob_register_mut(), msg_id, origin).await,
+        Map(read) => map(read, stores.map_storage_mut(), msg_id, origin).await,
+        Sequence(read) => sequence(read, stores.sequence_storage_mut(), msg_id, origin).await,
     }
 }
 
 async fn blob(
     read: &BlobRead,
-    register: &BlobRegister,
+    register: &mut BlobRegister,
     msg_id: MessageId,
     origin: EndUser,
 ) -> Result<NodeDuty> {
@@ -44,7 +44,7 @@ async fn blob(
 
 async fn map(
     read: &MapRead,
-    storage: &MapStorage,
+    storage: &mut MapStorage,
     msg_id: MessageId,
     origin: EndUser,
 ) -> Result<NodeDuty> {
@@ -53,7 +53,7 @@ async fn map(
 
 async fn sequence(
     read: &SequenceRead,
-    storage: &SequenceStorage,
+    storage: &mut SequenceStorage,
     msg_id: MessageId,
     origin: EndUser,
 ) -> Result<NodeDuty> {
@@ -0,0 +1,245 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Replication of Maps and Sequences to the adults closest to them, so that they outlive the
+//! elders holding them.
+
+use super::Metadata;
+use crate::{
+    node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
+    Result,
+};
+use log::{info, warn};
+use sn_data_types::{Map, MapAddress, Sequence, SequenceAddress};
+use sn_messaging::{
+    client::{Message, NodeCmd, NodeSystemCmd},
+    Aggregation, DstLocation, MessageId,
+};
+use std::cmp::Ordering;
+use xor_name::XorName;
+
+// How many Maps and Sequences are sent to their holders on each reconciliation round.
+const CHUNKS_PER_RECONCILIATION: usize = 100;
+
+/// The address of a Map or Sequence.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum ReplicaAddress {
+    Map(MapAddress),
+    Sequence(SequenceAddress),
+}
+
+impl ReplicaAddress {
+    fn name(&self) -> &XorName {
+        match self {
+            Self::Map(address) => address.name(),
+            Self::Sequence(address) => address.name(),
+        }
+    }
+}
+
+/// The state of a Map or Sequence after a write which changed it, which its replicas are brought
+/// up to.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub(super) enum ReplicaUpdate {
+    Map(Map),
+    Sequence(Sequence),
+    Deleted(ReplicaAddress),
+}
+
+impl ReplicaUpdate {
+    fn address(&self) -> ReplicaAddress {
+        match self {
+            Self::Map(map) => ReplicaAddress::Map(*map.address()),
+            Self::Sequence(sequence) => ReplicaAddress::Sequence(*sequence.address()),
+            Self::Deleted(address) => *address,
+        }
+    }
+
+    /// The msg which brings a replica up to date. The id is derived from the content, so that
+    /// the same msg from each of our elders is delivered only once.
+    fn into_cmd(self) -> Result<(MessageId, NodeSystemCmd)> {
+        Ok(match self {
+            Self::Map(map) => (
+                MessageId::from_content(&map)?,
+                NodeSystemCmd::ReplicateMap(map),
+            ),
+            Self::Sequence(sequence) => (
+                MessageId::from_content(&sequence)?,
+                NodeSystemCmd::ReplicateSequence(sequence),
+            ),
+            Self::Deleted(ReplicaAddress::Map(address)) => (
+                MessageId::from_content(&address)?,
+                NodeSystemCmd::DeleteMapReplica(address),
+            ),
+            Self::Deleted(ReplicaAddress::Sequence(address)) => (
+                MessageId::from_content(&address)?,
+                NodeSystemCmd::DeleteSequenceReplica(address),
+            ),
+        })
+    }
+}
+
+impl Metadata {
+    /// The msg which brings a replica of the Map or Sequence at `address` up to date with our
+    /// copy, or deletes it if we don't hold one.
+    async fn replication_cmd(&self, address: ReplicaAddress) -> Result<(MessageId, NodeSystemCmd)> {
+        let update = match address {
+            ReplicaAddress::Map(address) => self
+                .elder_stores
+                .map_storage()
+                .chunks(&[address])
+                .await?
+                .pop()
+                .map(ReplicaUpdate::Map),
+            ReplicaAddress::Sequence(address) => self
+                .elder_stores
+                .sequence_storage()
+                .chunks(&[address])
+                .await?
+                .pop()
+                .map(ReplicaUpdate::Sequence),
+        };
+        update.unwrap_or(ReplicaUpdate::Deleted(address)).into_cmd()
+    }
+
+    /// Brings the replicas of a Map or Sequence up to date with a write which changed it.
+    pub(super) async fn replicate_write(&self, update: ReplicaUpdate) -> Result<NodeDuties> {
+        let holders = self.replica_holders(&update.address()).await;
+        let (msg_id, cmd) = update.into_cmd()?;
+        Ok(send_to_holders(holders, msg_id, cmd))
+    }
+
+    /// Sends the Maps and Sequences a lost adult held replicas of to the adults which take its
+    /// place among their closest ones.
+    pub(super) async fn replace_lost_replicas(&self, lost: XorName) -> Result<NodeDuties> {
+        let mut ops = vec![];
+        for address in self.replica_addresses().await {
+            let holders = self.replica_holders(&address).await;
+            // With fewer adults than copies, all of them hold one already.
+            if holders.len() < self.copy_count {
+                continue;
+            }
+            let new_holder = match holders.last() {
+                Some(last) if address.name().cmp_distance(&lost, last) == Ordering::Less => *last,
+                _ => continue,
+            };
+            let (msg_id, cmd) = self.replication_cmd(address).await?;
+            ops.extend(send_to_holders(vec![new_holder], msg_id, cmd));
+        }
+        if !ops.is_empty() {
+            info!("Replacing {} replicas held by {:?}", ops.len(), lost);
+        }
+        Ok(ops)
+    }
+
+    /// Sends the next batch of our Maps and Sequences to their holders, which then catch up with
+    /// our copies, or send theirs back if those are ahead. This also fills in replicas at adults
+    /// which became closest to the data since it was written.
+    pub(super) async fn reconcile_replicas(&mut self) -> Result<NodeDuties> {
+        let addresses = self.replica_addresses().await;
+        if addresses.is_empty() {
+            return Ok(vec![]);
+        }
+        let start = self.reconcile_cursor % addresses.len();
+        let count = CHUNKS_PER_RECONCILIATION.min(addresses.len());
+        self.reconcile_cursor = start + count;
+
+        let mut ops = vec![];
+        for address in addresses.into_iter().cycle().skip(start).take(count) {
+            let holders = self.replica_holders(&address).await;
+            let (msg_id, cmd) = self.replication_cmd(address).await?;
+            ops.extend(send_to_holders(holders, msg_id, cmd));
+        }
+        Ok(ops)
+    }
+
+    /// Merges in the replica of a Map which an adult found to be ahead of our copy, if the
+    /// adult is one of those holding its replicas. Subscribers are notified of the changes.
+    pub async fn merge_map_replica(
+        &mut self,
+        map: Map,
+        msg_id: MessageId,
+        sender: XorName,
+    ) -> Result<NodeDuties> {
+        if !self
+            .is_replica_holder(ReplicaAddress::Map(*map.address()), sender)
+            .await
+        {
+            return Ok(vec![]);
+        }
+        self.elder_stores
+            .map_storage_mut()
+            .merge_replica(map, msg_id)
+            .await
+    }
+
+    /// Takes over the replica of a Sequence which an adult found to be ahead of our copy, if
+    /// the adult is one of those holding its replicas. Subscribers are notified of the changes.
+    pub async fn merge_sequence_replica(
+        &mut self,
+        sequence: Sequence,
+        msg_id: MessageId,
+        sender: XorName,
+    ) -> Result<NodeDuties> {
+        let address = ReplicaAddress::Sequence(*sequence.address());
+        if !self.is_replica_holder(address, sender).await {
+            return Ok(vec![]);
+        }
+        self.elder_stores
+            .sequence_storage_mut()
+            .merge_replica(sequence, msg_id)
+            .await
+    }
+
+    async fn is_replica_holder(&self, address: ReplicaAddress, sender: XorName) -> bool {
+        if self.replica_holders(&address).await.contains(&sender) {
+            true
+        } else {
+            warn!(
+                "Dropping replica of {:?} from {:?}, which doesn't hold it",
+                address, sender
+            );
+            false
+        }
+    }
+
+    async fn replica_addresses(&self) -> Vec<ReplicaAddress> {
+        let maps = self.elder_stores.map_storage().keys().await;
+        let sequences = self.elder_stores.sequence_storage().keys().await;
+        maps.into_iter()
+            .map(ReplicaAddress::Map)
+            .chain(sequences.into_iter().map(ReplicaAddress::Sequence))
+            .collect()
+    }
+
+    // The adults closest to the data hold its replicas.
+    async fn replica_holders(&self, address: &ReplicaAddress) -> Vec<XorName> {
+        self.reader
+            .our_adults_sorted_by_distance_to(address.name(), self.copy_count)
+            .await
+    }
+}
+
+fn send_to_holders(holders: Vec<XorName>, msg_id: MessageId, cmd: NodeSystemCmd) -> NodeDuties {
+    holders
+        .into_iter()
+        .map(|holder| {
+            NodeDuty::Send(OutgoingMsg {
+                msg: Message::NodeCmd {
+                    cmd: NodeCmd::System(cmd.clone()),
+                    id: msg_id,
+                    target_section_pk: None,
+                },
+                section_source: false, // sent as single node
+                dst: DstLocation::Node(holder),
+                aggregation: Aggregation::None,
+            })
+        })
+        .collect()
+}
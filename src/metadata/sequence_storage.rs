@@ -6,19 +6,25 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use super::{
+    pagination,
+    replication::{ReplicaAddress, ReplicaUpdate},
+    subscriptions::{Subscriptions, SUBSCRIPTION_TTL},
+};
 use crate::{
-    chunk_store::{SequenceChunkStore, UsedSpace},
+    chunk_store::{CacheStats, SequenceChunkStore, StoreConfig, UsedSpace},
+    chunks::is_ahead,
     error::convert_to_error_message,
-    node_ops::{NodeDuty, OutgoingMsg},
+    node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
     Error, Network, Result,
 };
-use log::info;
+use log::{info, warn};
 use sn_data_types::{
     Error as DtError, Sequence, SequenceAction, SequenceAddress, SequenceEntry, SequenceIndex,
     SequenceOp, SequenceUser,
 };
 use sn_messaging::{
-    client::{CmdError, Message, QueryResponse, SequenceRead, SequenceWrite},
+    client::{CmdError, Event, Message, QueryResponse, SequenceRead, SequenceWrite},
     Aggregation, DstLocation, EndUser, MessageId, SrcLocation,
 };
 
@@ -26,20 +32,110 @@ use std::{
     fmt::{self, Display, Formatter},
     path::Path,
 };
+use xor_name::Prefix;
 
 /// Operations over the data type Sequence.
 pub(super) struct SequenceStorage {
     chunks: SequenceChunkStore,
+    subscriptions: Subscriptions<SequenceAddress>,
 }
 
 impl SequenceStorage {
-    pub(super) async fn new(path: &Path, used_space: UsedSpace) -> Result<Self> {
-        let chunks = SequenceChunkStore::new(path, used_space).await?;
-        Ok(Self { chunks })
+    pub(super) async fn new(
+        path: &Path,
+        used_space: UsedSpace,
+        config: &StoreConfig,
+    ) -> Result<Self> {
+        let chunks = SequenceChunkStore::new(path, used_space, config).await?;
+        Ok(Self {
+            chunks,
+            subscriptions: Subscriptions::new(),
+        })
+    }
+
+    pub(super) async fn close(self) -> Result<()> {
+        self.chunks.close().await
+    }
+
+    pub(super) fn cache_stats(&self) -> CacheStats {
+        self.chunks.cache_stats()
+    }
+
+    /// The Sequences which `prefix` doesn't cover, and which thus belong to our sibling section
+    /// after a split.
+    pub(super) async fn keys_outside(&self, prefix: &Prefix) -> Vec<SequenceAddress> {
+        self.keys()
+            .await
+            .into_iter()
+            .filter(|address| !prefix.matches(address.name()))
+            .collect()
+    }
+
+    /// The addresses of all Sequences we hold.
+    pub(super) async fn keys(&self) -> Vec<SequenceAddress> {
+        self.chunks.keys().await
+    }
+
+    /// The Sequences at `addresses` which we still hold.
+    pub(super) async fn chunks(&self, addresses: &[SequenceAddress]) -> Result<Vec<Sequence>> {
+        let mut sequences = vec![];
+        for address in addresses {
+            if self.chunks.has(address).await {
+                sequences.push(self.chunks.get(address).await?);
+            }
+        }
+        Ok(sequences)
+    }
+
+    /// Stores a Sequence handed over by another section, unless we have it already.
+    pub(super) async fn receive_chunk(&mut self, sequence: Sequence) -> Result<()> {
+        if self.chunks.has(sequence.address()).await {
+            return Ok(());
+        }
+        self.chunks.put(&sequence).await
+    }
+
+    /// Takes over a replica which an adult found to be ahead of our copy, sent in the msg with
+    /// `msg_id`, and notifies subscribers of the entries it appends. Replicas of Sequences we
+    /// don't hold are ignored, as we may have deleted them since. Only the owner and those it
+    /// permits can write to a Sequence, so a replica is only taken over if it has our policy, and
+    /// appends to our entries without replacing any.
+    pub(super) async fn merge_replica(
+        &mut self,
+        sequence: Sequence,
+        msg_id: MessageId,
+    ) -> Result<NodeDuties> {
+        let existing = match self.chunks.get(sequence.address()).await {
+            Ok(existing) => existing,
+            Err(_) => return Ok(vec![]),
+        };
+        if !is_ahead(&sequence, &existing)? {
+            return Ok(vec![]);
+        }
+        if !extends(&sequence, &existing)? {
+            warn!(
+                "Dropping replica of {:?}, which doesn't extend our copy",
+                sequence.address()
+            );
+            return Ok(vec![]);
+        }
+        self.chunks.put(&sequence).await?;
+        let start = existing.len(None)?;
+        Ok(self.notify(&sequence, start, msg_id)?.unwrap_or_default())
+    }
+
+    /// Removes Sequences which another section has taken over.
+    pub(super) async fn remove_chunks(&mut self, addresses: &[SequenceAddress]) -> Result<()> {
+        for address in addresses {
+            if self.chunks.has(address).await {
+                self.chunks.delete(address).await?;
+            }
+        }
+        Ok(())
     }
 
     pub(super) async fn read(
-        &self,
+        &mut self,
         read: &SequenceRead,
         msg_id: MessageId,
         origin: EndUser,
@@ -48,6 +144,14 @@ impl SequenceStorage {
         match read {
             Get(address) => self.get(*address, msg_id, origin).await,
             GetRange { address, range } => self.get_range(*address, *range, msg_id, origin).await,
+            GetRangePage {
+                address,
+                range,
+                limit,
+            } => {
+                self.get_range_page(*address, *range, *limit, msg_id, origin)
+                    .await
+            }
             GetLastEntry(address) => self.get_last_entry(*address, msg_id, origin).await,
             GetOwner(address) => self.get_owner(*address, msg_id, origin).await,
             GetUserPermissions { address, user } => {
@@ -56,19 +160,23 @@ impl SequenceStorage {
             }
             GetPublicPolicy(address) => self.get_public_policy(*address, msg_id, origin).await,
             GetPrivatePolicy(address) => self.get_private_policy(*address, msg_id, origin).await,
+            Subscribe(address) => self.subscribe(*address, msg_id, origin).await,
+            Unsubscribe(address) => Ok(self.unsubscribe(*address, msg_id, origin)),
         }
     }
 
+    /// Applies `write`, returning the new state of the Sequence along with the duties, if it
+    /// changed.
     pub(super) async fn write(
         &mut self,
         write: SequenceWrite,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
         use SequenceWrite::*;
         info!("Matching Sequence Write");
         match write {
-            New(data) => self.store(&data, msg_id, origin).await,
+            New(data) => self.store(data, msg_id, origin).await,
             Edit(operation) => {
                 info!("Editing Sequence");
                 self.edit(operation, msg_id, origin).await
@@ -79,16 +187,24 @@ impl SequenceStorage {
 
     async fn store(
         &mut self,
-        data: &Sequence,
+        data: Sequence,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        let result = if self.chunks.has(data.address()) {
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
+        let result = if self.chunks.has(data.address()).await {
             Err(Error::DataExists)
         } else {
             self.chunks.put(&data).await
         };
-        self.ok_or_error(result, msg_id, origin).await
+        let update = if result.is_ok() {
+            Some(ReplicaUpdate::Sequence(data))
+        } else {
+            None
+        };
+        Ok((
+            vec![self.ok_or_error(result, msg_id, origin).await?],
+            update,
+        ))
     }
 
     async fn get(
@@ -97,7 +213,7 @@ impl SequenceStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let result = match self.get_chunk(address, SequenceAction::Read, origin) {
+        let result = match self.get_chunk(address, SequenceAction::Read, origin).await {
             Ok(res) => Ok(res),
             Err(error) => Err(convert_to_error_message(error)?),
         };
@@ -114,13 +230,13 @@ impl SequenceStorage {
         }))
     }
 
-    fn get_chunk(
+    async fn get_chunk(
         &self,
         address: SequenceAddress,
         action: SequenceAction,
         origin: EndUser,
     ) -> Result<Sequence> {
-        let data = self.chunks.get(&address)?;
+        let data = self.chunks.get(&address).await?;
         data.check_permission(action, Some(*origin.id()))?;
         Ok(data)
     }
@@ -130,8 +246,8 @@ impl SequenceStorage {
         address: SequenceAddress,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        let result = match self.chunks.get(&address).and_then(|sequence| {
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
+        let result = match self.chunks.get(&address).await.and_then(|sequence| {
             // TODO - Sequence::check_permission() doesn't support Delete yet in safe-nd
             if sequence.address().is_public() {
                 return Err(Error::InvalidMessage(
@@ -149,11 +265,24 @@ impl SequenceStorage {
                 Ok(())
             }
         }) {
-            Ok(()) => self.chunks.delete(&address).await,
+            Ok(()) => {
+                let result = self.chunks.delete(&address).await;
+                if result.is_ok() {
+                    self.subscriptions.remove(&address);
+                }
+                result
+            }
             Err(error) => Err(error),
         };
-
-        self.ok_or_error(result, msg_id, origin).await
+        let update = if result.is_ok() {
+            Some(ReplicaUpdate::Deleted(ReplicaAddress::Sequence(address)))
+        } else {
+            None
+        };
+        Ok((
+            vec![self.ok_or_error(result, msg_id, origin).await?],
+            update,
+        ))
     }
 
     async fn get_range(
@@ -165,6 +294,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| {
                 sequence
                     .in_range(range.0, range.1, Some(*origin.id()))?
@@ -186,6 +316,51 @@ impl SequenceStorage {
         }))
     }
 
+    /// Get a page of the entries in `range`. The index the next page starts from is returned
+    /// along with the page, counted from the start, so that it stays put as entries are
+    /// appended.
+    async fn get_range_page(
+        &self,
+        address: SequenceAddress,
+        range: (SequenceIndex, SequenceIndex),
+        limit: u64,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        let result = match self
+            .get_chunk(address, SequenceAction::Read, origin)
+            .await
+            .and_then(|sequence| {
+                let len = sequence.len(Some(*origin.id()))?;
+                let start = match range.0 {
+                    SequenceIndex::FromStart(index) => index,
+                    SequenceIndex::FromEnd(index) => len.saturating_sub(index),
+                };
+                let entries = sequence
+                    .in_range(range.0, range.1, Some(*origin.id()))?
+                    .ok_or(Error::NetworkData(DtError::NoSuchEntry))?;
+                let entries = entries
+                    .into_iter()
+                    .zip(start..)
+                    .map(|(entry, index)| (SequenceIndex::FromStart(index), entry));
+                Ok(pagination::page(entries, limit, Vec::len))
+            }) {
+            Ok(res) => Ok(res),
+            Err(error) => Err(convert_to_error_message(error)?),
+        };
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::GetSequenceRangePage(result),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to a response..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        }))
+    }
+
     async fn get_last_entry(
         &self,
         address: SequenceAddress,
@@ -194,6 +369,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| match sequence.last_entry(Some(*origin.id()))? {
                 Some(entry) => Ok((sequence.len(Some(*origin.id()))? - 1, entry.to_vec())),
                 None => Err(Error::NetworkData(DtError::NoSuchEntry)),
@@ -222,6 +398,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| {
                 if sequence.is_public() {
                     let policy = sequence.public_policy()?;
@@ -256,6 +433,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| {
                 sequence
                     .permissions(user, Some(*origin.id()))
@@ -285,6 +463,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| {
                 let res = if sequence.is_public() {
                     let policy = sequence.public_policy()?;
@@ -318,6 +497,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| {
                 let res = if !sequence.is_public() {
                     let policy = sequence.private_policy(Some(*origin.id()))?;
@@ -343,31 +523,79 @@ impl SequenceStorage {
         }))
     }
 
+    /// Appends to a Sequence, and notifies its subscribers of the appended entries.
     async fn edit(
         &mut self,
         write_op: SequenceOp<SequenceEntry>,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
         let address = write_op.address;
         info!("Editing Sequence chunk");
+        let mut start = 0;
         let result = self
-            .edit_chunk(
-                address,
-                SequenceAction::Append,
-                origin,
-                move |mut sequence| {
-                    sequence.apply_op(write_op)?;
-                    Ok(sequence)
-                },
-            )
+            .edit_chunk(address, SequenceAction::Append, origin, |mut sequence| {
+                start = sequence.len(None)?;
+                sequence.apply_op(write_op)?;
+                Ok(sequence)
+            })
             .await;
-        if result.is_ok() {
-            info!("Editing Sequence chunk SUCCESSFUL!");
-        } else {
-            info!("Editing Sequence chunk FAILEDDD!");
+        let sequence = match result {
+            Ok(sequence) => {
+                info!("Editing Sequence chunk SUCCESSFUL!");
+                sequence
+            }
+            Err(error) => {
+                info!("Editing Sequence chunk FAILEDDD!");
+                return Ok((
+                    vec![
+                        self.ok_or_error(Err::<(), _>(error), msg_id, origin)
+                            .await?,
+                    ],
+                    None,
+                ));
+            }
+        };
+
+        // an op which was applied already appends nothing
+        match self.notify(&sequence, start, msg_id)? {
+            Some(ops) => Ok((ops, Some(ReplicaUpdate::Sequence(sequence)))),
+            None => Ok((vec![], None)),
+        }
+    }
+
+    // Notifies the subscribers to `sequence` which can still read it of the entries appended
+    // from `start` on by the msg with `msg_id`, unless there are none.
+    fn notify(
+        &mut self,
+        sequence: &Sequence,
+        start: u64,
+        msg_id: MessageId,
+    ) -> Result<Option<NodeDuties>> {
+        let entries = sequence
+            .in_range(
+                SequenceIndex::FromStart(start),
+                SequenceIndex::FromEnd(0),
+                None,
+            )?
+            .unwrap_or_default();
+        if entries.is_empty() {
+            return Ok(None);
         }
-        self.ok_or_error(result, msg_id, origin).await
+        let address = *sequence.address();
+        let event = Event::SequenceAppended {
+            address,
+            start,
+            entries,
+        };
+        let ops =
+            self.subscriptions
+                .notify(&address, *address.name(), event, msg_id, |subscriber| {
+                    sequence
+                        .check_permission(SequenceAction::Read, Some(*subscriber.id()))
+                        .is_ok()
+                });
+        Ok(Some(ops))
     }
 
     async fn edit_chunk<F>(
@@ -376,15 +604,66 @@ impl SequenceStorage {
         action: SequenceAction,
         origin: EndUser,
         write_fn: F,
-    ) -> Result<()>
+    ) -> Result<Sequence>
     where
         F: FnOnce(Sequence) -> Result<Sequence>,
     {
         info!("Getting Sequence chunk for Edit");
-        let result = self.get_chunk(address, action, origin)?;
+        let result = self.get_chunk(address, action, origin).await?;
         let sequence = write_fn(result)?;
         info!("Edited Sequence chunk successfully");
-        self.chunks.put(&sequence).await
+        self.chunks.put(&sequence).await?;
+        Ok(sequence)
+    }
+
+    /// Subscribe to the appends to a Sequence, which requires permission to read it. Responds
+    /// with the seconds the subscription lasts unless renewed.
+    async fn subscribe(
+        &mut self,
+        address: SequenceAddress,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        let result = match self
+            .get_chunk(address, SequenceAction::Read, origin)
+            .await
+            .and_then(|_| self.subscriptions.subscribe(address, origin))
+        {
+            Ok(()) => Ok(SUBSCRIPTION_TTL.as_secs()),
+            Err(error) => Err(convert_to_error_message(error)?),
+        };
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::Subscribe(result),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to a response..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        }))
+    }
+
+    /// Unsubscribe from the appends to a Sequence.
+    fn unsubscribe(
+        &mut self,
+        address: SequenceAddress,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> NodeDuty {
+        self.subscriptions.unsubscribe(&address, &origin);
+        NodeDuty::Send(OutgoingMsg {
+            msg: Message::QueryResponse {
+                response: QueryResponse::Unsubscribe(Ok(())),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+                target_section_pk: None,
+            },
+            section_source: false, // strictly this is not correct, but we don't expect responses to a response..
+            dst: DstLocation::EndUser(origin),
+            aggregation: Aggregation::None, // TODO: to_be_aggregated: Aggregation::AtDestination,
+        })
     }
 
     async fn ok_or_error<T>(
@@ -414,6 +693,21 @@ impl SequenceStorage {
     }
 }
 
+// Whether `replica` has the policy of `ours`, and holds its entries followed by others.
+fn extends(replica: &Sequence, ours: &Sequence) -> Result<bool> {
+    let same_policy = if ours.is_public() {
+        replica.public_policy()? == ours.public_policy()?
+    } else {
+        replica.private_policy(None)? == ours.private_policy(None)?
+    };
+    if !same_policy {
+        return Ok(false);
+    }
+    let len = SequenceIndex::FromStart(ours.len(None)?);
+    let start = SequenceIndex::FromStart(0);
+    Ok(replica.in_range(start, len, None)? == ours.in_range(start, len, None)?)
+}
+
 impl Display for SequenceStorage {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         write!(formatter, "SequenceStorage")
@@ -0,0 +1,205 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Subscriptions of clients to the changes of Maps and Sequences.
+
+use crate::{
+    node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
+    Error, Result,
+};
+use sn_messaging::{
+    client::{Event, Message},
+    Aggregation, DstLocation, EndUser, MessageId,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+use xor_name::XorName;
+
+// How long a subscription lasts, unless renewed by subscribing again.
+pub(super) const SUBSCRIPTION_TTL: Duration = Duration::from_secs(10 * 60);
+// How many subscriptions a client can have to the data of a store.
+const MAX_SUBSCRIPTIONS_PER_CLIENT: usize = 100;
+
+/// The clients subscribed to the changes of the data at each address, with the time their
+/// subscriptions expire at.
+pub(super) struct Subscriptions<A> {
+    subscribers: BTreeMap<A, HashMap<EndUser, Instant>>,
+}
+
+impl<A: Ord + Copy> Subscriptions<A> {
+    pub(super) fn new() -> Self {
+        Self {
+            subscribers: BTreeMap::new(),
+        }
+    }
+
+    /// Subscribes `subscriber` to the changes of the data at `address`, or renews its
+    /// subscription for another `SUBSCRIPTION_TTL`.
+    pub(super) fn subscribe(&mut self, address: A, subscriber: EndUser) -> Result<()> {
+        let now = Instant::now();
+        self.remove_expired(now);
+        let renewal = matches!(
+            self.subscribers.get(&address),
+            Some(subscribers) if subscribers.contains_key(&subscriber)
+        );
+        if !renewal {
+            let count = self
+                .subscribers
+                .values()
+                .flat_map(|subscribers| subscribers.keys())
+                .filter(|existing| existing.id() == subscriber.id())
+                .count();
+            if count >= MAX_SUBSCRIPTIONS_PER_CLIENT {
+                return Err(Error::TooManySubscriptions);
+            }
+        }
+        let _ = self
+            .subscribers
+            .entry(address)
+            .or_default()
+            .insert(subscriber, now + SUBSCRIPTION_TTL);
+        Ok(())
+    }
+
+    pub(super) fn unsubscribe(&mut self, address: &A, subscriber: &EndUser) {
+        if let Some(subscribers) = self.subscribers.get_mut(address) {
+            let _ = subscribers.remove(subscriber);
+            if subscribers.is_empty() {
+                let _ = self.subscribers.remove(address);
+            }
+        }
+    }
+
+    /// Drops the subscriptions to deleted data.
+    pub(super) fn remove(&mut self, address: &A) {
+        let _ = self.subscribers.remove(address);
+    }
+
+    /// Sends `event` to the subscribers to the data at `address`, which is named `name`. The
+    /// event is caused by the cmd with `correlation_id`, which each of our elders processes, so
+    /// its id is derived from that cmd's, for subscribers to get it once. The permissions may
+    /// have changed since they subscribed, so those which `can_read` the data no more are
+    /// dropped.
+    pub(super) fn notify<F>(
+        &mut self,
+        address: &A,
+        name: XorName,
+        event: Event,
+        correlation_id: MessageId,
+        can_read: F,
+    ) -> NodeDuties
+    where
+        F: Fn(&EndUser) -> bool,
+    {
+        let now = Instant::now();
+        let subscribers = match self.subscribers.get_mut(address) {
+            Some(subscribers) => subscribers,
+            None => return vec![],
+        };
+        subscribers.retain(|subscriber, expiry| *expiry > now && can_read(subscriber));
+        let id = MessageId::combine(vec![correlation_id.0, name]);
+        let ops = subscribers
+            .keys()
+            .map(|subscriber| {
+                NodeDuty::Send(OutgoingMsg {
+                    msg: Message::Event {
+                        event: event.clone(),
+                        id,
+                        correlation_id,
+                        target_section_pk: None,
+                    },
+                    section_source: false, // strictly this is not correct, but we don't expect responses to an event..
+                    dst: DstLocation::EndUser(*subscriber),
+                    aggregation: Aggregation::None,
+                })
+            })
+            .collect();
+        if subscribers.is_empty() {
+            let _ = self.subscribers.remove(address);
+        }
+        ops
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        for subscribers in self.subscribers.values_mut() {
+            subscribers.retain(|_, expiry| *expiry > now);
+        }
+        self.subscribers
+            .retain(|_, subscribers| !subscribers.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sn_data_types::Keypair;
+
+    fn client() -> EndUser {
+        EndUser::AllClients(Keypair::new_ed25519(&mut rand::thread_rng()).public_key())
+    }
+
+    #[test]
+    fn subscriptions_are_limited_per_client() -> Result<()> {
+        let mut subscriptions = Subscriptions::new();
+        let subscriber = client();
+        for address in 0..MAX_SUBSCRIPTIONS_PER_CLIENT {
+            subscriptions.subscribe(address, subscriber)?;
+        }
+        assert!(matches!(
+            subscriptions.subscribe(MAX_SUBSCRIPTIONS_PER_CLIENT, subscriber),
+            Err(Error::TooManySubscriptions)
+        ));
+
+        // renewals and other clients are not limited
+        subscriptions.subscribe(0, subscriber)?;
+        subscriptions.subscribe(MAX_SUBSCRIPTIONS_PER_CLIENT, client())?;
+
+        // unsubscribing makes room again
+        subscriptions.unsubscribe(&0, &subscriber);
+        subscriptions.subscribe(MAX_SUBSCRIPTIONS_PER_CLIENT, subscriber)?;
+        Ok(())
+    }
+
+    #[test]
+    fn only_subscribers_are_notified() -> Result<()> {
+        let mut subscriptions = Subscriptions::new();
+        subscriptions.subscribe(0, client())?;
+        let event = Event::MapChanged {
+            address: sn_data_types::MapAddress::Seq {
+                name: XorName::random(),
+                tag: 0,
+            },
+            version: 0,
+            keys: Default::default(),
+        };
+
+        let notify = |subscriptions: &mut Subscriptions<_>, address, can_read: bool| {
+            subscriptions.notify(
+                &address,
+                XorName::random(),
+                event.clone(),
+                MessageId::new(),
+                |_| can_read,
+            )
+        };
+        assert_eq!(notify(&mut subscriptions, 0, true).len(), 1);
+        assert!(notify(&mut subscriptions, 1, true).is_empty());
+
+        // subscriptions to deleted data are dropped
+        subscriptions.remove(&0);
+        assert!(notify(&mut subscriptions, 0, true).is_empty());
+
+        // and so are those of subscribers which can no longer read the data
+        subscriptions.subscribe(0, client())?;
+        assert!(notify(&mut subscriptions, 0, false).is_empty());
+        assert!(notify(&mut subscriptions, 0, true).is_empty());
+        Ok(())
+    }
+}
@@ -8,28 +8,34 @@
 
 use super::{
     blob_register::BlobRegister, elder_stores::ElderStores, map_storage::MapStorage,
-    sequence_storage::SequenceStorage,
+    replication::ReplicaUpdate, sequence_storage::SequenceStorage,
 };
 use crate::Result;
-use crate::{network::Network, node_ops::NodeDuty};
+use crate::{
+    network::Network,
+    node_ops::{NodeDuties, NodeDuty},
+};
 use log::info;
 use sn_messaging::{
     client::{BlobWrite, DataCmd, MapWrite, SequenceWrite},
     EndUser, MessageId,
 };
 
+/// Applies `cmd`, returning the duties it gives rise to, along with the new state of the Map or
+/// Sequence it changed, if any.
 pub(super) async fn get_result(
     cmd: DataCmd,
     msg_id: MessageId,
     origin: EndUser,
     stores: &mut ElderStores,
-) -> Result<NodeDuty> {
+) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
     use DataCmd::*;
     info!("Writing Data");
     match cmd {
         Blob(write) => {
             info!("Writing Blob");
-            blob(write, stores.blob_register_mut(), msg_id, origin).await
+            let duty = blob(write, stores.blob_register_mut(), msg_id, origin).await?;
+            Ok((NodeDuties::from(duty), None))
         }
         Map(write) => {
             info!("Writing Map");
@@ -56,7 +62,7 @@ async fn map(
     storage: &mut MapStorage,
     msg_id: MessageId,
     origin: EndUser,
-) -> Result<NodeDuty> {
+) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
     storage.write(write, msg_id, origin).await
 }
 
@@ -65,6 +71,6 @@ async fn sequence(
     storage: &mut SequenceStorage,
     msg_id: MessageId,
     origin: EndUser,
-) -> Result<NodeDuty> {
+) -> Result<(NodeDuties, Option<ReplicaUpdate>)> {
     storage.write(write, msg_id, origin).await
 }
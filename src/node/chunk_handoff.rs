@@ -0,0 +1,120 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    chunks::Chunks,
+    node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
+    Node, Result,
+};
+use log::{info, warn};
+use sn_data_types::BlobAddress;
+use sn_messaging::{
+    client::{Message, NodeCmd, NodeSystemCmd},
+    Aggregation, DstLocation, MessageId,
+};
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+// How many chunk addresses are announced per msg.
+const ADDRESSES_PER_ANNOUNCEMENT: usize = 1000;
+// How long our section has to copy our chunks to other adults, before we announce those not yet
+// copied again.
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// The chunks held by an adult which was promoted, kept until
+/// the section has copied them to other adults.
+pub(crate) struct ChunkHandoff {
+    chunks: Chunks,
+    remaining: BTreeSet<BlobAddress>,
+    announced: Instant,
+}
+
+impl ChunkHandoff {
+    pub(super) fn new(chunks: Chunks, remaining: BTreeSet<BlobAddress>) -> Self {
+        Self {
+            chunks,
+            remaining,
+            announced: Instant::now(),
+        }
+    }
+
+    /// The chunks being handed off, which we still serve to their new holders.
+    pub(crate) fn chunks(&self) -> &Chunks {
+        &self.chunks
+    }
+
+    /// Stops the handoff, keeping the chunks which weren't dropped yet.
+    pub(crate) fn into_chunks(self) -> Chunks {
+        self.chunks
+    }
+}
+
+impl Node {
+    /// Announces the chunks we hold to our section, as we are no longer an adult,
+    /// so that elders pick new holders for them which copy them from us.
+    pub(super) async fn announce_chunks(&self, addresses: &BTreeSet<BlobAddress>) -> NodeDuties {
+        let section = self.network_api.our_prefix().await.name();
+        let addresses: Vec<BlobAddress> = addresses.iter().copied().collect();
+        addresses
+            .chunks(ADDRESSES_PER_ANNOUNCEMENT)
+            .map(|batch| {
+                NodeDuty::Send(OutgoingMsg {
+                    msg: Message::NodeCmd {
+                        cmd: NodeCmd::System(NodeSystemCmd::HandOffChunks {
+                            addresses: batch.iter().copied().collect(),
+                        }),
+                        id: MessageId::new(),
+                        target_section_pk: None,
+                    },
+                    section_source: false, // sent as single node
+                    dst: DstLocation::Section(section),
+                    aggregation: Aggregation::None,
+                })
+            })
+            .collect()
+    }
+
+    /// Called when a chunk has been copied to a new holder. The handed off chunks are deleted
+    /// once all of them have been copied.
+    pub(crate) async fn chunk_handed_off(&mut self, address: &BlobAddress) -> Result<()> {
+        let done = match &mut self.stores.chunk_handoff {
+            Some(handoff) => {
+                let _ = handoff.remaining.remove(address);
+                handoff.remaining.is_empty()
+            }
+            None => return Ok(()),
+        };
+        if done {
+            if let Some(mut handoff) = self.stores.chunk_handoff.take() {
+                info!("All chunks handed off");
+                handoff.chunks.clear().await?;
+                handoff.chunks.close().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Announces the chunks which the section didn't copy in time again, as the new holders
+    /// may have left, or the announcement been lost. The chunks are kept until copied.
+    pub(crate) async fn check_chunk_handoff(&mut self) -> NodeDuties {
+        let remaining = match &mut self.stores.chunk_handoff {
+            Some(handoff) if handoff.announced.elapsed() >= HANDOFF_TIMEOUT => {
+                handoff.announced = Instant::now();
+                handoff.remaining.clone()
+            }
+            _ => return vec![],
+        };
+        warn!(
+            "{} chunks were not copied in time, announcing them again",
+            remaining.len()
+        );
+        self.announce_chunks(&remaining).await
+    }
+}
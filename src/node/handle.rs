@@ -21,7 +21,7 @@ use crate::{
     Error, Node, Result,
 };
 use dashmap::DashMap;
-use log::{debug, info};
+use log::{debug, info, warn};
 use sn_data_types::{CreditAgreementProof, CreditId, PublicKey, SectionElders, WalletHistory};
 use sn_messaging::{
     client::{Message, NodeCmd, NodeQuery, Query},
@@ -40,9 +40,13 @@ impl Node {
                 newbie,
             } => {
                 if newbie {
-                    self.level_up().await?;
+                    self.level_up().await
+                } else {
+                    if let Some(meta_data) = &mut self.meta_data {
+                        meta_data.update_elders().await;
+                    }
+                    Ok(vec![])
                 }
-                Ok(vec![])
             }
             NodeDuty::SplitSection {
                 our_key,
@@ -51,13 +55,48 @@ impl Node {
                 newbie,
             } => {
                 if newbie {
-                    self.begin_split_as_newbie(our_key, our_prefix).await?;
-                    Ok(vec![])
+                    self.begin_split_as_newbie(our_key, our_prefix).await
                 } else {
+                    if let Some(meta_data) = &mut self.meta_data {
+                        meta_data.update_elders().await;
+                    }
                     self.begin_split_as_oldie(our_prefix, our_key, sibling_key)
                         .await
                 }
             }
+            NodeDuty::ReceiveDataHandover {
+                blob_records,
+                maps,
+                sequences,
+                msg_id,
+                sender,
+            } => {
+                let our_prefix = self.network_api.our_prefix().await;
+                let meta_data = self.get_metadata()?;
+                meta_data
+                    .receive_handover(blob_records, maps, sequences, msg_id, sender, our_prefix)
+                    .await
+            }
+            NodeDuty::CompleteDataHandover {
+                correlation_id,
+                sender,
+            } => {
+                if let Some(meta_data) = &mut self.meta_data {
+                    meta_data.complete_handover(correlation_id, sender).await?;
+                } else {
+                    self.stores
+                        .complete_metadata_handover(correlation_id, sender)
+                        .await?;
+                }
+                Ok(vec![])
+            }
+            NodeDuty::RetryDataHandovers => {
+                if let Some(meta_data) = &mut self.meta_data {
+                    meta_data.retry_handovers().await
+                } else {
+                    self.stores.retry_metadata_handover().await
+                }
+            }
             // a remote section asks for the replicas of their wallet
             NodeDuty::GetSectionElders { msg_id, origin } => {
                 Ok(vec![self.get_section_elders(msg_id, origin).await?])
@@ -135,20 +174,7 @@ impl Node {
                 node_rewards,
                 user_wallets,
             } => Ok(vec![self.synch_state(node_rewards, user_wallets).await?]),
-            NodeDuty::LevelDown => {
-                self.meta_data = None;
-                self.transfers = None;
-                self.section_funds = None;
-                self.chunks = Some(
-                    Chunks::new(
-                        self.node_info.node_name,
-                        self.node_info.root_dir.as_path(),
-                        self.used_space.clone(),
-                    )
-                    .await?,
-                );
-                Ok(vec![])
-            }
+            NodeDuty::LevelDown => self.level_down().await,
             //
             // ----------- Transfers -----------
             NodeDuty::GetTransferReplicaEvents { msg_id, origin } => {
@@ -216,6 +242,7 @@ impl Node {
                 read,
                 msg_id,
                 origin,
+                elder,
             } => {
                 // TODO: remove this conditional branching
                 // routing should take care of this
@@ -227,7 +254,7 @@ impl Node {
                     .matches(&&data_section_addr)
                 {
                     let chunks = self.get_chunks()?;
-                    Ok(vec![chunks.read(&read, msg_id, origin).await?])
+                    chunks.read(&read, msg_id, origin, elder).await
                 } else {
                     Ok(vec![NodeDuty::Send(OutgoingMsg {
                         msg: Message::NodeQuery {
@@ -300,7 +327,7 @@ impl Node {
             }
             NodeDuty::ProcessWrite { cmd, id, origin } => {
                 let meta_data = self.get_metadata()?;
-                Ok(vec![meta_data.write(cmd, id, origin).await?])
+                meta_data.write(cmd, id, origin).await
             }
             NodeDuty::ProcessDataPayment { msg, origin } => {
                 let transfers = self.get_transfers()?;
@@ -325,7 +352,16 @@ impl Node {
                 new_holder,
                 id,
             } => {
-                let chunks = self.get_chunks()?;
+                let chunks = if let Some(chunks) = &self.stores.chunks {
+                    chunks
+                } else if let Some(handoff) = &self.stores.chunk_handoff {
+                    // a promoted adult still serves the chunks it is handing off
+                    handoff.chunks()
+                } else {
+                    return Err(Error::InvalidOperation(
+                        "No immutable chunks at this node".to_string(),
+                    ));
+                };
                 Ok(vec![
                     chunks
                         .get_chunk_for_replication(address, id, new_holder)
@@ -342,19 +378,205 @@ impl Node {
                     self.network_api.our_name().await,
                 ]);
                 if msg_id == correlation_id {
+                    let address = *data.address();
                     let chunks = self.get_chunks()?;
-                    Ok(vec![chunks.store_replicated_chunk(data).await?])
+                    let mut ops = NodeDuties::from(chunks.store_replicated_chunk(data).await?);
+                    ops.push(
+                        self.notify_replication_completed(address, correlation_id)
+                            .await?,
+                    );
+                    Ok(ops)
                 } else {
                     log::warn!("Invalid message ID");
                     Ok(vec![])
                 }
             }
+            NodeDuty::ChunkServed {
+                address,
+                holder,
+                correlation_id,
+            } => {
+                let meta_data = self.get_metadata()?;
+                Ok(vec![
+                    meta_data
+                        .receive_read_outcome(Ok(address), holder, correlation_id)
+                        .await?,
+                ])
+            }
+            NodeDuty::ChunkReadFailed {
+                error,
+                holder,
+                correlation_id,
+            } => {
+                let meta_data = self.get_metadata()?;
+                Ok(vec![
+                    meta_data
+                        .receive_read_outcome(Err(error), holder, correlation_id)
+                        .await?,
+                ])
+            }
+            NodeDuty::ScrubChunks => {
+                if let Some(meta_data) = &self.meta_data {
+                    meta_data.log_cache_stats();
+                }
+                for dir in self.stores.used_space.dirs().await {
+                    info!("Data dir {}", dir);
+                }
+                if let Some(chunks) = &mut self.stores.chunks {
+                    chunks.scrub().await
+                } else {
+                    // elders don't hold chunks
+                    Ok(vec![])
+                }
+            }
+            NodeDuty::RepairChunk { address, holder } => {
+                let metadata = self.get_metadata()?;
+                metadata.repair_chunk(address, holder).await
+            }
+            NodeDuty::AuditReplication => {
+                let mut ops = self.check_chunk_handoff().await;
+                // only elders keep chunk metadata
+                if let Some(meta_data) = &mut self.meta_data {
+                    ops.extend(meta_data.audit_replication().await?);
+                }
+                Ok(ops)
+            }
+            NodeDuty::RetryReads => {
+                if let Some(meta_data) = &mut self.meta_data {
+                    meta_data.retry_reads().await
+                } else {
+                    // only elders read chunks from holders
+                    Ok(vec![])
+                }
+            }
+            NodeDuty::ChallengeStorage => {
+                if let Some(meta_data) = &mut self.meta_data {
+                    meta_data.challenge_storage().await
+                } else {
+                    // only elders keep chunk metadata
+                    Ok(vec![])
+                }
+            }
+            NodeDuty::ProveStorage {
+                address,
+                nonce,
+                msg_id,
+                origin,
+            } => {
+                let chunks = self.get_chunks()?;
+                Ok(vec![
+                    chunks.prove_storage(address, nonce, msg_id, origin).await?,
+                ])
+            }
+            NodeDuty::ReceiveStorageProof {
+                address,
+                nonce,
+                proof,
+                holder,
+                correlation_id,
+            } => {
+                let metadata = self.get_metadata()?;
+                metadata
+                    .receive_storage_proof(address, nonce, proof, holder, correlation_id)
+                    .await
+            }
+            NodeDuty::ReplicationCompleted {
+                address,
+                holder,
+                msg_id,
+            } => {
+                self.chunk_handed_off(&address).await?;
+                let metadata = self.get_metadata()?;
+                Ok(vec![
+                    metadata.finish_replication(address, holder, msg_id).await?,
+                ])
+            }
+            NodeDuty::HandOffChunks { holder, addresses } => {
+                let metadata = self.get_metadata()?;
+                metadata.hand_off_chunks(holder, addresses).await
+            }
+            NodeDuty::RetryChunkStore {
+                data,
+                holder,
+                msg_id,
+                origin,
+            } => {
+                let metadata = self.get_metadata()?;
+                Ok(vec![
+                    metadata
+                        .retry_chunk_store(data, holder, msg_id, origin)
+                        .await?,
+                ])
+            }
+            NodeDuty::ReceiveMapReplica {
+                map,
+                msg_id,
+                sender,
+            } => {
+                if let Some(meta_data) = &mut self.meta_data {
+                    meta_data.merge_map_replica(map, msg_id, sender).await
+                } else if !self.is_our_elder(&sender).await {
+                    Ok(vec![])
+                } else if let Some(replicas) = &mut self.stores.replicas {
+                    Ok(NodeDuties::from(replicas.receive_map(map).await?))
+                } else {
+                    Ok(vec![])
+                }
+            }
+            NodeDuty::ReceiveSequenceReplica {
+                sequence,
+                msg_id,
+                sender,
+            } => {
+                if let Some(meta_data) = &mut self.meta_data {
+                    meta_data
+                        .merge_sequence_replica(sequence, msg_id, sender)
+                        .await
+                } else if !self.is_our_elder(&sender).await {
+                    Ok(vec![])
+                } else if let Some(replicas) = &mut self.stores.replicas {
+                    Ok(NodeDuties::from(replicas.receive_sequence(sequence).await?))
+                } else {
+                    Ok(vec![])
+                }
+            }
+            NodeDuty::DeleteMapReplica { address, sender } => {
+                if !self.is_our_elder(&sender).await {
+                    return Ok(vec![]);
+                }
+                if let Some(replicas) = &mut self.stores.replicas {
+                    replicas.delete_map(&address).await?;
+                }
+                Ok(vec![])
+            }
+            NodeDuty::DeleteSequenceReplica { address, sender } => {
+                if !self.is_our_elder(&sender).await {
+                    return Ok(vec![]);
+                }
+                if let Some(replicas) = &mut self.stores.replicas {
+                    replicas.delete_sequence(&address).await?;
+                }
+                Ok(vec![])
+            }
             NodeDuty::NoOp => Ok(vec![]),
         }
     }
 
+    // Replicas are only taken from the elders of our section.
+    async fn is_our_elder(&self, sender: &XorName) -> bool {
+        if self.network_api.our_elder_names().await.contains(sender) {
+            true
+        } else {
+            warn!(
+                "Dropping replica msg from {:?}, not one of our elders",
+                sender
+            );
+            false
+        }
+    }
+
     fn get_chunks(&mut self) -> Result<&mut Chunks> {
-        if let Some(chunks) = &mut self.chunks {
+        if let Some(chunks) = &mut self.stores.chunks {
             Ok(chunks)
         } else {
             Err(Error::InvalidOperation(
@@ -26,8 +26,8 @@ use section_funds::{
     Credits,
 };
 use sn_data_types::{
-    ActorHistory, CreditAgreementProof, CreditId, NodeAge, PublicKey, SectionElders, Token,
-    WalletHistory,
+    ActorHistory, BlobAddress, CreditAgreementProof, CreditId, NodeAge, PublicKey, SectionElders,
+    Token, WalletHistory,
 };
 use sn_messaging::{
     client::{
@@ -46,7 +46,7 @@ impl Node {
         &mut self,
         our_key: PublicKey,
         our_prefix: Prefix,
-    ) -> Result<()> {
+    ) -> Result<NodeDuties> {
         let section_key = self.network_api.section_public_key().await?;
         if our_key != section_key {
             return Err(Error::Logic(format!(
@@ -57,7 +57,7 @@ impl Node {
 
         debug!("begin_split_as_newbie");
 
-        self.level_up().await?;
+        let ops = self.level_up().await?;
 
         let section = OurSection {
             our_prefix,
@@ -78,7 +78,7 @@ impl Node {
             payments: Default::default(),
         });
 
-        Ok(())
+        Ok(ops)
     }
 
     /// Called on split reported from routing layer.
@@ -130,7 +130,15 @@ impl Node {
             },
             ElderSigning::new(self.network_api.clone()).await?,
         );
-        ops.push(process.reward_and_mint(wallets.node_wallets()).await?);
+        let storage_failures = match &self.meta_data {
+            Some(meta_data) => meta_data.storage_failures()?,
+            None => Default::default(),
+        };
+        ops.push(
+            process
+                .reward_and_mint(wallets.node_wallets(), storage_failures)
+                .await?,
+        );
 
         self.section_funds = Some(SectionFunds::Churning {
             process,
@@ -173,6 +181,11 @@ impl Node {
             aggregation: Aggregation::None,               // AtDestination
         }));
 
+        // hand over the data our sibling is now responsible for
+        if let Some(meta_data) = &mut self.meta_data {
+            ops.extend(meta_data.split_section(our_prefix).await?);
+        }
+
         Ok(ops)
     }
 
@@ -245,18 +258,46 @@ impl Node {
         }))
     }
 
-    ///
-    pub(crate) async fn register_wallet(&self) -> OutgoingMsg {
-        let address = self.network_api.our_prefix().await.name();
-        OutgoingMsg {
-            msg: Message::NodeCmd {
-                cmd: NodeCmd::System(NodeSystemCmd::RegisterWallet(self.node_info.reward_key)),
+    /// Tells our section that we stored our copy of a replicated chunk.
+    pub(crate) async fn notify_replication_completed(
+        &self,
+        address: BlobAddress,
+        correlation_id: MessageId,
+    ) -> Result<NodeDuty> {
+        let proof = self.network_api.sign_as_node(&address).await?;
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::NodeEvent {
+                event: NodeEvent::ReplicationCompleted {
+                    chunk: address,
+                    proof,
+                },
                 id: MessageId::new(),
+                correlation_id,
                 target_section_pk: None,
             },
             section_source: false, // sent as single node
-            dst: DstLocation::Section(address),
+            dst: DstLocation::Section(*address.name()),
             aggregation: Aggregation::None,
-        }
+        }))
+    }
+
+    ///
+    pub(crate) async fn register_wallet(&self) -> OutgoingMsg {
+        let address = self.network_api.our_prefix().await.name();
+        register_wallet(self.node_info.reward_key, address)
+    }
+}
+
+/// Registers `reward_key` as our reward wallet with the section at `address`.
+pub(super) fn register_wallet(reward_key: PublicKey, address: XorName) -> OutgoingMsg {
+    OutgoingMsg {
+        msg: Message::NodeCmd {
+            cmd: NodeCmd::System(NodeSystemCmd::RegisterWallet(reward_key)),
+            id: MessageId::new(),
+            target_section_pk: None,
+        },
+        section_source: false, // sent as single node
+        dst: DstLocation::Section(address),
+        aggregation: Aggregation::None,
     }
 }
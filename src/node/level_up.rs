@@ -6,6 +6,7 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use super::interaction::register_wallet;
 use crate::{
     capacity::{Capacity, ChunkHolderDbs, RateLimit},
     event_mapping::{map_routing_event, LazyError, Mapping, MsgContext},
@@ -28,30 +29,35 @@ use sn_messaging::{
     client::{Message, NodeCmd, NodeSystemCmd},
     Aggregation, DstLocation, MessageId,
 };
-use sn_routing::XorName;
+use sn_routing::{Prefix, XorName};
 use sn_transfers::TransferActor;
 use std::collections::BTreeMap;
 
 impl Node {
     /// Level up on promotion
-    pub async fn level_up(&mut self) -> Result<()> {
+    pub async fn level_up(&mut self) -> Result<NodeDuties> {
         //
-        // do not hande immutable chunks anymore
-        self.chunks = None;
-        self.used_space.reset().await;
-
-        //
-        // start handling metadata
-        let dbs = ChunkHolderDbs::new(self.node_info.path())?;
+        // do not hande immutable chunks and replicas anymore,
+        // but keep the chunks we hold until they are copied to other adults,
+        // and start handling metadata
         let reader = AdultReader::new(self.network_api.clone());
-        let meta_data =
-            Metadata::new(&self.node_info.path(), &self.used_space, dbs, reader).await?;
+        let (handed_off, meta_data) = self
+            .stores
+            .promote(reader, self.mutable_copy_count, self.map_history_depth)
+            .await?;
+        let ops = if handed_off.is_empty() {
+            vec![]
+        } else {
+            info!("Handing off {} chunks", handed_off.len());
+            self.announce_chunks(&handed_off).await
+        };
+        let dbs = meta_data.chunk_holder_dbs();
         self.meta_data = Some(meta_data);
 
         //
         // start handling transfers
-        let dbs = ChunkHolderDbs::new(self.node_info.root_dir.as_path())?;
-        let rate_limit = RateLimit::new(self.network_api.clone(), Capacity::new(dbs.clone()));
+        // (sharing the chunk holder dbs, which can only be opened once)
+        let rate_limit = RateLimit::new(self.network_api.clone(), Capacity::new(dbs));
         let user_wallets = BTreeMap::<PublicKey, ActorHistory>::new();
         let replicas = transfer_replicas(&self.node_info, &self.network_api, user_wallets).await?;
         self.transfers = Some(Transfers::new(replicas, rate_limit));
@@ -63,7 +69,33 @@ impl Node {
             payments: Default::default(),
         });
 
-        Ok(())
+        Ok(ops)
+    }
+
+    /// Level down on demotion, handing our elder state over to
+    /// the remaining elders and resuming adult duties.
+    pub async fn level_down(&mut self) -> Result<NodeDuties> {
+        let meta_data = match self.meta_data.take() {
+            Some(meta_data) => meta_data,
+            // we are not an elder
+            None => return Ok(vec![]),
+        };
+        let our_prefix = self.network_api.our_prefix().await;
+
+        //
+        // hand over metadata, keeping it until the remaining elders confirm it,
+        // and start handling immutable chunks and replicas again
+        let mut ops = self.stores.demote(meta_data, our_prefix).await?;
+
+        //
+        // hand over reward wallets, and stop handling elder duties
+        ops.extend(leave_elder_duties(
+            our_prefix,
+            self.node_info.reward_key,
+            self.transfers.take(),
+            self.section_funds.take(),
+        ));
+        Ok(ops)
     }
 
     /// Continue the level up and handle more responsibilities.
@@ -105,3 +137,83 @@ impl Node {
         }
     }
 }
+
+/// Tears down the transfers and reward wallets we kept as an elder, handing the wallets over to
+/// the remaining elders of `section`, with which we then register our reward wallet as an adult.
+fn leave_elder_duties(
+    section: Prefix,
+    reward_key: PublicKey,
+    transfers: Option<Transfers>,
+    section_funds: Option<SectionFunds>,
+) -> NodeDuties {
+    let user_wallets = match &transfers {
+        Some(transfers) => transfers.user_wallets(),
+        None => BTreeMap::new(),
+    };
+    let node_wallets = match &section_funds {
+        Some(SectionFunds::KeepingNodeWallets { wallets, .. })
+        | Some(SectionFunds::Churning { wallets, .. }) => wallets.node_wallets(),
+        None => BTreeMap::new(),
+    };
+    vec![
+        NodeDuty::Send(OutgoingMsg {
+            msg: Message::NodeCmd {
+                cmd: NodeCmd::System(NodeSystemCmd::ReceiveExistingData {
+                    node_rewards: node_wallets,
+                    user_wallets,
+                }),
+                id: MessageId::new(),
+                target_section_pk: None,
+            },
+            section_source: false, // sent as single node
+            dst: DstLocation::Section(section.name()),
+            aggregation: Aggregation::None,
+        }),
+        NodeDuty::Send(register_wallet(reward_key, section.name())),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaving_elder_duties_hands_over_wallets_and_registers_ours() {
+        let section = Prefix::default();
+        let reward_key = PublicKey::from(bls::SecretKey::random().public_key());
+        let node = XorName::random();
+        let node_wallet = PublicKey::from(bls::SecretKey::random().public_key());
+        let mut node_wallets = BTreeMap::new();
+        let _ = node_wallets.insert(node, (5, node_wallet));
+        let section_funds = SectionFunds::KeepingNodeWallets {
+            wallets: RewardWallets::new(node_wallets.clone()),
+            payments: Default::default(),
+        };
+
+        let ops = leave_elder_duties(section, reward_key, None, Some(section_funds));
+
+        assert_eq!(ops.len(), 2);
+        assert!(matches!(
+            &ops[0],
+            NodeDuty::Send(OutgoingMsg {
+                msg: Message::NodeCmd {
+                    cmd: NodeCmd::System(NodeSystemCmd::ReceiveExistingData { node_rewards, .. }),
+                    ..
+                },
+                dst: DstLocation::Section(dst),
+                ..
+            }) if *node_rewards == node_wallets && *dst == section.name()
+        ));
+        assert!(matches!(
+            &ops[1],
+            NodeDuty::Send(OutgoingMsg {
+                msg: Message::NodeCmd {
+                    cmd: NodeCmd::System(NodeSystemCmd::RegisterWallet(key)),
+                    ..
+                },
+                dst: DstLocation::Section(dst),
+                ..
+            }) if *key == reward_key && *dst == section.name()
+        ));
+    }
+}
@@ -6,16 +6,18 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+mod chunk_handoff;
 mod handle;
 mod interaction;
 mod level_up;
 mod messaging;
+mod stores;
 mod update_transfers;
 
+use self::stores::NodeStores;
 use crate::{
     capacity::{Capacity, ChunkHolderDbs, RateLimit},
-    chunk_store::UsedSpace,
-    chunks::Chunks,
+    chunk_store::{StoreConfig, UsedSpace},
     event_mapping::{map_routing_event, LazyError, Mapping, MsgContext},
     metadata::{adult_reader::AdultReader, Metadata},
     node_ops::{NodeDuties, NodeDuty},
@@ -41,7 +43,20 @@ use std::sync::Arc;
 use std::{
     fmt::{self, Display, Formatter},
     net::SocketAddr,
+    time::Duration,
 };
+use tokio::time::{interval_at, Instant};
+
+// How often the next batch of stored chunks is verified against their content.
+const CHUNK_SCRUB_INTERVAL: Duration = Duration::from_secs(60);
+// How often elders check that all chunks have enough holders.
+const REPLICATION_AUDIT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+// How often elders check for chunk reads which holders didn't answer in time.
+const READ_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+// How often elders check for data handovers which their sibling section didn't confirm in time.
+const HANDOVER_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+// How often elders challenge the holders of a chunk to prove that they store it.
+const STORAGE_CHALLENGE_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Static info about the node.
 #[derive(Clone)]
@@ -70,10 +85,13 @@ pub struct Node {
     network_api: Network,
     network_events: EventStream,
     node_info: NodeInfo,
-    used_space: UsedSpace,
     prefix: Prefix,
-    // immutable chunks
-    chunks: Option<Chunks>,
+    // chunks and replicas, held as an adult
+    stores: NodeStores,
+    // how many adults keep a replica of each Map and Sequence
+    mutable_copy_count: usize,
+    // how many earlier versions of each Map are kept, as an elder
+    map_history_depth: u64,
     // data operations
     meta_data: Option<Metadata>,
     // transfers
@@ -120,20 +138,21 @@ impl Node {
             reward_key,
         };
 
-        let used_space = UsedSpace::new(config.max_capacity());
+        let used_space = UsedSpace::from_config(config);
+        let store_config = StoreConfig::new(config, node_info.path()).await?;
 
         let mut node = Self {
             prefix: network_api.our_prefix().await,
-            chunks: Some(
-                Chunks::new(
-                    node_info.node_name,
-                    node_info.root_dir.as_path(),
-                    used_space.clone(),
-                )
-                .await?,
-            ),
+            stores: NodeStores::new(
+                node_info.node_name,
+                node_info.path(),
+                used_space,
+                store_config,
+            )
+            .await?,
+            mutable_copy_count: config.mutable_copy_count(),
+            map_history_depth: config.map_history_depth(),
             node_info,
-            used_space,
             network_api,
             network_events,
             meta_data: None,
@@ -143,7 +162,8 @@ impl Node {
 
         // was not necessary when AE changes were in
         if config.is_first() {
-            node.level_up().await?;
+            // the genesis node holds no chunks to hand off
+            let _ = node.level_up().await?;
         }
 
         messaging::send(node.register_wallet().await, &node.network_api).await;
@@ -160,11 +180,50 @@ impl Node {
     /// Blocks until the node is terminated, which is done
     /// by client sending in a `Command` to free it.
     pub async fn run(&mut self) -> Result<()> {
-        while let Some(event) = self.network_events.next().await {
-            // tokio spawn should only be needed around intensive tasks, ie sign/verify
-            match map_routing_event(event, &self.network_api).await {
-                Mapping::Ok { op, ctx } => self.process_while_any(op, ctx).await,
-                Mapping::Error(error) => handle_error(error),
+        let mut scrub_timer =
+            interval_at(Instant::now() + CHUNK_SCRUB_INTERVAL, CHUNK_SCRUB_INTERVAL);
+        let mut audit_timer = interval_at(
+            Instant::now() + REPLICATION_AUDIT_INTERVAL,
+            REPLICATION_AUDIT_INTERVAL,
+        );
+        let mut read_retry_timer =
+            interval_at(Instant::now() + READ_RETRY_INTERVAL, READ_RETRY_INTERVAL);
+        let mut handover_timer = interval_at(
+            Instant::now() + HANDOVER_RETRY_INTERVAL,
+            HANDOVER_RETRY_INTERVAL,
+        );
+        let mut challenge_timer = interval_at(
+            Instant::now() + STORAGE_CHALLENGE_INTERVAL,
+            STORAGE_CHALLENGE_INTERVAL,
+        );
+        loop {
+            tokio::select! {
+                event = self.network_events.next() => {
+                    let event = match event {
+                        Some(event) => event,
+                        None => break,
+                    };
+                    // tokio spawn should only be needed around intensive tasks, ie sign/verify
+                    match map_routing_event(event, &self.network_api).await {
+                        Mapping::Ok { op, ctx } => self.process_while_any(op, ctx).await,
+                        Mapping::Error(error) => handle_error(error),
+                    }
+                }
+                _ = scrub_timer.tick() => {
+                    self.process_while_any(NodeDuty::ScrubChunks, None).await
+                }
+                _ = audit_timer.tick() => {
+                    self.process_while_any(NodeDuty::AuditReplication, None).await
+                }
+                _ = read_retry_timer.tick() => {
+                    self.process_while_any(NodeDuty::RetryReads, None).await
+                }
+                _ = handover_timer.tick() => {
+                    self.process_while_any(NodeDuty::RetryDataHandovers, None).await
+                }
+                _ = challenge_timer.tick() => {
+                    self.process_while_any(NodeDuty::ChallengeStorage, None).await
+                }
             }
         }
 
@@ -0,0 +1,406 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::chunk_handoff::ChunkHandoff;
+use crate::{
+    capacity::ChunkHolderDbs,
+    chunk_store::{StoreConfig, UsedSpace},
+    chunks::{Chunks, MutableReplicas},
+    metadata::{adult_reader::AdultReader, Metadata},
+    node_ops::NodeDuties,
+    Result,
+};
+use log::info;
+use sn_data_types::BlobAddress;
+use sn_messaging::{MessageId, SrcLocation};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+use xor_name::{Prefix, XorName};
+
+/// The stores of the data a node holds as an adult, which change hands
+/// as the node is promoted to elder and demoted back.
+pub(crate) struct NodeStores {
+    node_name: XorName,
+    root_dir: PathBuf,
+    pub(super) config: StoreConfig,
+    pub(super) used_space: UsedSpace,
+    // immutable chunks
+    pub(super) chunks: Option<Chunks>,
+    // immutable chunks held before promotion, until copied to other adults
+    pub(super) chunk_handoff: Option<ChunkHandoff>,
+    // replicas of Map and Sequence chunks, held as an adult
+    pub(super) replicas: Option<MutableReplicas>,
+    // the metadata we held as an elder before demotion, until the remaining elders confirm
+    // they took it over
+    pub(super) metadata_handover: Option<Metadata>,
+}
+
+impl NodeStores {
+    /// The stores of an adult.
+    pub(crate) async fn new(
+        node_name: XorName,
+        root_dir: &Path,
+        used_space: UsedSpace,
+        config: StoreConfig,
+    ) -> Result<Self> {
+        let chunks = Chunks::new(node_name, root_dir, used_space.clone(), &config).await?;
+        let replicas = MutableReplicas::new(root_dir, used_space.clone(), &config).await?;
+        Ok(Self {
+            node_name,
+            root_dir: root_dir.to_path_buf(),
+            config,
+            used_space,
+            chunks: Some(chunks),
+            chunk_handoff: None,
+            replicas: Some(replicas),
+            metadata_handover: None,
+        })
+    }
+
+    /// Stops holding data as an adult on promotion, and opens the metadata we handle as an elder.
+    /// The chunks we hold are kept until copied to other adults, and their addresses returned for
+    /// announcing. The other stores are closed, those of the replicas then holding the Maps and
+    /// Sequences we handle as an elder. If we were still handing our metadata over after an
+    /// earlier demotion, we take it back instead.
+    pub(crate) async fn promote(
+        &mut self,
+        reader: AdultReader,
+        copy_count: usize,
+        map_history_depth: u64,
+    ) -> Result<(BTreeSet<BlobAddress>, Metadata)> {
+        let addresses = match self.chunks.take() {
+            Some(chunks) => {
+                let addresses = chunks.addresses().await;
+                if addresses.is_empty() {
+                    chunks.close().await?;
+                } else {
+                    self.chunk_handoff = Some(ChunkHandoff::new(chunks, addresses.clone()));
+                }
+                addresses
+            }
+            None => BTreeSet::new(),
+        };
+        if let Some(replicas) = self.replicas.take() {
+            replicas.close().await?;
+        }
+        let meta_data = match self.metadata_handover.take() {
+            Some(mut meta_data) => {
+                info!("Taking back the metadata we were handing over");
+                meta_data.resume().await;
+                meta_data
+            }
+            None => {
+                Metadata::new(
+                    &self.root_dir,
+                    &self.used_space,
+                    &self.config,
+                    ChunkHolderDbs::new(&self.root_dir)?,
+                    reader,
+                    copy_count,
+                    map_history_depth,
+                )
+                .await?
+            }
+        };
+        Ok((addresses, meta_data))
+    }
+
+    /// Resumes holding data as an adult on demotion, along with the chunks
+    /// we were still handing off, if any. Our metadata is handed over to
+    /// the remaining elders of `our_prefix`, and kept until they confirm it,
+    /// after which its Maps and Sequences stores are opened as replicas.
+    pub(crate) async fn demote(
+        &mut self,
+        mut meta_data: Metadata,
+        our_prefix: Prefix,
+    ) -> Result<NodeDuties> {
+        let ops = meta_data.hand_over_all(our_prefix).await?;
+        self.chunks = Some(match self.chunk_handoff.take() {
+            Some(handoff) => handoff.into_chunks(),
+            None => {
+                Chunks::new(
+                    self.node_name,
+                    &self.root_dir,
+                    self.used_space.clone(),
+                    &self.config,
+                )
+                .await?
+            }
+        });
+        if meta_data.has_pending_handovers() {
+            self.metadata_handover = Some(meta_data);
+        } else {
+            self.hold_replicas(meta_data).await?;
+        }
+        Ok(ops)
+    }
+
+    /// Called when the remaining elders confirm a handover of our metadata after demotion.
+    pub(crate) async fn complete_metadata_handover(
+        &mut self,
+        correlation_id: MessageId,
+        sender: SrcLocation,
+    ) -> Result<()> {
+        let done = match &mut self.metadata_handover {
+            Some(meta_data) => {
+                meta_data.complete_handover(correlation_id, sender).await?;
+                !meta_data.has_pending_handovers()
+            }
+            None => return Ok(()),
+        };
+        if done {
+            if let Some(meta_data) = self.metadata_handover.take() {
+                info!("All metadata handed over");
+                self.hold_replicas(meta_data).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the handovers of our metadata which the remaining elders didn't confirm in time
+    /// again.
+    pub(crate) async fn retry_metadata_handover(&mut self) -> Result<NodeDuties> {
+        match &mut self.metadata_handover {
+            Some(meta_data) => meta_data.retry_handovers().await,
+            None => Ok(vec![]),
+        }
+    }
+
+    // Closes the metadata, along with the chunk holder dbs so that they can be opened on the
+    // next promotion, and opens its Maps and Sequences stores as replicas.
+    async fn hold_replicas(&mut self, meta_data: Metadata) -> Result<()> {
+        meta_data.close().await?;
+        self.replicas = Some(
+            MutableReplicas::new(&self.root_dir, self.used_space.clone(), &self.config).await?,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        node_ops::{NodeDuty, OutgoingMsg},
+        Error,
+    };
+    use sn_data_types::{Blob, Keypair, Map, PublicBlob, UnseqMap};
+    use sn_messaging::client::{Message, NodeCmd, NodeSystemCmd};
+    use tempdir::TempDir;
+
+    const COPY_COUNT: usize = 4;
+    const MAP_HISTORY_DEPTH: u64 = 2;
+
+    async fn adult_stores(root: &TempDir) -> Result<NodeStores> {
+        NodeStores::new(
+            XorName::random(),
+            root.path(),
+            UsedSpace::new(u64::MAX),
+            StoreConfig::default(),
+        )
+        .await
+    }
+
+    fn temp_dir() -> Result<TempDir> {
+        TempDir::new("node_stores").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
+    }
+
+    async fn promote(stores: &mut NodeStores) -> Result<(BTreeSet<BlobAddress>, Metadata)> {
+        let reader = AdultReader::fixed(stores.node_name, BTreeSet::new(), vec![]);
+        stores.promote(reader, COPY_COUNT, MAP_HISTORY_DEPTH).await
+    }
+
+    // Gives the adult a replica of a Map, for its metadata to hold once promoted.
+    async fn receive_replica(stores: &mut NodeStores) -> Result<()> {
+        let owner = Keypair::new_ed25519(&mut rand::thread_rng()).public_key();
+        let map = Map::from(UnseqMap::new(XorName::random(), 10, owner));
+        match &mut stores.replicas {
+            Some(replicas) => {
+                let _ = replicas.receive_map(map).await?;
+                Ok(())
+            }
+            None => Err(Error::Logic("No replicas".to_string())),
+        }
+    }
+
+    fn handover_ids(ops: &[NodeDuty]) -> Vec<MessageId> {
+        ops.iter()
+            .filter_map(|op| match op {
+                NodeDuty::Send(OutgoingMsg {
+                    msg:
+                        Message::NodeCmd {
+                            cmd: NodeCmd::System(NodeSystemCmd::ReceiveDataHandover { .. }),
+                            id,
+                            ..
+                        },
+                    ..
+                }) => Some(*id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn promotion_hands_off_chunks_and_demotion_takes_them_back() -> Result<()> {
+        let root = temp_dir()?;
+        let mut stores = adult_stores(&root).await?;
+        let blob = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        if let Some(chunks) = &mut stores.chunks {
+            let _ = chunks.store_replicated_chunk(blob.clone()).await?;
+        }
+        let used = stores.used_space.total().await;
+        assert!(used > 0);
+
+        let (addresses, meta_data) = promote(&mut stores).await?;
+        assert_eq!(addresses, vec![*blob.address()].into_iter().collect());
+        assert!(stores.chunks.is_none());
+        assert!(stores.chunk_handoff.is_some());
+        assert!(stores.replicas.is_none());
+        // the chunks handed off still count
+        assert_eq!(stores.used_space.total().await, used);
+
+        // demoted before the handoff completed, we keep the chunks and the space they use
+        let ops = stores.demote(meta_data, Prefix::default()).await?;
+        assert!(ops.is_empty());
+        assert!(stores.chunk_handoff.is_none());
+        assert!(stores.replicas.is_some());
+        let chunks = match &mut stores.chunks {
+            Some(chunks) => chunks,
+            None => return Err(Error::Logic("No chunks after demotion".to_string())),
+        };
+        assert!(chunks.addresses().await.contains(blob.address()));
+        let _ = chunks
+            .store_replicated_chunk(Blob::Public(PublicBlob::new(vec![4, 5, 6])))
+            .await?;
+        assert!(stores.used_space.total().await > used);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn demotion_without_handoff_starts_afresh() -> Result<()> {
+        let root = temp_dir()?;
+        let mut stores = adult_stores(&root).await?;
+
+        let (addresses, meta_data) = promote(&mut stores).await?;
+        assert!(addresses.is_empty());
+        assert!(stores.chunks.is_none());
+        assert!(stores.chunk_handoff.is_none());
+        assert!(stores.replicas.is_none());
+
+        let _ = stores.demote(meta_data, Prefix::default()).await?;
+        assert!(stores.replicas.is_some());
+        let chunks = match &mut stores.chunks {
+            Some(chunks) => chunks,
+            None => return Err(Error::Logic("No chunks after demotion".to_string())),
+        };
+        let _ = chunks
+            .store_replicated_chunk(Blob::Public(PublicBlob::new(vec![1, 2, 3])))
+            .await?;
+        assert!(stores.used_space.total().await > 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replicas_count_once_across_promotion_and_demotion() -> Result<()> {
+        let root = temp_dir()?;
+        let mut stores = adult_stores(&root).await?;
+        // a chunk to hand off, for the used space to be kept on promotion
+        if let Some(chunks) = &mut stores.chunks {
+            let _ = chunks
+                .store_replicated_chunk(Blob::Public(PublicBlob::new(vec![1, 2, 3])))
+                .await?;
+        }
+        let chunks_used = stores.used_space.total().await;
+        receive_replica(&mut stores).await?;
+        let used = stores.used_space.total().await;
+        assert!(used > chunks_used);
+
+        // the elder stores open those of the replicas
+        let (_, meta_data) = promote(&mut stores).await?;
+        assert_eq!(stores.used_space.total().await, used);
+
+        // and are kept until the Map is handed over, after which it no longer counts
+        let ops = stores.demote(meta_data, Prefix::default()).await?;
+        assert_eq!(stores.used_space.total().await, used);
+        for msg_id in handover_ids(&ops) {
+            stores
+                .complete_metadata_handover(msg_id, SrcLocation::Section(XorName::random()))
+                .await?;
+        }
+        assert!(stores.replicas.is_some());
+        assert_eq!(stores.used_space.total().await, chunks_used);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn demoted_elders_keep_their_metadata_until_the_section_confirms_it() -> Result<()> {
+        let root = temp_dir()?;
+        let mut stores = adult_stores(&root).await?;
+        receive_replica(&mut stores).await?;
+        let (_, meta_data) = promote(&mut stores).await?;
+
+        let ops = stores.demote(meta_data, Prefix::default()).await?;
+        let ids = handover_ids(&ops);
+        assert_eq!(ids.len(), 1);
+        assert!(stores.chunks.is_some());
+        assert!(stores.metadata_handover.is_some());
+        // the replicas are opened on the same stores once the metadata is closed
+        assert!(stores.replicas.is_none());
+        // nor is the handover sent again before it times out
+        assert!(stores.retry_metadata_handover().await?.is_empty());
+
+        // a single node can't confirm it
+        stores
+            .complete_metadata_handover(ids[0], SrcLocation::Node(XorName::random()))
+            .await?;
+        assert!(stores.metadata_handover.is_some());
+
+        stores
+            .complete_metadata_handover(ids[0], SrcLocation::Section(XorName::random()))
+            .await?;
+        assert!(stores.metadata_handover.is_none());
+        assert!(stores.replicas.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn promote_demote_promote() -> Result<()> {
+        let root = temp_dir()?;
+        let mut stores = adult_stores(&root).await?;
+
+        // promote: the chunk holder dbs are opened along with the metadata
+        let (_, meta_data) = promote(&mut stores).await?;
+        let full_adult = XorName::random();
+        meta_data
+            .chunk_holder_dbs()
+            .add_full_adult(&full_adult)
+            .await?;
+
+        // demote: having nothing to hand over, the metadata is closed at once
+        let ops = stores.demote(meta_data, Prefix::default()).await?;
+        assert!(ops.is_empty());
+        assert!(stores.metadata_handover.is_none());
+        receive_replica(&mut stores).await?;
+
+        // promote again: the dbs can be opened again, and kept their state
+        let (_, meta_data) = promote(&mut stores).await?;
+        assert!(meta_data.chunk_holder_dbs().is_full_adult(&full_adult)?);
+
+        // demote with a Map to hand over, and promote again before it is confirmed: the
+        // metadata is taken back, and no longer handed over
+        let ops = stores.demote(meta_data, Prefix::default()).await?;
+        assert_eq!(handover_ids(&ops).len(), 1);
+        let (_, meta_data) = promote(&mut stores).await?;
+        assert!(stores.metadata_handover.is_none());
+        assert!(!meta_data.has_pending_handovers());
+        assert!(meta_data.chunk_holder_dbs().is_full_adult(&full_adult)?);
+        Ok(())
+    }
+}
@@ -10,13 +10,13 @@ use bls::PublicKeySet;
 #[cfg(feature = "simulated-payouts")]
 use sn_data_types::Transfer;
 use sn_data_types::{
-    ActorHistory, Blob, BlobAddress, Credit, CreditAgreementProof, NodeAge, PublicKey,
-    ReplicaEvent, RewardAccumulation, RewardProposal, SectionElders, SignatureShare, SignedCredit,
-    SignedTransfer, SignedTransferShare, Token, TransferAgreementProof, TransferValidated,
-    WalletHistory,
+    ActorHistory, Blob, BlobAddress, Credit, CreditAgreementProof, Map, MapAddress, NodeAge,
+    PublicKey, ReplicaEvent, RewardAccumulation, RewardProposal, SectionElders, Sequence,
+    SequenceAddress, SignatureShare, SignedCredit, SignedTransfer, SignedTransferShare, Token,
+    TransferAgreementProof, TransferValidated, WalletHistory,
 };
 use sn_messaging::{
-    client::{BlobRead, BlobWrite, Message, NodeSystemCmd},
+    client::{BlobRead, BlobWrite, Error as ErrorMessage, Message, NodeSystemCmd},
     Aggregation, DstLocation, EndUser, MessageId, SrcLocation,
 };
 use sn_routing::{NodeElderChange, Prefix};
@@ -87,6 +87,9 @@ pub enum NodeDuty {
         read: BlobRead,
         msg_id: MessageId,
         origin: EndUser,
+        /// The elder which asked for the chunk on behalf of the client,
+        /// and which is told whether it was sent.
+        elder: Option<XorName>,
     },
     WriteChunk {
         write: BlobWrite,
@@ -152,6 +155,25 @@ pub enum NodeDuty {
         /// The wallets of users on the network.
         user_wallets: BTreeMap<PublicKey, ActorHistory>,
     },
+    /// Take over the data handed over
+    /// by our sibling section after a split.
+    ReceiveDataHandover {
+        /// The holders and owner of each Blob chunk.
+        blob_records: BTreeMap<BlobAddress, (BTreeSet<XorName>, Option<PublicKey>)>,
+        maps: Vec<Map>,
+        sequences: Vec<Sequence>,
+        msg_id: MessageId,
+        sender: SrcLocation,
+    },
+    /// Drop the data handed over to another
+    /// section, as its elders confirmed it.
+    CompleteDataHandover {
+        correlation_id: MessageId,
+        sender: SrcLocation,
+    },
+    /// Hand over data again, which the receiving
+    /// section didn't confirm in time.
+    RetryDataHandovers,
     /// As members are lost for various reasons
     /// there are certain things nodes need
     /// to do, to update for that.
@@ -213,6 +235,100 @@ pub enum NodeDuty {
         data: Blob,
         correlation_id: MessageId,
     },
+    /// A holder sent a client the chunk we asked it to.
+    ChunkServed {
+        address: BlobAddress,
+        holder: XorName,
+        correlation_id: MessageId,
+    },
+    /// A holder couldn't read the chunk we asked it
+    /// to send a client.
+    ChunkReadFailed {
+        error: ErrorMessage,
+        holder: XorName,
+        correlation_id: MessageId,
+    },
+    /// Verify the integrity of the chunks
+    /// stored at this node.
+    ScrubChunks,
+    /// Restore the copy of a chunk that
+    /// a holder found to be corrupt.
+    RepairChunk {
+        address: BlobAddress,
+        holder: XorName,
+    },
+    /// Check that all chunks have enough
+    /// holders, and replicate those which don't.
+    AuditReplication,
+    /// Ask the next holders for chunks
+    /// which weren't read in time.
+    RetryReads,
+    /// Challenge the holders of a random
+    /// chunk to prove that they store it.
+    ChallengeStorage,
+    /// Prove to the challenging elder that we
+    /// store the chunk, by hashing it with the nonce.
+    ProveStorage {
+        address: BlobAddress,
+        nonce: [u8; 32],
+        msg_id: MessageId,
+        origin: SrcLocation,
+    },
+    /// Check a holder's answer to a storage challenge.
+    ReceiveStorageProof {
+        address: BlobAddress,
+        nonce: [u8; 32],
+        proof: Option<XorName>,
+        holder: XorName,
+        correlation_id: MessageId,
+    },
+    /// Record a new holder which stored
+    /// its copy of a replicated chunk.
+    ReplicationCompleted {
+        address: BlobAddress,
+        holder: XorName,
+        msg_id: MessageId,
+    },
+    /// Have the chunks of a holder which is no
+    /// longer an adult copied to other adults.
+    HandOffChunks {
+        holder: XorName,
+        addresses: BTreeSet<BlobAddress>,
+    },
+    /// Store a chunk at another adult, as the
+    /// holder it was sent to had no space for it.
+    RetryChunkStore {
+        data: Blob,
+        holder: XorName,
+        msg_id: MessageId,
+        origin: EndUser,
+    },
+    /// Merge the copy of a Map sent by another node:
+    /// a replica from one of our elders if we are an adult,
+    /// or a replica which is ahead if we are an elder.
+    ReceiveMapReplica {
+        map: Map,
+        msg_id: MessageId,
+        sender: XorName,
+    },
+    /// Merge the copy of a Sequence sent by another node:
+    /// a replica from one of our elders if we are an adult,
+    /// or a replica which is ahead if we are an elder.
+    ReceiveSequenceReplica {
+        sequence: Sequence,
+        msg_id: MessageId,
+        sender: XorName,
+    },
+    /// Delete our replica of a Map which one of our elders deleted.
+    DeleteMapReplica {
+        address: MapAddress,
+        sender: XorName,
+    },
+    /// Delete our replica of a Sequence which one of our elders deleted.
+    DeleteSequenceReplica {
+        address: SequenceAddress,
+        sender: XorName,
+    },
     NoOp,
 }
 
@@ -249,6 +365,9 @@ impl Debug for NodeDuty {
             Self::SynchState { .. } => write!(f, "SynchState"),
             Self::ChurnMembers { .. } => write!(f, "ChurnMembers"),
             Self::SplitSection { .. } => write!(f, "SplitSection"),
+            Self::ReceiveDataHandover { .. } => write!(f, "ReceiveDataHandover"),
+            Self::CompleteDataHandover { .. } => write!(f, "CompleteDataHandover"),
+            Self::RetryDataHandovers => write!(f, "RetryDataHandovers"),
             Self::GetSectionElders { .. } => write!(f, "GetSectionElders"),
 
             Self::NoOp => write!(f, "No op."),
@@ -267,6 +386,22 @@ impl Debug for NodeDuty {
             Self::ReplicateChunk { .. } => write!(f, "ReplicateChunk"),
             Self::GetChunkForReplication { .. } => write!(f, "GetChunkForReplication"),
             Self::StoreChunkForReplication { .. } => write!(f, "StoreChunkForReplication"),
+            Self::ChunkServed { .. } => write!(f, "ChunkServed"),
+            Self::ChunkReadFailed { .. } => write!(f, "ChunkReadFailed"),
+            Self::ScrubChunks => write!(f, "ScrubChunks"),
+            Self::RepairChunk { .. } => write!(f, "RepairChunk"),
+            Self::RetryChunkStore { .. } => write!(f, "RetryChunkStore"),
+            Self::AuditReplication => write!(f, "AuditReplication"),
+            Self::RetryReads => write!(f, "RetryReads"),
+            Self::ChallengeStorage => write!(f, "ChallengeStorage"),
+            Self::ProveStorage { .. } => write!(f, "ProveStorage"),
+            Self::ReceiveStorageProof { .. } => write!(f, "ReceiveStorageProof"),
+            Self::ReplicationCompleted { .. } => write!(f, "ReplicationCompleted"),
+            Self::HandOffChunks { .. } => write!(f, "HandOffChunks"),
+            Self::ReceiveMapReplica { .. } => write!(f, "ReceiveMapReplica"),
+            Self::ReceiveSequenceReplica { .. } => write!(f, "ReceiveSequenceReplica"),
+            Self::DeleteMapReplica { .. } => write!(f, "DeleteMapReplica"),
+            Self::DeleteSequenceReplica { .. } => write!(f, "DeleteSequenceReplica"),
         }
     }
 }
@@ -16,19 +16,25 @@ const MIN_REWARD_AGE: u8 = 5;
 /// Calculates reward for each public key
 /// proportional to the age of its node,
 /// out of the total amount supplied.
+/// Each storage challenge a node failed
+/// counts as one age less.
 pub fn distribute_rewards(
     amount: Token,
     nodes: BTreeMap<XorName, (NodeAge, PublicKey)>,
+    storage_failures: &BTreeMap<XorName, u64>,
 ) -> BTreeMap<XorName, (NodeAge, PublicKey, Token)> {
-    let reward_buckets = get_buckets(nodes);
+    let reward_buckets = get_buckets(nodes, storage_failures);
     distribute(amount, reward_buckets)
 }
 
 fn get_buckets(
     nodes: BTreeMap<XorName, (NodeAge, PublicKey)>,
+    storage_failures: &BTreeMap<XorName, u64>,
 ) -> BTreeMap<NodeAge, BTreeMap<XorName, PublicKey>> {
     let mut reward_buckets = BTreeMap::new();
     for (node_name, (age, wallet)) in nodes {
+        let failures = storage_failures.get(&node_name).copied().unwrap_or(0);
+        let age = age.saturating_sub(u64::min(failures, NodeAge::MAX as u64) as NodeAge);
         if age >= MIN_REWARD_AGE {
             let _ = reward_buckets
                 .entry(age)
@@ -133,7 +139,7 @@ mod test {
         let now = std::time::Instant::now();
 
         // calc
-        let rewards = distribute_rewards(amount, nodes);
+        let rewards = distribute_rewards(amount, nodes, &BTreeMap::new());
 
         // stop timer
         let duration = now.elapsed();
@@ -154,6 +160,28 @@ mod test {
         println!("Total rewards: {:?}", total);
     }
 
+    #[test]
+    fn storage_failures_lower_rewards() {
+        let amount = Token::from_nano(1_000_000_000);
+        let (honest, failing, excluded) = (XorName::random(), XorName::random(), XorName::random());
+        let mut nodes = BTreeMap::<XorName, (NodeAge, PublicKey)>::new();
+        for node in &[honest, failing, excluded] {
+            let _ = nodes.insert(*node, (MIN_REWARD_AGE + 2, get_random_pk()));
+        }
+        let mut failures = BTreeMap::new();
+        let _ = failures.insert(failing, 1);
+        let _ = failures.insert(excluded, 3);
+
+        let rewards = distribute_rewards(amount, nodes, &failures);
+
+        assert!(!rewards.contains_key(&excluded));
+        let reward = |node| rewards.get(&node).map(|(_, _, amount)| amount.as_nano());
+        assert!(matches!(
+            (reward(honest), reward(failing)),
+            (Some(honest), Some(failing)) if honest > failing
+        ));
+    }
+
     fn get_random_pk() -> PublicKey {
         PublicKey::from(bls::SecretKey::random().public_key())
     }
@@ -76,11 +76,13 @@ impl RewardProcess {
 
     /// Calculates reward for each node
     /// proportional to the age of it,
-    /// out of the total payments received.
+    /// out of the total payments received,
+    /// less the storage challenges it failed.
     /// Additionally adds minted tokens.
     pub async fn reward_and_mint(
         &mut self,
         our_nodes: BTreeMap<XorName, (NodeAge, PublicKey)>,
+        storage_failures: BTreeMap<XorName, u64>,
     ) -> Result<NodeDuty> {
         //  -----  MINTING  -----
         // This is the minting of new coins happening;
@@ -89,7 +91,8 @@ impl RewardProcess {
 
         // Calculate our nodes' rewards;
         // the size being the sum of payments to parent section.
-        let reward_credits = self.get_reward_proposals(minting, self.section.our_key, our_nodes);
+        let reward_credits =
+            self.get_reward_proposals(minting, self.section.our_key, our_nodes, &storage_failures);
         let reward_sum: u64 = reward_credits.iter().map(|c| c.amount().as_nano()).sum();
 
         let proposal = self.sign_proposed_rewards(reward_credits).await?;
@@ -148,11 +151,12 @@ impl RewardProcess {
         minting: u8,
         section_key: PublicKey,
         nodes: BTreeMap<XorName, (NodeAge, PublicKey)>,
+        storage_failures: &BTreeMap<XorName, u64>,
     ) -> Vec<CreditProposal> {
         // multiply by minting rate as to add the tokens to be minted
         let rewards = Token::from_nano(minting as u64 * self.balance.as_nano());
         // create reward distribution
-        distribute_rewards(rewards, nodes)
+        distribute_rewards(rewards, nodes, storage_failures)
             .into_iter()
             .map(|(node, (age, wallet, amount))| {
                 let id = MessageId::combine(vec![node, XorName::from(section_key)])
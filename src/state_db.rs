@@ -8,13 +8,16 @@
 
 use crate::{utils, Error, Result};
 use bls::{self, serde_impl::SerdeSecret, PublicKey, SecretKey, PK_SIZE};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::{io::ErrorKind, path::Path};
 use tokio::fs;
 
 const AGE_GROUP_FILENAME: &str = "age_group";
 const REWARD_PUBLIC_KEY_FILENAME: &str = "reward_public_key";
 const REWARD_SECRET_KEY_FILENAME: &str = "reward_secret_key";
+const CHUNK_KEY_FILENAME: &str = "chunk_key";
+const CHUNK_KEY_SIZE: usize = 32;
 
 /// Writes the public and secret key to different locations at disk.
 pub async fn store_new_reward_keypair(
@@ -29,6 +32,29 @@ pub async fn store_new_reward_keypair(
     Ok(())
 }
 
+/// Writes a new random key for encrypting chunks at rest to disk, and returns it.
+pub async fn store_new_chunk_key(root_dir: &Path) -> Result<[u8; CHUNK_KEY_SIZE]> {
+    let key: [u8; CHUNK_KEY_SIZE] = rand::thread_rng().gen();
+    fs::write(root_dir.join(CHUNK_KEY_FILENAME), vec_to_hex(key.to_vec())).await?;
+    Ok(key)
+}
+
+/// Returns the key for encrypting chunks at rest, or None if there is none on disk.
+pub async fn get_chunk_key(root_dir: &Path) -> Result<Option<[u8; CHUNK_KEY_SIZE]>> {
+    let hex_str = match fs::read_to_string(root_dir.join(CHUNK_KEY_FILENAME)).await {
+        Ok(hex_str) => hex_str,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    let key_bytes = parse_hex(&hex_str);
+    if key_bytes.len() != CHUNK_KEY_SIZE {
+        return Err(Error::Logic("Invalid chunk key bytes".to_string()));
+    }
+    let mut key = [0; CHUNK_KEY_SIZE];
+    key.copy_from_slice(&key_bytes);
+    Ok(Some(key))
+}
+
 // /// Writes the info to disk.
 // pub async fn store_age_group(root_dir: &Path, age_group: &AgeGroup) -> Result<()> {
 //     let path = root_dir.join(AGE_GROUP_FILENAME);
@@ -105,6 +131,17 @@ mod test {
         Ok(())
     }
 
+    /// Storing and reading back the chunk key.
+    #[tokio::test]
+    async fn chunk_key_roundtrip() -> Result<()> {
+        let root_dir = tempdir::TempDir::new("chunk_key")
+            .map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        assert_eq!(get_chunk_key(root_dir.path()).await?, None);
+        let key = store_new_chunk_key(root_dir.path()).await?;
+        assert_eq!(get_chunk_key(root_dir.path()).await?, Some(key));
+        Ok(())
+    }
+
     fn gen_key() -> PublicKey {
         SecretKey::random().public_key()
     }
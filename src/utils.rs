@@ -16,6 +16,8 @@ use log::{Log, Metadata, Record};
 use pickledb::{PickleDb, PickleDbDumpPolicy};
 use rand::{distributions::Standard, CryptoRng, Rng};
 use serde::{de::DeserializeOwned, Serialize};
+use sn_data_types::{BlobAddress, PublicKey, Signature};
+use sn_messaging::{client::BlobWrite, MessageId};
 use std::io::Write;
 use std::{fs, path::Path};
 
@@ -56,6 +58,18 @@ pub(crate) fn deserialise<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
     bincode::deserialize(bytes).map_err(Error::Bincode)
 }
 
+/// Verifies the signature which authorises `BlobWrite::DeletePrivate`: the owner's signature
+/// over the delete of the blob at `address`, in the message with id `msg_id`.
+pub(crate) fn verify_blob_deletion(
+    owner: &PublicKey,
+    address: &BlobAddress,
+    msg_id: &MessageId,
+    signature: &Signature,
+) -> Result<()> {
+    let payload = BlobWrite::delete_private_payload(address, msg_id)?;
+    owner.verify(signature, payload).map_err(Error::NetworkData)
+}
+
 /// Initialize logging
 pub fn init_logging(config: &Config) {
     // Custom formatter for logs